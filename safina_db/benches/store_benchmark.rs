@@ -1,26 +1,26 @@
-use criterion::{criterion_group, criterion_main, Criterion};
-use safina_db::{Store, STORAGE_MUTEX};
-use std::time::{SystemTime, UNIX_EPOCH};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use safina_db::storage::Durability;
+use safina_db::{Backend, Database, Options, Storage, Store, WriteBatch};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of keys the concurrent read benchmark looks up in every thread.
+const READS_PER_THREAD: usize = 10_000;
+
+/// Number of keys the sharded write benchmark updates in every thread.
+const WRITES_PER_THREAD: usize = 1_000;
+
+/// Number of keys the durability benchmark updates in every thread, each write hitting disk.
+const DURABLE_WRITES_PER_THREAD: usize = 50;
 
 pub fn setup_test_store(db_name: &str) -> Store {
-    let mut store = Store::new();
     let start = SystemTime::now();
     let since_the_epoch = start
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards");
     let timestamp = since_the_epoch.as_secs();
     let db_name = format!("db-test-bench-{}-{}", db_name, timestamp);
-    match STORAGE_MUTEX
-        .lock()
-        .unwrap()
-        .load_file(Some(db_name.as_str()))
-    {
-        Ok(data) => store.data = data,
-        Err(err) => {
-            println!("Invalid: {}", err);
-        }
-    }
-    store
+    Store::open(Box::new(Storage::new(Some(db_name.as_str())))).unwrap()
 }
 
 pub fn bench_store_crud(c: &mut Criterion) {
@@ -36,7 +36,7 @@ pub fn bench_store_crud(c: &mut Criterion) {
                 store
                     .insert(
                         &fresh_key,
-                        &format!("value-{}", i),
+                        format!("value-{}", i),
                     )
                     .unwrap();
             }
@@ -68,12 +68,153 @@ pub fn bench_store_crud(c: &mut Criterion) {
         b.iter(|| {
             for i in 0..1000 {
                 let fresh_key = format!("b-{}-key-{}", counter, i);
-                store.delete(&fresh_key);
+                store.delete(&fresh_key).unwrap();
             }
             counter += 1;
         })
     });
+    counter = 0;
+    group.bench_function("write_batch 1000 keys", |b| {
+        b.iter(|| {
+            let mut batch = WriteBatch::new();
+            for i in 0..1000 {
+                batch.put(format!("batch-{}-key-{}", counter, i), format!("value-{}", i));
+            }
+            store.write_batch(batch).unwrap();
+            counter += 1;
+        })
+    });
+    group.bench_function("incr", |b| {
+        b.iter(|| {
+            store.incr("counter").unwrap();
+        })
+    });
+}
+
+/// Measures read throughput as the number of threads reading the same database grows.
+pub fn bench_concurrent_reads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("[Database concurrent reads");
+    group.sample_size(10);
+    let options = Options {
+        backend: Backend::Memory,
+        sweep_interval: None,
+        ..Options::default()
+    };
+    let db = Database::open("bench-reads", options).unwrap();
+    for i in 0..READS_PER_THREAD {
+        db.insert(format!("key-{}", i), format!("value-{}", i))
+            .unwrap();
+    }
+
+    for threads in [1, 2, 4, 8] {
+        group.throughput(Throughput::Elements((threads * READS_PER_THREAD) as u64));
+        group.bench_with_input(BenchmarkId::new("get", threads), &threads, |b, &threads| {
+            b.iter(|| {
+                thread::scope(|scope| {
+                    for t in 0..threads {
+                        let db = &db;
+                        scope.spawn(move || {
+                            for i in 0..READS_PER_THREAD {
+                                let key = format!("key-{}", (i + t) % READS_PER_THREAD);
+                                assert!(db.get(&key).unwrap().is_some());
+                            }
+                        });
+                    }
+                });
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Measures write throughput of 8 threads updating their own keys, as the number of shards
+/// the keys are split into grows.
+pub fn bench_sharded_writes(c: &mut Criterion) {
+    const THREADS: usize = 8;
+    let mut group = c.benchmark_group("[Database sharded writes");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((THREADS * WRITES_PER_THREAD) as u64));
+
+    for shard_count in [1, 4, 16] {
+        let options = Options {
+            backend: Backend::Memory,
+            sweep_interval: None,
+            shard_count,
+            ..Options::default()
+        };
+        let db = Database::open("bench-writes", options).unwrap();
+        for i in 0..THREADS * WRITES_PER_THREAD {
+            db.insert(format!("key-{}", i), "value").unwrap();
+        }
+        group.bench_with_input(BenchmarkId::new("update", shard_count), &db, |b, db| {
+            b.iter(|| {
+                thread::scope(|scope| {
+                    for t in 0..THREADS {
+                        scope.spawn(move || {
+                            for i in 0..WRITES_PER_THREAD {
+                                let key = format!("key-{}", t * WRITES_PER_THREAD + i);
+                                db.update(&key, "new_value").unwrap();
+                            }
+                        });
+                    }
+                });
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Measures write throughput of 8 threads updating their own keys in a persistent database,
+/// for each durability mode. The modes syncing less often lose more writes in a crash.
+pub fn bench_durability(c: &mut Criterion) {
+    const THREADS: usize = 8;
+    let mut group = c.benchmark_group("[Database durability modes");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((THREADS * DURABLE_WRITES_PER_THREAD) as u64));
+
+    let modes = [
+        ("always", Durability::Always),
+        ("group-commit", Durability::GroupCommit(Duration::from_micros(200))),
+        ("periodic", Durability::Periodic(Duration::from_millis(100))),
+        ("none", Durability::None),
+    ];
+    for (name, durability) in modes {
+        let since_the_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards");
+        let db_name = format!("db-test-bench-durability-{}-{}", name, since_the_epoch.as_nanos());
+        let options = Options {
+            sweep_interval: None,
+            durability,
+            ..Options::default()
+        };
+        let db = Database::open(&db_name, options).unwrap();
+        for i in 0..THREADS * DURABLE_WRITES_PER_THREAD {
+            db.insert(format!("key-{}", i), "value").unwrap();
+        }
+        group.bench_with_input(BenchmarkId::new("update", name), &db, |b, db| {
+            b.iter(|| {
+                thread::scope(|scope| {
+                    for t in 0..THREADS {
+                        scope.spawn(move || {
+                            for i in 0..DURABLE_WRITES_PER_THREAD {
+                                let key = format!("key-{}", t * DURABLE_WRITES_PER_THREAD + i);
+                                db.update(&key, "new_value").unwrap();
+                            }
+                        });
+                    }
+                });
+            })
+        });
+    }
+    group.finish();
 }
 
-criterion_group!(benches, bench_store_crud);
+criterion_group!(
+    benches,
+    bench_store_crud,
+    bench_concurrent_reads,
+    bench_sharded_writes,
+    bench_durability
+);
 criterion_main!(benches);
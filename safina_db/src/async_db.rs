@@ -0,0 +1,227 @@
+use std::ops::RangeBounds;
+use std::panic;
+use std::sync::Arc;
+
+use tokio::task;
+
+use crate::batch::WriteBatch;
+use crate::database::{Database, Options};
+use crate::error::{Error, Result};
+use crate::kv_store::{Value, KV};
+use crate::transaction::{IsolationLevel, Transaction};
+
+/// An async handle on a `Database`, for applications running on tokio.
+///
+/// Every operation runs on tokio's blocking thread pool, so waiting for a lock or for the
+/// backend to sync a write to disk never stalls the executor. The handle is cheap to clone:
+/// clones share the same database, which is closed once the last of them is dropped.
+/// Operations still waiting for a blocking thread when the runtime shuts down are cancelled,
+/// and fail with `Error::Closed`.
+///
+/// Available with the `async` feature.
+///
+/// # Example
+/// ```rust
+/// use safina_db::{AsyncDatabase, Backend, Options};
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let options = Options { backend: Backend::Memory, ..Options::default() };
+/// let db = AsyncDatabase::open("example", options).await.unwrap();
+/// db.insert("key", "value").await.unwrap();
+/// assert_eq!(db.get("key").await.unwrap(), Some(b"value".to_vec()));
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncDatabase {
+    db: Arc<Database>,
+}
+
+impl AsyncDatabase {
+    /// Opens (or creates) the database at `path`, see `Database::open`.
+    pub async fn open(path: &str, options: Options) -> Result<AsyncDatabase> {
+        let path = path.to_string();
+        let db = blocking(move || Database::open(&path, options)).await??;
+        Ok(AsyncDatabase::from(db))
+    }
+
+    /// Returns the database under the handle, for the operations that have no async flavour.
+    /// Calling them blocks the current thread.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Returns a copy of the value associated with `key`, if any.
+    pub async fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Value>> {
+        let key = key.as_ref().to_vec();
+        self.run(move |db| db.get(key)).await?
+    }
+
+    /// Inserts a new key-value pair, see `Store::insert`.
+    pub async fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<()> {
+        let (key, value) = (key.as_ref().to_vec(), value.as_ref().to_vec());
+        self.run(move |db| db.insert(key, value)).await?
+    }
+
+    /// Updates the value of an existing key, see `Store::update`.
+    pub async fn update<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<()> {
+        let (key, value) = (key.as_ref().to_vec(), value.as_ref().to_vec());
+        self.run(move |db| db.update(key, value)).await?
+    }
+
+    /// Returns a copy of the value associated with `key` along with its version, see
+    /// `Store::get_with_version`.
+    pub async fn get_with_version<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<(Value, u64)>> {
+        let key = key.as_ref().to_vec();
+        self.run(move |db| db.get_with_version(key)).await?
+    }
+
+    /// Updates the value of a key if its version is still `version`, see
+    /// `Store::update_if_version`.
+    pub async fn update_if_version<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        version: u64,
+        value: V,
+    ) -> Result<Option<u64>> {
+        let (key, value) = (key.as_ref().to_vec(), value.as_ref().to_vec());
+        self.run(move |db| db.update_if_version(key, version, value))
+            .await?
+    }
+
+    /// Replaces the value of a key if it is still `expected`, see `Store::compare_and_swap`.
+    pub async fn compare_and_swap<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        expected: Option<V>,
+        new: Option<V>,
+    ) -> Result<bool> {
+        let key = key.as_ref().to_vec();
+        let expected = expected.map(|value| value.as_ref().to_vec());
+        let new = new.map(|value| value.as_ref().to_vec());
+        self.run(move |db| db.compare_and_swap(key, expected, new))
+            .await?
+    }
+
+    /// Folds `operand` into the value of a key with one of the `Options::merge_operators`,
+    /// see `Store::merge`.
+    pub async fn merge<K: AsRef<[u8]>, O: AsRef<[u8]>>(
+        &self,
+        key: K,
+        operator: &str,
+        operand: O,
+    ) -> Result<()> {
+        let (key, operand) = (key.as_ref().to_vec(), operand.as_ref().to_vec());
+        let operator = operator.to_string();
+        self.run(move |db| db.merge(key, &operator, operand))
+            .await?
+    }
+
+    /// Adds `delta` to the counter stored at a key, see `Store::incr_by`.
+    pub async fn incr_by<K: AsRef<[u8]>>(&self, key: K, delta: i64) -> Result<i64> {
+        let key = key.as_ref().to_vec();
+        self.run(move |db| db.incr_by(key, delta)).await?
+    }
+
+    /// Deletes a key if it exists, see `Store::delete`.
+    pub async fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<()> {
+        let key = key.as_ref().to_vec();
+        self.run(move |db| db.delete(key)).await?
+    }
+
+    /// Applies the writes of `batch` with a single persistence step, see
+    /// `Database::write_batch`.
+    pub async fn write_batch(&self, batch: WriteBatch) -> Result<()> {
+        self.run(move |db| db.write_batch(batch)).await?
+    }
+
+    /// Returns a copy of the pairs within `range`, in key order, see `Store::scan`.
+    pub async fn scan<'k, R: RangeBounds<&'k str>>(&self, range: R) -> Result<Vec<KV>> {
+        let start = range.start_bound().map(|key| key.as_bytes().to_vec());
+        let end = range.end_bound().map(|key| key.as_bytes().to_vec());
+        self.run(move |db| {
+            db.scan_bytes((
+                start.as_ref().map(Vec::as_slice),
+                end.as_ref().map(Vec::as_slice),
+            ))
+        })
+        .await?
+    }
+
+    /// Returns a copy of the pairs whose key starts with `prefix`, in key order.
+    pub async fn scan_prefix<P: AsRef<[u8]>>(&self, prefix: P) -> Result<Vec<KV>> {
+        let prefix = prefix.as_ref().to_vec();
+        self.run(move |db| db.scan_prefix(prefix)).await?
+    }
+
+    /// Runs `f` in a serializable transaction, see `Database::transaction`.
+    ///
+    /// `f` runs on the blocking thread pool as a whole, so it must not await anything.
+    pub async fn transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.transaction_with(IsolationLevel::default(), f).await
+    }
+
+    /// Runs `f` in a transaction at the given isolation level, see `Database::transaction`.
+    pub async fn transaction_with<T, F>(&self, isolation: IsolationLevel, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.run(move |db| db.transaction_with(isolation, f))
+            .await?
+    }
+
+    /// Asks the backends to compact what they persisted so far, see `Store::flush`.
+    pub async fn flush(&self) -> Result<()> {
+        self.run(|db| db.flush()).await?
+    }
+
+    /// Closes the database if this is the last handle on it, see `Database::close`.
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the database is closed, or other handles still use it.
+    /// * `Err(Error)` - An error the backends hit while flushing.
+    pub async fn close(self) -> Result<()> {
+        match Arc::try_unwrap(self.db) {
+            Ok(db) => blocking(move || db.close()).await?,
+            Err(_) => Ok(()), // Closed when the last clone is dropped
+        }
+    }
+
+    /// Runs `f` against the database on the blocking thread pool, see `blocking`.
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Database) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        blocking(move || f(&db)).await
+    }
+}
+
+impl From<Database> for AsyncDatabase {
+    fn from(db: Database) -> Self {
+        AsyncDatabase { db: Arc::new(db) }
+    }
+}
+
+/// Runs `f` on the blocking thread pool and waits for its result, resuming its panic if it
+/// panicked.
+///
+/// # Returns
+/// * `Ok(T)` - The result of `f`.
+/// * `Err(Error::Closed)` - If the task was cancelled before it ran, as the runtime shut down.
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(value) => Ok(value),
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(Error::Closed), // Cancelled by the runtime shutting down
+    }
+}
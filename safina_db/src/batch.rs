@@ -0,0 +1,90 @@
+use crate::keyspace::Keyspace;
+use crate::storage::Mutation;
+
+/// A set of puts and deletes applied together, with a single persistence step.
+///
+/// Writing keys one by one persists (and with `Durability::Always`, syncs) every write on
+/// its own. A batch hands all of them to the backend at once, as one atomic unit: after a
+/// crash either the whole batch is recovered, or none of it.
+///
+/// Unlike `insert` and `update`, the writes don't check whether their key exists: a put sets
+/// the key whether it exists or not, dropping its expiry time, and deleting a key that
+/// doesn't exist does nothing. The writes are applied in the order they were added, so the
+/// last write to a key wins.
+///
+/// A batch can write to several keyspaces with `put_in` and `delete_in`, still atomically.
+///
+/// # Example
+/// ```rust
+/// use safina_db::{Backend, Database, Options, WriteBatch};
+///
+/// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+/// db.insert("old", "value").unwrap();
+/// let mut batch = WriteBatch::new();
+/// batch.put("key1", "value1").put("key2", "value2").delete("old");
+/// db.write_batch(batch).unwrap();
+/// assert_eq!(db.get_string("key2").unwrap(), Some("value2".to_string()));
+/// assert_eq!(db.get("old").unwrap(), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteBatch {
+    pub(crate) mutations: Vec<Mutation>,
+}
+
+impl WriteBatch {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the write setting `key` to `value`, whether or not the key exists.
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> &mut Self {
+        self.mutations
+            .push(Mutation::put(key.as_ref(), value.as_ref(), None));
+        self
+    }
+
+    /// Adds the deletion of `key`.
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> &mut Self {
+        self.mutations.push(Mutation::Delete {
+            key: key.as_ref().to_vec(),
+        });
+        self
+    }
+
+    /// Adds the write setting `key` of `keyspace` to `value`, whether or not the key exists.
+    /// The pair expires after the default TTL of the keyspace, if it has one.
+    pub fn put_in<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        keyspace: &Keyspace,
+        key: K,
+        value: V,
+    ) -> &mut Self {
+        self.mutations.push(Mutation::put(
+            &keyspace.key(key),
+            &keyspace.encode_value(value),
+            keyspace.default_expiry(),
+        ));
+        self
+    }
+
+    /// Adds the deletion of `key` of `keyspace`.
+    pub fn delete_in<K: AsRef<[u8]>>(&mut self, keyspace: &Keyspace, key: K) -> &mut Self {
+        self.delete(keyspace.key(key))
+    }
+
+    /// Returns the number of writes in the batch.
+    pub fn len(&self) -> usize {
+        self.mutations.len()
+    }
+
+    /// Returns `true` if the batch holds no write.
+    pub fn is_empty(&self) -> bool {
+        self.mutations.is_empty()
+    }
+
+    /// Removes every write from the batch, so it can be reused.
+    pub fn clear(&mut self) {
+        self.mutations.clear();
+    }
+}
@@ -1,29 +1,64 @@
-use crate::STORE_MUTEX;
-use clap::{arg, Command};
+use crate::data_dir::{DataDir, DEFAULT_DATABASE};
+use crate::error::{Error, Result};
+use crate::index::Extractor;
+use crate::keyspace::{Compression, Keyspace, KeyspaceOptions, DEFAULT_KEYSPACE};
+use crate::kv_store::KV;
+use crate::merge::{self, MergeOperator};
+use crate::watch::{Event, EventKind, Watcher};
+use crate::{Database, IsolationLevel, Transaction, WriteBatch};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use clap::{arg, ArgMatches, Command};
 use std::io::Write;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// What a REPL session has selected, kept from one command to the next.
+struct Session {
+    /// Name of the database selected by `use database`.
+    database: String,
+    db: Arc<Database>,
+    /// Name of the keyspace selected by `use` or `keyspace use`.
+    keyspace: String,
+    /// The transaction opened by `begin`, if any.
+    tx: Option<Transaction>,
+}
 
 /// Runs the REPL loop, reading user input and responding accordingly.
 ///
+/// # Arguments
+/// * `dir` - The data directory holding the databases the commands are run against. The
+///   session starts in its default database.
+///
 /// # Returns
 /// * `Ok(())` if the REPL exits successfully.
-/// * `Err(String)` if an error occurs during execution.
-pub fn run() -> Result<(), String> {
+/// * `Err(Error)` if reading input or writing output fails.
+pub fn run(dir: &DataDir) -> Result<()> {
+    let mut session = Session {
+        database: DEFAULT_DATABASE.to_string(),
+        db: dir.database(DEFAULT_DATABASE)?,
+        keyspace: DEFAULT_KEYSPACE.to_string(),
+        tx: None,
+    };
     loop {
-        let line: String = readline()?;
+        let line: String = readline(&session)?;
         let line: &str = line.trim();
         if line.is_empty() {
             continue;
         }
 
-        match respond(line) {
+        match respond(dir, &mut session, line) {
             Ok(quit) => {
                 if quit {
                     break;
                 }
             }
             Err(err) => {
-                write!(std::io::stdout(), "{err}").map_err(|e| e.to_string())?;
-                std::io::stdout().flush().map_err(|e| e.to_string())?;
+                write!(std::io::stdout(), "{err}")?;
+                std::io::stdout().flush()?;
             }
         }
     }
@@ -34,18 +69,25 @@ pub fn run() -> Result<(), String> {
 /// Processes the user input and executes the corresponding command.
 ///
 /// # Arguments
+/// * `dir` - The data directory, which `create database`, `drop database`, `list databases`
+///   and `use database` work on.
+/// * `session` - The database and keyspace the keys of the command belong to, and the open
+///   transaction, if any. `insert`, `get`, `update`, `delete`, `mset`, `mdel`, `incr` and
+///   `decr` go through the transaction, while `scan` and `ttl` read the committed data and
+///   `watch` reports the committed changes.
 /// * `line` - The input line entered by the user.
 ///
 /// # Returns
 /// * `Ok(bool)` - A boolean indicating whether to quit the REPL.
 /// * `Err(String)` - An error message if the input processing fails.
-fn respond(line: &str) -> Result<bool, String> {
+fn respond(dir: &DataDir, session: &mut Session, line: &str) -> std::result::Result<bool, String> {
     let args = shlex::split(line).ok_or("error: Invalid quoting")?;
     let matches = cli()
         .try_get_matches_from(args)
         .map_err(|e| e.to_string())?;
-
-    let mut store: std::sync::MutexGuard<crate::Store> = STORE_MUTEX.lock().unwrap();
+    let db = Arc::clone(&session.db);
+    let keyspace = &db.keyspace(&session.keyspace).map_err(|e| e.to_string())?;
+    let tx = &mut session.tx;
 
     match matches.subcommand() {
         Some(("insert", sub_matches)) => {
@@ -59,8 +101,18 @@ fn respond(line: &str) -> Result<bool, String> {
                 .get_one::<String>("value")
                 .map(|s| s.as_str())
                 .unwrap();
+            let bytes = Encoding::from_matches(sub_matches).decode(value)?;
+            let ttl = sub_matches.get_one::<Duration>("ttl").copied();
+            let ttl = ttl.or(keyspace.options().default_ttl);
+            let (stored_key, stored) = (keyspace.key(key), keyspace.encode_value(&bytes));
+            let result = match (tx.as_mut(), ttl) {
+                (Some(tx), Some(ttl)) => tx.insert_with_ttl(stored_key, stored, ttl),
+                (Some(tx), None) => tx.insert(stored_key, stored),
+                (None, Some(ttl)) => keyspace.insert_with_ttl(key, bytes, ttl),
+                (None, None) => keyspace.insert(key, bytes),
+            };
 
-            match store.insert(key, value) {
+            match result {
                 Ok(_) => println!("Inserted entry {{'{key}': '{value}'}}"),
                 Err(e) => println!("Error {}", e),
             }
@@ -73,8 +125,18 @@ fn respond(line: &str) -> Result<bool, String> {
                 .map(|s| s.as_str())
                 .unwrap();
 
-            match store.get(key) {
-                Ok(pair) => println!("Entry: {{\"{key}\" : \"{}\"}}", pair.value),
+            let encoding = Encoding::from_matches(sub_matches);
+            let result = match tx.as_mut() {
+                Some(tx) => tx
+                    .get(keyspace.key(key))
+                    .and_then(|stored| stored.ok_or(Error::KeyNotFound))
+                    .and_then(|stored| keyspace.decode_value(&stored)),
+                None => keyspace
+                    .get(key)
+                    .and_then(|value| value.ok_or(Error::KeyNotFound)),
+            };
+            match result {
+                Ok(value) => println!("Entry: {{\"{key}\" : \"{}\"}}", encoding.encode(&value)),
                 Err(e) => println!("Error: {}", e),
             }
         }
@@ -90,8 +152,13 @@ fn respond(line: &str) -> Result<bool, String> {
                 .get_one::<String>("value")
                 .map(|s| s.as_str())
                 .unwrap();
+            let bytes = Encoding::from_matches(sub_matches).decode(value)?;
 
-            match store.update(key, value) {
+            let result = match tx.as_mut() {
+                Some(tx) => tx.update(keyspace.key(key), keyspace.encode_value(bytes)),
+                None => keyspace.update(key, bytes),
+            };
+            match result {
                 Ok(_) => println!("Updated entry {{'{key}' : '{value}'}}"),
                 Err(e) => println!("Error {}", e),
             }
@@ -104,8 +171,323 @@ fn respond(line: &str) -> Result<bool, String> {
                 .map(|s| s.as_str())
                 .unwrap();
 
-            store.delete(key);
-            println!("Entry deleted successfully")
+            let result = match tx.as_mut() {
+                Some(tx) => tx.delete(keyspace.key(key)),
+                None => keyspace.delete(key),
+            };
+            match result {
+                Ok(_) => println!("Entry deleted successfully"),
+                Err(e) => println!("Error {}", e),
+            }
+        }
+        Some(("mset", sub_matches)) => {
+            // Handle the 'mset' command to set several entries in a single atomic write
+            let args: Vec<&String> = sub_matches.get_many::<String>("pairs").unwrap().collect();
+            if !args.len().is_multiple_of(2) {
+                return Err(format!("error: missing the value of key '{}'", args[args.len() - 1]));
+            }
+            let encoding = Encoding::from_matches(sub_matches);
+            let mut batch = WriteBatch::new();
+            for pair in args.chunks(2) {
+                batch.put_in(keyspace, pair[0], encoding.decode(pair[1])?);
+            }
+
+            let count = batch.len();
+            let result = match tx.as_mut() {
+                Some(tx) => tx.write_batch(batch),
+                None => db.write_batch(batch),
+            };
+            match result {
+                Ok(_) => println!("Set {count} entries"),
+                Err(e) => println!("Error {}", e),
+            }
+        }
+
+        Some(("mdel", sub_matches)) => {
+            // Handle the 'mdel' command to delete several entries in a single atomic write
+            let mut batch = WriteBatch::new();
+            for key in sub_matches.get_many::<String>("keys").unwrap() {
+                batch.delete_in(keyspace, key);
+            }
+
+            let count = batch.len();
+            let result = match tx.as_mut() {
+                Some(tx) => tx.write_batch(batch),
+                None => db.write_batch(batch),
+            };
+            match result {
+                Ok(_) => println!("Deleted {count} entries"),
+                Err(e) => println!("Error {}", e),
+            }
+        }
+        Some((name @ ("incr" | "decr"), sub_matches)) => {
+            // Handle the 'incr' and 'decr' commands to change a counter atomically
+            let key: &str = sub_matches
+                .get_one::<String>("key")
+                .map(|s| s.as_str())
+                .unwrap();
+            let by = sub_matches.get_one::<i64>("by").copied().unwrap_or(1);
+            let delta = if name == "incr" { Some(by) } else { by.checked_neg() };
+            let delta = delta.ok_or_else(|| format!("error: can't decrement by {by}"))?;
+
+            let result = match tx.as_mut() {
+                Some(tx) => incr_in_transaction(tx, keyspace, key, delta),
+                None => keyspace.incr_by(key, delta),
+            };
+            match result {
+                Ok(count) => println!("Counter {{'{key}' : '{count}'}}"),
+                Err(e) => println!("Error {}", e),
+            }
+        }
+        Some(("scan", sub_matches)) => {
+            // Handle the 'scan' command to list entries in key order
+            let get = |name: &str| sub_matches.get_one::<String>(name).map(|s| s.as_str());
+            let limit = sub_matches
+                .get_one::<usize>("limit")
+                .copied()
+                .unwrap_or(usize::MAX);
+
+            let scan = match get("prefix") {
+                Some(prefix) => keyspace.scan_prefix(prefix),
+                None => keyspace.scan((
+                    get("start").map_or(Bound::Unbounded, Bound::Included),
+                    get("end").map_or(Bound::Unbounded, Bound::Excluded),
+                )),
+            };
+            let scan = scan.map_err(|e| e.to_string())?;
+            let entries: Vec<KV> = if sub_matches.get_flag("reverse") {
+                scan.into_iter().rev().take(limit).collect()
+            } else {
+                scan.into_iter().take(limit).collect()
+            };
+
+            let encoding = Encoding::from_matches(sub_matches);
+            for pair in &entries {
+                println!(
+                    "Entry: {{\"{}\" : \"{}\"}}",
+                    pair.key_str(),
+                    encoding.encode(&pair.value)
+                );
+            }
+            println!("({} entries)", entries.len());
+        }
+        Some(("ttl", sub_matches)) => {
+            // Handle the 'ttl' command to show how long a key has left to live
+            let key: &str = sub_matches
+                .get_one::<String>("key")
+                .map(|s| s.as_str())
+                .unwrap();
+
+            let result = keyspace.ttl(key);
+            match result {
+                Ok(Some(ttl)) => println!("TTL: {}", format_duration(ttl)),
+                Ok(None) => println!("TTL: none, the entry never expires"),
+                Err(e) => println!("Error {}", e),
+            }
+        }
+        Some(("watch", sub_matches)) => {
+            // Handle the 'watch' command to print the changes of a key prefix until Enter is pressed
+            let prefix: &str = sub_matches
+                .get_one::<String>("prefix")
+                .map_or("", |s| s.as_str());
+
+            let watcher = db.watch(keyspace.key(prefix)).map_err(|e| e.to_string())?;
+            println!("Watching keys starting with '{prefix}', press Enter to stop");
+            let count = print_events(watcher, keyspace, Encoding::from_matches(sub_matches))
+                .map_err(|e| e.to_string())?;
+            println!("Stopped watching ({count} events)");
+        }
+        Some(("use", sub_matches)) => match sub_matches.subcommand() {
+            Some(("database", matches)) => {
+                // Handle the 'use database' command to run the next commands in another database
+                let name = matches.get_one::<String>("name").unwrap();
+                if tx.is_some() {
+                    println!("Error: a transaction is open, commit or rollback it first");
+                } else {
+                    match dir.database(name) {
+                        Ok(selected) => {
+                            session.db = selected;
+                            session.database = name.to_string();
+                            session.keyspace = DEFAULT_KEYSPACE.to_string();
+                            println!("Using database '{name}'");
+                        }
+                        Err(e) => println!("Error {}", e),
+                    }
+                }
+            }
+            _ => {
+                // Handle the 'use' command to run the next commands in another keyspace, like 'keyspace use'
+                let name = sub_matches.get_one::<String>("keyspace").unwrap();
+                match db.keyspace(name) {
+                    Ok(_) => {
+                        session.keyspace = name.to_string();
+                        println!("Using keyspace '{name}'");
+                    }
+                    Err(e) => println!("Error {}", e),
+                }
+            }
+        },
+        Some(("create", sub_matches)) => {
+            // Handle the 'create database' command to add a database to the data directory
+            let (_, matches) = sub_matches.subcommand().unwrap();
+            let name = matches.get_one::<String>("name").unwrap();
+            match dir.create_database(name) {
+                Ok(_) => println!("Database '{name}' created"),
+                Err(e) => println!("Error {}", e),
+            }
+        }
+        Some(("drop", sub_matches)) => {
+            // Handle the 'drop database' command to delete a database and its files
+            let (_, matches) = sub_matches.subcommand().unwrap();
+            let name = matches.get_one::<String>("name").unwrap();
+            if *name == session.database {
+                println!("Error: database '{name}' is in use, use another database first");
+            } else {
+                match dir.drop_database(name) {
+                    Ok(()) => println!("Database '{name}' dropped"),
+                    Err(e) => println!("Error {}", e),
+                }
+            }
+        }
+        Some(("list", _matches)) => {
+            // Handle the 'list databases' command to show the databases of the data directory
+            for name in dir.databases().map_err(|e| e.to_string())? {
+                let current = if name == session.database {
+                    " (in use)"
+                } else {
+                    ""
+                };
+                println!("Database: {name}{current}");
+            }
+        }
+        Some(("keyspace", sub_matches)) => {
+            // Handle the 'keyspace' commands to create, drop, list and select keyspaces
+            match sub_matches.subcommand() {
+                Some(("use", matches)) => {
+                    let name = matches.get_one::<String>("name").unwrap();
+                    match db.keyspace(name) {
+                        Ok(_) => {
+                            session.keyspace = name.to_string();
+                            println!("Using keyspace '{name}'");
+                        }
+                        Err(e) => println!("Error {}", e),
+                    }
+                }
+                Some(("create", matches)) => {
+                    let name = matches.get_one::<String>("name").unwrap();
+                    let options = KeyspaceOptions {
+                        default_ttl: matches.get_one::<Duration>("ttl").copied(),
+                        compression: if matches.get_flag("lz4") {
+                            Compression::Lz4
+                        } else {
+                            Compression::None
+                        },
+                    };
+                    match db.create_keyspace(name, options) {
+                        Ok(_) => println!("Keyspace '{name}' created"),
+                        Err(e) => println!("Error {}", e),
+                    }
+                }
+                Some(("drop", matches)) => {
+                    let name = matches.get_one::<String>("name").unwrap();
+                    match db.drop_keyspace(name) {
+                        Ok(()) => {
+                            println!("Keyspace '{name}' dropped");
+                            if keyspace.name() == name {
+                                session.keyspace = DEFAULT_KEYSPACE.to_string();
+                                println!("Using keyspace '{DEFAULT_KEYSPACE}'");
+                            }
+                        }
+                        Err(e) => println!("Error {}", e),
+                    }
+                }
+                _ => {
+                    for name in db.keyspaces().map_err(|e| e.to_string())? {
+                        let current = if name == keyspace.name() {
+                            " (in use)"
+                        } else {
+                            ""
+                        };
+                        println!("Keyspace: {name}{current}");
+                    }
+                }
+            }
+        }
+        Some(("index", sub_matches)) => {
+            // Handle the 'index' commands to create, drop and list the secondary indexes
+            match sub_matches.subcommand() {
+                Some(("create", matches)) => {
+                    let name = matches.get_one::<String>("name").unwrap();
+                    let path = matches.get_one::<String>("path").unwrap();
+                    match db.create_index(name, Extractor::json_path(path)) {
+                        Ok(()) => println!("Index '{name}' created on '{path}'"),
+                        Err(e) => println!("Error {}", e),
+                    }
+                }
+                Some(("drop", matches)) => {
+                    let name = matches.get_one::<String>("name").unwrap();
+                    match db.drop_index(name) {
+                        Ok(()) => println!("Index '{name}' dropped"),
+                        Err(e) => println!("Error {}", e),
+                    }
+                }
+                _ => {
+                    for name in db.indexes().map_err(|e| e.to_string())? {
+                        println!("Index: {name}");
+                    }
+                }
+            }
+        }
+        Some(("find", sub_matches)) => {
+            // Handle the 'find' command to list the entries an index finds by a value
+            let index = sub_matches.get_one::<String>("index").unwrap();
+            let value = sub_matches.get_one::<String>("value").unwrap();
+            let encoding = Encoding::from_matches(sub_matches);
+
+            match keyspace.find(index, value) {
+                Ok(entries) => {
+                    for pair in &entries {
+                        println!(
+                            "Entry: {{\"{}\" : \"{}\"}}",
+                            pair.key_str(),
+                            encoding.encode(&pair.value)
+                        );
+                    }
+                    println!("({} entries)", entries.len());
+                }
+                Err(e) => println!("Error {}", e),
+            }
+        }
+        Some(("begin", matches)) => {
+            // Handle the 'begin' command to start buffering writes in a transaction
+            if tx.is_some() {
+                println!("Error: a transaction is already open, commit or rollback it first");
+            } else {
+                let isolation = matches
+                    .get_one::<IsolationLevel>("isolation")
+                    .copied()
+                    .unwrap_or_default();
+                *tx = Some(db.begin_with(isolation).map_err(|e| e.to_string())?);
+                println!("Transaction started ({})", format_isolation(isolation));
+            }
+        }
+        Some(("commit", _matches)) => {
+            // Handle the 'commit' command to apply the open transaction atomically
+            match tx.take().map(Transaction::commit) {
+                Some(Ok(())) => println!("Transaction committed"),
+                Some(Err(e)) => println!("Error {}, the transaction was rolled back", e),
+                None => println!("Error: no transaction is open"),
+            }
+        }
+        Some(("rollback", _matches)) => {
+            // Handle the 'rollback' command to discard the open transaction
+            match tx.take() {
+                Some(open) => {
+                    open.rollback();
+                    println!("Transaction rolled back");
+                }
+                None => println!("Error: no transaction is open"),
+            }
         }
         Some(("quit", _matches)) => {
             // Handle the 'quit' command to exit the REPL
@@ -141,13 +523,19 @@ fn cli() -> Command {
                 .about("Inserts a new entry")
                 .arg_required_else_help(true)
                 .arg(arg!(key: [KEY]).required(true))
-                .arg(arg!(value: [VALUE]).required(true)),
+                .arg(arg!(value: [VALUE]).required(true))
+                .arg(
+                    arg!(--ttl <DURATION> "expire the entry after DURATION, e.g. 500ms, 30s, 5m, 2h or 1d")
+                        .value_parser(parse_duration),
+                )
+                .args(encoding_args("VALUE is")),
         )
         .subcommand(
             Command::new("get")
                 .about("get entry value by key")
                 .arg_required_else_help(true)
-                .arg(arg!(key: [KEY]).required(true)),
+                .arg(arg!(key: [KEY]).required(true))
+                .args(encoding_args("print the value")),
         )
         .subcommand(
             Command::new("delete")
@@ -160,8 +548,165 @@ fn cli() -> Command {
                 .about("update entry value")
                 .arg_required_else_help(true)
                 .arg(arg!(key: [KEY]).required(true))
-                .arg(arg!(value: [VALUE]).required(true)),
+                .arg(arg!(value: [VALUE]).required(true))
+                .args(encoding_args("VALUE is")),
+        )
+        .subcommand(
+            Command::new("mset")
+                .about("set several entries at once, atomically")
+                .arg_required_else_help(true)
+                .arg(arg!(pairs: [PAIRS] "KEY VALUE pairs").required(true).num_args(2..))
+                .args(encoding_args("VALUEs are")),
+        )
+        .subcommand(
+            Command::new("mdel")
+                .about("delete several entries at once, atomically")
+                .arg_required_else_help(true)
+                .arg(arg!(keys: [KEYS]).required(true).num_args(1..)),
+        )
+        .subcommand(
+            Command::new("incr")
+                .about("increment a counter, a missing entry counts as 0")
+                .arg_required_else_help(true)
+                .arg(arg!(key: [KEY]).required(true))
+                .arg(counter_step()),
+        )
+        .subcommand(
+            Command::new("decr")
+                .about("decrement a counter, a missing entry counts as 0")
+                .arg_required_else_help(true)
+                .arg(arg!(key: [KEY]).required(true))
+                .arg(counter_step()),
+        )
+        .subcommand(
+            Command::new("scan")
+                .about("list entries in key order")
+                .arg(
+                    arg!(--prefix <PREFIX> "only keys starting with PREFIX")
+                        .conflicts_with_all(["start", "end"]),
+                )
+                .arg(arg!(--start <KEY> "first key of the range (inclusive)"))
+                .arg(arg!(--end <KEY> "end of the range (exclusive)"))
+                .arg(
+                    arg!(--limit <N> "maximum number of entries to print")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(arg!(--reverse "iterate from the greatest key down"))
+                .args(encoding_args("print the values")),
+        )
+        .subcommand(
+            Command::new("ttl")
+                .about("show how long an entry has left to live")
+                .arg_required_else_help(true)
+                .arg(arg!(key: [KEY]).required(true)),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("print the changes of the entries whose key starts with PREFIX, until Enter is pressed")
+                .arg(arg!(prefix: [PREFIX] "watch every entry if omitted"))
+                .args(encoding_args("print the values")),
+        )
+        .subcommand(
+            Command::new("use")
+                .about("run the next commands in another keyspace of the database, or with 'use database', in another database")
+                .arg_required_else_help(true)
+                .args_conflicts_with_subcommands(true)
+                .arg(arg!(keyspace: [KEYSPACE] "the keyspace, 'default' for the keys outside any"))
+                .subcommand(
+                    Command::new("database")
+                        .about("run the next commands in another database, in its default keyspace")
+                        .arg_required_else_help(true)
+                        .arg(arg!(name: [NAME]).required(true)),
+                ),
+        )
+        .subcommand(
+            Command::new("create")
+                .about("create a database")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("database")
+                        .arg_required_else_help(true)
+                        .arg(arg!(name: [NAME]).required(true)),
+                ),
+        )
+        .subcommand(
+            Command::new("drop")
+                .about("drop a database and every file it holds")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("database")
+                        .arg_required_else_help(true)
+                        .arg(arg!(name: [NAME]).required(true)),
+                ),
         )
+        .subcommand(
+            Command::new("list")
+                .about("list the databases, the default one first")
+                .subcommand_required(true)
+                .subcommand(Command::new("databases")),
+        )
+        .subcommand(
+            Command::new("keyspace")
+                .about("create, drop, list or use the keyspaces of the database")
+                .subcommand(
+                    Command::new("use")
+                        .about("run the next commands in another keyspace")
+                        .arg_required_else_help(true)
+                        .arg(arg!(name: [NAME] "the keyspace, 'default' for the keys outside any").required(true)),
+                )
+                .subcommand(
+                    Command::new("create")
+                        .about("create a keyspace")
+                        .arg_required_else_help(true)
+                        .arg(arg!(name: [NAME]).required(true))
+                        .arg(
+                            arg!(--ttl <DURATION> "expire the entries inserted without a TTL after DURATION")
+                                .value_parser(parse_duration),
+                        )
+                        .arg(arg!(--lz4 "compress the values with LZ4")),
+                )
+                .subcommand(
+                    Command::new("drop")
+                        .about("drop a keyspace and every entry it holds")
+                        .arg_required_else_help(true)
+                        .arg(arg!(name: [NAME]).required(true)),
+                )
+                .subcommand(Command::new("list").about("list the keyspaces, the default one first")),
+        )
+        .subcommand(
+            Command::new("index")
+                .about("create, drop or list the secondary indexes of the database")
+                .subcommand(
+                    Command::new("create")
+                        .about("index the entries by the field at a JSON path of their values")
+                        .arg_required_else_help(true)
+                        .arg(arg!(name: [NAME]).required(true))
+                        .arg(arg!(path: [PATH] "e.g. $.email, address.city or tags").required(true)),
+                )
+                .subcommand(
+                    Command::new("drop")
+                        .about("drop a secondary index")
+                        .arg_required_else_help(true)
+                        .arg(arg!(name: [NAME]).required(true)),
+                )
+                .subcommand(Command::new("list").about("list the secondary indexes")),
+        )
+        .subcommand(
+            Command::new("find")
+                .about("list the entries of the keyspace an index finds by VALUE, in key order")
+                .arg_required_else_help(true)
+                .arg(arg!(index: [INDEX]).required(true))
+                .arg(arg!(value: [VALUE]).required(true))
+                .args(encoding_args("print the values")),
+        )
+        .subcommand(
+            Command::new("begin").about("start a transaction").arg(
+                arg!(--isolation <LEVEL> "read-committed, snapshot (or repeatable-read) or serializable, the default")
+                    .value_parser(parse_isolation),
+            ),
+        )
+        .subcommand(Command::new("commit").about("commit the open transaction"))
+        .subcommand(Command::new("rollback").about("discard the open transaction"))
         .subcommand(
             Command::new("quit")
                 .alias("exit")
@@ -170,17 +715,232 @@ fn cli() -> Command {
         )
 }
 
+/// The `--by` argument of `incr` and `decr`.
+fn counter_step() -> clap::Arg {
+    arg!(--by <N> "change the counter by N instead of 1")
+        .value_parser(clap::value_parser!(i64))
+        .allow_negative_numbers(true)
+}
+
+/// Adds `delta` to a counter inside a transaction, which buffers the new value until the
+/// commit; the commit then fails if another writer changed the counter meanwhile.
+///
+/// # Returns
+/// * `Ok(i64)` - The new value of the counter.
+/// * `Err(Error)` - If the value is not an integer or the transaction can't read the key.
+fn incr_in_transaction(
+    tx: &mut Transaction,
+    keyspace: &Keyspace,
+    key: &str,
+    delta: i64,
+) -> Result<i64> {
+    let stored_key = keyspace.key(key);
+    let current = tx
+        .get(&stored_key)?
+        .map(|stored| keyspace.decode_value(&stored))
+        .transpose()?;
+    let value = merge::Counter.merge(
+        key.as_bytes(),
+        current.as_deref(),
+        delta.to_string().as_bytes(),
+    )?;
+    let stored = keyspace.encode_value(&value);
+    match (current, keyspace.options().default_ttl) {
+        (Some(_), _) => tx.update(stored_key, stored)?, // Keeps the expiry time
+        (None, Some(ttl)) => tx.insert_with_ttl(stored_key, stored, ttl)?,
+        (None, None) => tx.insert(stored_key, stored)?,
+    }
+    merge::parse_integer(key.as_bytes(), &value)
+}
+
+/// Prints the events of `watcher` as they come, until the user presses Enter.
+///
+/// # Returns
+/// * `Ok(usize)` - The number of events printed.
+/// * `Err(Error)` - If reading the input fails.
+fn print_events(watcher: Watcher, keyspace: &Keyspace, encoding: Encoding) -> Result<usize> {
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let stop = &stop;
+        let printer = scope.spawn(move || {
+            let mut count = 0;
+            while !stop.load(Ordering::Relaxed) {
+                // Wake up now and then to notice the stop
+                if let Some(event) = watcher.recv_timeout(Duration::from_millis(100)) {
+                    println!("{}", format_event(&event, keyspace, encoding));
+                    count += 1;
+                }
+            }
+            count
+        });
+        let mut buffer = String::new();
+        let read = std::io::stdin().read_line(&mut buffer);
+        stop.store(true, Ordering::Relaxed);
+        let count = printer.join().expect("the event printer panicked");
+        read.map(|_| count).map_err(Error::from)
+    })
+}
+
+/// Formats a watch event of `keyspace` for printing, without the prefix of the keyspace.
+fn format_event(event: &Event, keyspace: &Keyspace, encoding: Encoding) -> String {
+    let prefix_len = keyspace.key("").len();
+    let key = String::from_utf8_lossy(&event.key[prefix_len..]);
+    match (event.kind, &event.value) {
+        (EventKind::Put, Some(value)) => format!(
+            "Event #{}: put {{\"{key}\" : \"{}\"}}",
+            event.seq,
+            keyspace
+                .decode_value(value)
+                .map_or_else(|e| e.to_string(), |value| encoding.encode(&value))
+        ),
+        (EventKind::Expire, _) => format!("Event #{}: expire \"{key}\"", event.seq),
+        _ => format!("Event #{}: delete \"{key}\"", event.seq),
+    }
+}
+
+/// Parses the name of an isolation level.
+///
+/// # Returns
+/// * `Ok(IsolationLevel)` - The parsed level.
+/// * `Err(String)` - An error message if the input names no level.
+fn parse_isolation(input: &str) -> std::result::Result<IsolationLevel, String> {
+    match input {
+        "read-committed" => Ok(IsolationLevel::ReadCommitted),
+        "snapshot" | "repeatable-read" => Ok(IsolationLevel::Snapshot),
+        "serializable" => Ok(IsolationLevel::Serializable),
+        _ => Err(format!(
+            "unknown isolation level '{input}', expected read-committed, snapshot or serializable"
+        )),
+    }
+}
+
+/// Formats an isolation level the way `parse_isolation` reads it.
+fn format_isolation(isolation: IsolationLevel) -> &'static str {
+    match isolation {
+        IsolationLevel::ReadCommitted => "read-committed",
+        IsolationLevel::Snapshot => "snapshot",
+        IsolationLevel::Serializable => "serializable",
+    }
+}
+
+/// Parses a duration made of a number and a unit: `ms`, `s`, `m`, `h` or `d`.
+/// A number without a unit is a number of seconds.
+///
+/// # Returns
+/// * `Ok(Duration)` - The parsed duration.
+/// * `Err(String)` - An error message if the input is not a valid duration.
+fn parse_duration(input: &str) -> std::result::Result<Duration, String> {
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{input}'"))?;
+    let millis = match unit {
+        "ms" => 1,
+        "" | "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => {
+            return Err(format!(
+                "invalid duration unit '{unit}', expected ms, s, m, h or d"
+            ))
+        }
+    };
+    number
+        .checked_mul(millis)
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("duration '{input}' is too long"))
+}
+
+/// Formats a time to live for printing, in whole seconds past the first second.
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{}s", duration.as_secs())
+    }
+}
+
+/// Returns the `--hex` and `--base64` flags selecting how a command encodes values.
+///
+/// # Arguments
+/// * `what` - The start of the help text, e.g. "VALUE is" gives "VALUE is hex encoded".
+fn encoding_args(what: &str) -> [clap::Arg; 2] {
+    [
+        arg!(--hex)
+            .help(format!("{what} hex encoded"))
+            .conflicts_with("base64"),
+        arg!(--base64).help(format!("{what} base64 encoded")),
+    ]
+}
+
+/// How values are typed in and printed by the REPL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    /// UTF-8 text; invalid sequences are printed as `U+FFFD`.
+    Text,
+    Hex,
+    Base64,
+}
+
+impl Encoding {
+    /// Reads the encoding selected by the `--hex` / `--base64` flags of a command.
+    fn from_matches(matches: &ArgMatches) -> Self {
+        if matches.get_flag("hex") {
+            Encoding::Hex
+        } else if matches.get_flag("base64") {
+            Encoding::Base64
+        } else {
+            Encoding::Text
+        }
+    }
+
+    /// Decodes a value typed by the user into bytes.
+    fn decode(self, input: &str) -> std::result::Result<Vec<u8>, String> {
+        match self {
+            Encoding::Text => Ok(input.as_bytes().to_vec()),
+            Encoding::Hex => {
+                hex::decode(input).map_err(|e| format!("error: Invalid hex value: {e}"))
+            }
+            Encoding::Base64 => BASE64
+                .decode(input)
+                .map_err(|e| format!("error: Invalid base64 value: {e}")),
+        }
+    }
+
+    /// Encodes a value for printing.
+    fn encode(self, value: &[u8]) -> String {
+        match self {
+            Encoding::Text => String::from_utf8_lossy(value).into_owned(),
+            Encoding::Hex => hex::encode(value),
+            Encoding::Base64 => BASE64.encode(value),
+        }
+    }
+}
+
 /// Reads a line of input from the user.
 ///
+/// # Arguments
+/// * `session` - The database and keyspace in use, which the prompt shows unless they are
+///   the default ones, and whether a transaction is open.
+///
 /// # Returns
 /// * `Ok(String)` - The input line entered by the user.
-/// * `Err(String)` - An error message if reading input fails.
-fn readline() -> Result<String, String> {
-    write!(std::io::stdout(), "\n(safinaDB) ➜ ").map_err(|e| e.to_string())?;
-    std::io::stdout().flush().map_err(|e| e.to_string())?;
+/// * `Err(Error)` - An error if reading input fails.
+fn readline(session: &Session) -> Result<String> {
+    let location = match (session.database.as_str(), session.keyspace.as_str()) {
+        (DEFAULT_DATABASE, DEFAULT_KEYSPACE) => String::new(),
+        (database, DEFAULT_KEYSPACE) => format!(" {database}"),
+        (database, keyspace) => format!(" {database}:{keyspace}"),
+    };
+    let tx = if session.tx.is_some() { " tx" } else { "" };
+    let prompt = format!("(safinaDB{location}{tx})");
+    write!(std::io::stdout(), "\n{prompt} ➜ ")?;
+    std::io::stdout().flush()?;
     let mut buffer = String::new();
-    std::io::stdin()
-        .read_line(&mut buffer)
-        .map_err(|e| e.to_string())?;
+    std::io::stdin().read_line(&mut buffer)?;
     Ok(buffer)
 }
@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::database::{Database, Options};
+use crate::error::{Error, Result};
+use crate::storage::snapshot;
+
+/// Name of the database a data directory always holds.
+pub const DEFAULT_DATABASE: &str = "default";
+
+/// Name of the database files within the directory of each database.
+const DATABASE_FILE: &str = "db";
+
+/// A directory holding any number of named databases.
+///
+/// Each database lives in its own subdirectory, `<root>/<name>`, so its files (shards,
+/// write-ahead logs, change log) are isolated from the others, and is opened with the
+/// `Options` of the data directory. A database is only opened the first time it is asked
+/// for, then its handle is shared by every caller until it is dropped or the data directory
+/// is closed.
+///
+/// Names are made of ASCII letters, digits, `-` and `_`. The `default` database always
+/// exists.
+///
+/// # Example
+/// ```rust
+/// use safina_db::{Backend, DataDir, Options};
+///
+/// # let _ = std::fs::remove_dir_all("example-data");
+/// let options = Options { backend: Backend::Memory, ..Options::default() };
+/// let dir = DataDir::open("example-data", options).unwrap();
+/// let sales = dir.create_database("sales").unwrap();
+/// sales.insert("order:1", "book").unwrap();
+/// assert_eq!(dir.databases().unwrap(), ["default", "sales"]);
+/// assert_eq!(dir.database("default").unwrap().get("order:1").unwrap(), None);
+/// # std::fs::remove_dir_all("example-data").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct DataDir {
+    root: PathBuf,
+    options: Options,
+    /// The databases opened so far, by name.
+    open: Mutex<BTreeMap<String, Arc<Database>>>,
+}
+
+impl DataDir {
+    /// Opens (or creates) the data directory at `root`. No database is opened yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The directory holding the databases.
+    /// * `options` - The settings every database is opened with.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(DataDir)` - The data directory, holding at least the default database.
+    /// * `Err(Error)` - An error message if the directory can't be created.
+    pub fn open<P: AsRef<Path>>(root: P, options: Options) -> Result<DataDir> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(root.join(DEFAULT_DATABASE))?;
+        Ok(DataDir {
+            root,
+            options,
+            open: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Returns the directory holding the databases.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Creates database `name` and opens it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Arc<Database>)` - The new database.
+    /// * `Err(Error::DatabaseExists)` - If the database already exists.
+    /// * `Err(Error::InvalidDatabaseName)` - If `name` can't name a database.
+    /// * `Err(Error)` - An error message if the database can't be created.
+    pub fn create_database(&self, name: &str) -> Result<Arc<Database>> {
+        check_name(name)?;
+        let mut open = self.open.lock().map_err(|_| Error::Poisoned)?;
+        match fs::create_dir(self.root.join(name)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                return Err(Error::DatabaseExists(name.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let db = match Database::open(&self.database_path(name), self.options.clone()) {
+            Ok(db) => Arc::new(db),
+            Err(e) => {
+                let _ = fs::remove_dir_all(self.root.join(name)); // Don't leave half a database
+                return Err(e);
+            }
+        };
+        open.insert(name.to_string(), Arc::clone(&db));
+        Ok(db)
+    }
+
+    /// Returns database `name`, opening it if this is the first time it is asked for.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Arc<Database>)` - The database.
+    /// * `Err(Error::DatabaseNotFound)` - If the database doesn't exist.
+    /// * `Err(Error)` - An error message if the database can't be opened.
+    pub fn database(&self, name: &str) -> Result<Arc<Database>> {
+        let mut open = self.open.lock().map_err(|_| Error::Poisoned)?;
+        if let Some(db) = open.get(name) {
+            return Ok(Arc::clone(db));
+        }
+        if check_name(name).is_err() || !self.root.join(name).is_dir() {
+            return Err(Error::DatabaseNotFound(name.to_string()));
+        }
+        let db = Arc::new(Database::open(
+            &self.database_path(name),
+            self.options.clone(),
+        )?);
+        open.insert(name.to_string(), Arc::clone(&db));
+        Ok(db)
+    }
+
+    /// Closes database `name` and deletes its files.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the database is gone.
+    /// * `Err(Error::DatabaseNotFound)` - If the database doesn't exist.
+    /// * `Err(Error::DatabaseInUse)` - If a handle on the database is still held elsewhere.
+    /// * `Err(Error::InvalidDatabaseName)` - For the default database, which can't be dropped.
+    /// * `Err(Error)` - An error message if the database can't be closed or deleted.
+    pub fn drop_database(&self, name: &str) -> Result<()> {
+        if name == DEFAULT_DATABASE {
+            return Err(Error::InvalidDatabaseName(name.to_string()));
+        }
+        let mut open = self.open.lock().map_err(|_| Error::Poisoned)?;
+        if check_name(name).is_err() || !self.root.join(name).is_dir() {
+            return Err(Error::DatabaseNotFound(name.to_string()));
+        }
+        if let Some(db) = open.remove(name) {
+            match Arc::try_unwrap(db) {
+                Ok(db) => db.close()?,
+                Err(db) => {
+                    open.insert(name.to_string(), db); // Still used, keep it open
+                    return Err(Error::DatabaseInUse(name.to_string()));
+                }
+            }
+        }
+        fs::remove_dir_all(self.root.join(name))?;
+        Ok(())
+    }
+
+    /// Moves a database opened on its own at `path`, as the CLI did before data directories,
+    /// into the default database.
+    ///
+    /// Only the files of that database are moved: the snapshot at `path`, `<path>.wal`,
+    /// `<path>.shards`, the shards `<path>.shard-<i>` and their `<path>.shard-<i>.wal`, the
+    /// commit log `<path>.txn` and the change log `<path>.cdc`. Other files named after
+    /// `path` are left alone. Nothing is moved unless the snapshots decode, nor while the
+    /// default database is open or holds files, so nothing gets overwritten.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - Once the database has been moved.
+    /// * `Ok(false)` - If there is no database at `path`.
+    /// * `Err(Error::Corruption)` - If `path` or one of the shards is not a snapshot.
+    /// * `Err(Error::DatabaseExists)` - If the default database already holds files.
+    /// * `Err(Error::DatabaseInUse)` - If the default database is open.
+    /// * `Err(Error)` - An error message if the files can't be moved.
+    pub fn import_legacy<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref();
+        let (Some(name), Some(parent)) = (path.file_name(), path.parent()) else {
+            return Ok(false);
+        };
+        let name = name.to_string_lossy().into_owned();
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        let mut files = Vec::new();
+        for entry in fs::read_dir(parent)? {
+            let file_name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(snapshot) = file_name.strip_prefix(&name).and_then(legacy_file) {
+                files.push((file_name, snapshot));
+            }
+        }
+        if files.is_empty() {
+            return Ok(false);
+        }
+        for (file_name, snapshot) in &files {
+            let file = parent.join(file_name);
+            if *snapshot && (!file.is_file() || snapshot::decode(&fs::read(&file)?).is_err()) {
+                return Err(Error::Corruption(format!(
+                    "{} is not a database snapshot",
+                    file.display()
+                )));
+            }
+        }
+
+        let open = self.open.lock().map_err(|_| Error::Poisoned)?;
+        if open.contains_key(DEFAULT_DATABASE) {
+            return Err(Error::DatabaseInUse(DEFAULT_DATABASE.to_string()));
+        }
+        let target = self.root.join(DEFAULT_DATABASE);
+        if fs::read_dir(&target)?.next().is_some() {
+            return Err(Error::DatabaseExists(DEFAULT_DATABASE.to_string()));
+        }
+        for (file_name, _) in files {
+            let renamed = file_name.replacen(&name, DATABASE_FILE, 1);
+            fs::rename(parent.join(&file_name), target.join(renamed))?;
+        }
+        Ok(true)
+    }
+
+    /// Returns the names of the databases, the default one first, then in name order.
+    pub fn databases(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() && name != DEFAULT_DATABASE && check_name(&name).is_ok()
+            {
+                names.push(name);
+            }
+        }
+        names.sort();
+        names.insert(0, DEFAULT_DATABASE.to_string());
+        Ok(names)
+    }
+
+    /// Returns `true` if database `name` has been opened, see `database`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - Whether the database is open.
+    /// * `Err(Error::Poisoned)` - If the lock on the open databases is poisoned.
+    pub fn is_open(&self, name: &str) -> Result<bool> {
+        let open = self.open.lock().map_err(|_| Error::Poisoned)?;
+        Ok(open.contains_key(name))
+    }
+
+    /// Closes every database opened so far, reporting the first error hit while flushing.
+    /// Databases still held elsewhere are closed once their last handle is dropped.
+    pub fn close(self) -> Result<()> {
+        let open = self.open.into_inner().map_err(|_| Error::Poisoned)?;
+        let mut result = Ok(());
+        for db in open.into_values() {
+            if let Ok(db) = Arc::try_unwrap(db) {
+                result = result.and(db.close()); // Every database is closed
+            }
+        }
+        result
+    }
+
+    /// Returns the path database `name` is opened at.
+    fn database_path(&self, name: &str) -> String {
+        self.root
+            .join(name)
+            .join(DATABASE_FILE)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Tells whether `suffix`, what follows the path of a legacy database in a file name, names
+/// one of its files, see `DataDir::import_legacy`.
+///
+/// # Returns
+///
+/// * `Some(true)` - For the snapshot of the database or of a shard.
+/// * `Some(false)` - For its other files.
+/// * `None` - If the file is not one of the database.
+fn legacy_file(suffix: &str) -> Option<bool> {
+    match suffix {
+        "" => Some(true),
+        ".wal" | ".shards" | ".txn" | ".cdc" => Some(false),
+        _ => {
+            let shard = suffix.strip_prefix(".shard-")?;
+            let (index, snapshot) = match shard.strip_suffix(".wal") {
+                Some(index) => (index, false),
+                None => (shard, true),
+            };
+            let digits = !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit());
+            digits.then_some(snapshot)
+        }
+    }
+}
+
+/// Checks that `name` can name a database: not empty, and only ASCII letters, digits, `-`
+/// and `_`, so it is a valid directory name on every platform.
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidDatabaseName(name.to_string()))
+    }
+}
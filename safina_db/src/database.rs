@@ -0,0 +1,1020 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::batch::WriteBatch;
+use crate::error::{Error, Result};
+use crate::index::{self, Decoder, Extractor, Index};
+use crate::keyspace::{
+    self, CompressedKeyspaces, Keyspace, KeyspaceOptions, CATALOG_KEY, DEFAULT_KEYSPACE,
+};
+use crate::kv_store::{Store, Value, KV};
+use crate::merge::MergeOperators;
+use crate::shards::{byte_bounds, Shards};
+use crate::snapshot::Snapshot;
+use crate::storage::bitcask::{Bitcask, BitcaskOptions};
+use crate::storage::commit_log::CommitLog;
+use crate::storage::{
+    ChangeLog, ChangeLogOptions, Durability, JsonStorage, MemoryBackend, Mutation, Storage,
+    StorageBackend, SyncTicket,
+};
+use crate::transaction::{IsolationLevel, Transaction};
+use crate::watch::Watcher;
+
+/// The storage backend a `Database` is opened with.
+#[derive(Debug, Clone, Default)]
+pub enum Backend {
+    /// A bincode snapshot file plus a write-ahead log, see `Storage`.
+    #[default]
+    Bincode,
+    /// A human readable JSON file rewritten on every mutation, see `JsonStorage`.
+    Json,
+    /// A directory of append-only segments, see `Bitcask`.
+    Bitcask(BitcaskOptions),
+    /// Nothing is persisted, the path is ignored.
+    Memory,
+}
+
+/// Settings a `Database` is opened with.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub backend: Backend,
+    /// How often a background thread deletes the expired pairs, persisting the deletions.
+    /// With `None` expired pairs are only hidden, until `Store::purge_expired` is called.
+    pub sweep_interval: Option<Duration>,
+    /// How many shards the keys are split into. Each shard has its own lock and backend
+    /// files, so writes to keys of different shards run in parallel. A database must be
+    /// reopened with the shard count it was created with; `0` counts as `1`.
+    pub shard_count: usize,
+    /// When writes are synced to disk, see `Durability`. Each shard syncs its own files.
+    pub durability: Durability,
+    /// Keeps a change log of every committed mutation in `<path>.cdc`, see `ChangeLog`.
+    /// `None`, the default, keeps none.
+    pub change_log: Option<ChangeLogOptions>,
+    /// The operators merges are folded with, by name, see `Store::merge`. A database must be
+    /// opened with every operator its files may hold operands of; the built-in ones by default.
+    pub merge_operators: MergeOperators,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            backend: Backend::default(),
+            sweep_interval: Some(Duration::from_secs(1)),
+            shard_count: 1,
+            durability: Durability::default(),
+            change_log: None,
+            merge_operators: MergeOperators::default(),
+        }
+    }
+}
+
+/// A handle on an open database.
+///
+/// The handle owns its stores and the storage backends under them, so any number of
+/// independent databases can be open in the same process. It is `Send + Sync`: share it
+/// between threads with an `Arc`, every operation locks the shard holding its key for its own
+/// duration. Dropping the handle closes the database, flushing the backends.
+///
+/// With a single shard, the default, the backend lives at the database path. With `n` shards,
+/// shard `i` lives at `<path>.shard-<i>`, `<path>.shards` records `n`, and `<path>.txn` logs
+/// the batches spanning several shards, see `write_batch`.
+///
+/// Unless disabled in the `Options`, a background thread regularly deletes expired pairs.
+/// If enabled in the `Options`, every committed mutation is also logged to a `ChangeLog` in
+/// `<path>.cdc`, shared by the shards.
+///
+/// The keys can be grouped in named keyspaces, see `Keyspace`. The methods of the database
+/// itself work on the default keyspace.
+///
+/// Pairs can be found by the fields of their values through secondary indexes, see
+/// `create_index`.
+///
+/// Opening the same path twice at once is not supported.
+///
+/// # Example
+/// ```rust
+/// use safina_db::{Backend, Database, Options};
+///
+/// let options = Options { backend: Backend::Memory, ..Options::default() };
+/// let db = Database::open("example", options).unwrap();
+/// db.insert("key", "value").unwrap();
+/// assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+/// assert_eq!(db.get_string("key").unwrap(), Some("value".to_string()));
+/// ```
+#[derive(Debug)]
+pub struct Database {
+    path: String,
+    shards: Arc<Shards>,
+    change_log: Option<Arc<ChangeLog>>,
+    /// The keyspaces besides the default one, as persisted under `CATALOG_KEY`.
+    keyspaces: RwLock<BTreeMap<String, KeyspaceOptions>>,
+    /// The keyspaces among them whose values the indexes decompress.
+    compressed: Arc<CompressedKeyspaces>,
+    /// The secondary indexes, each built in every shard, as persisted under
+    /// `index::CATALOG_KEY`. `None` for those computed by a closure that wasn't created again
+    /// since the database was opened.
+    indexes: RwLock<BTreeMap<String, Option<Extractor>>>,
+    sweeper: Option<Sweeper>,
+    closed: bool,
+}
+
+impl Database {
+    /// Opens (or creates) the database at `path` and loads its data.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The database file, or directory for the `Bitcask` backend.
+    /// * `options` - The settings to open the database with.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Database)` - The open database.
+    /// * `Err(Error::ShardCount)` - If the database was created with another shard count.
+    /// * `Err(Error)` - An error message if a backend can't be opened or loaded.
+    pub fn open(path: &str, options: Options) -> Result<Database> {
+        let shard_count = options.shard_count.max(1);
+        if !matches!(options.backend, Backend::Memory) {
+            check_shard_count(path, shard_count)?;
+        }
+        let change_log = match &options.change_log {
+            Some(log_options) => Some(Arc::new(ChangeLog::open(
+                format!("{path}.cdc"),
+                log_options.clone(),
+                options.durability,
+            )?)),
+            None => None,
+        };
+        let stores = (0..shard_count)
+            .map(|index| {
+                let path = shard_path(path, index, shard_count);
+                let durability = options.durability;
+                let operators = options.merge_operators.clone();
+                let backend: Box<dyn StorageBackend> = match &options.backend {
+                    Backend::Bincode => Box::new(
+                        Storage::with_durability(Some(&path), durability).with_merge_operators(operators),
+                    ),
+                    Backend::Json => Box::new(JsonStorage::new(&path).with_merge_operators(operators)),
+                    Backend::Bitcask(bitcask_options) => Box::new(
+                        Bitcask::open_with(&path, bitcask_options.clone(), durability)?
+                            .with_merge_operators(operators),
+                    ),
+                    Backend::Memory => Box::new(MemoryBackend::new()),
+                };
+                let mut store = Store::open(backend)?;
+                store.set_merge_operators(options.merge_operators.clone());
+                store.defer_syncs(); // Writers wait for their sync once the shard is unlocked
+                if let Some(change_log) = &change_log {
+                    store.set_change_log(Arc::clone(change_log));
+                }
+                Ok(store)
+            })
+            .collect::<Result<Vec<Store>>>()?;
+        let shards = match &options.backend {
+            Backend::Memory => Shards::new(stores),
+            _ if shard_count == 1 => Shards::new(stores), // A batch is atomic within a shard
+            _ => {
+                let (commit_log, intents) =
+                    CommitLog::open(&format!("{path}.txn"), options.durability)?;
+                Shards::with_commit_log(stores, commit_log, intents)?
+            }
+        };
+        let shards = Arc::new(shards);
+        let keyspaces = match shards.read(shards.index(CATALOG_KEY))?.get(CATALOG_KEY)? {
+            Some(catalog) => keyspace::decode_catalog(&catalog)?,
+            None => BTreeMap::new(),
+        };
+        let compressed = Arc::new(CompressedKeyspaces::default());
+        compressed.update(&keyspaces);
+        let shared = Arc::clone(&compressed);
+        let decoder = Decoder(Arc::new(move |key, value| shared.decode(key, value)));
+        for index in 0..shards.len() {
+            shards.write(index)?.set_index_decoder(decoder.clone());
+        }
+        let mut indexes = BTreeMap::new();
+        let catalog = shards.read(shards.index(index::CATALOG_KEY))?.get(index::CATALOG_KEY)?;
+        if let Some(catalog) = catalog {
+            for (name, path) in index::decode_catalog(&catalog)? {
+                let extractor = path.map(|path| Extractor::json_path(&path));
+                if let Some(extractor) = &extractor {
+                    for index in 0..shards.len() {
+                        shards.write(index)?.create_index(&name, extractor.clone())?; // Rebuilt from the data
+                    }
+                }
+                indexes.insert(name, extractor);
+            }
+        }
+        let sweeper = options
+            .sweep_interval
+            .map(|interval| Sweeper::spawn(Arc::clone(&shards), interval));
+        Ok(Database {
+            path: path.to_string(),
+            shards,
+            change_log,
+            keyspaces: RwLock::new(keyspaces),
+            compressed,
+            indexes: RwLock::new(indexes),
+            sweeper,
+            closed: false,
+        })
+    }
+
+    /// Returns the path the database was opened at.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the change log of the database, if the `Options` enabled it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::storage::ChangeLogOptions;
+    /// use safina_db::{Backend, Database, Options};
+    ///
+    /// # let _ = std::fs::remove_dir_all("example-cdc.cdc");
+    /// let options = Options {
+    ///     backend: Backend::Memory,
+    ///     change_log: Some(ChangeLogOptions::default()),
+    ///     ..Options::default()
+    /// };
+    /// let db = Database::open("example-cdc", options).unwrap();
+    /// db.insert("key", "value").unwrap();
+    /// let log = db.change_log().unwrap();
+    /// let changes = log.read(log.cursor("search").unwrap_or(1), 100).unwrap();
+    /// assert_eq!(changes.len(), 1);
+    /// log.save_cursor("search", changes[0].seq + 1).unwrap();
+    /// # std::fs::remove_dir_all("example-cdc.cdc").unwrap();
+    /// ```
+    pub fn change_log(&self) -> Option<&ChangeLog> {
+        self.change_log.as_deref()
+    }
+
+    /// Returns the number of shards the keys are split into.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the index of the shard holding `key`.
+    pub fn shard_of<K: AsRef<[u8]>>(&self, key: K) -> usize {
+        self.shards.index(key.as_ref())
+    }
+
+    /// Runs `read` on shard `index`, locked for shared access, e.g. to run several reads on
+    /// the same data. Any number of readers can hold a shard at once, writers wait for all
+    /// of them.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(T)` - What `read` returned.
+    /// * `Err(Error::Poisoned)` - If a thread panicked while holding the shard.
+    pub fn with_shard<T, F>(&self, index: usize, read: F) -> Result<T>
+    where
+        F: FnOnce(&Store) -> T,
+    {
+        Ok(read(&*self.shards.read(index)?))
+    }
+
+    /// Runs `write` on shard `index`, locked for exclusive access, e.g. to run several
+    /// operations in a row. Once the shard is unlocked, waits for the syncs the durability
+    /// mode deferred, so concurrent writers share a sync.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(T)` - What `write` returned, once its writes are synced.
+    /// * `Err(Error::Poisoned)` - If a thread panicked while holding the shard.
+    /// * `Err(Error)` - The error `write` returned, or the sync hit.
+    pub fn with_shard_mut<T, F>(&self, index: usize, write: F) -> Result<T>
+    where
+        F: FnOnce(&mut Store) -> Result<T>,
+    {
+        let (result, sync) = {
+            let mut store = self.shards.write(index)?;
+            let result = write(&mut store);
+            (result, store.take_syncs())
+        };
+        sync.into_iter().try_for_each(SyncTicket::wait)?;
+        result
+    }
+
+    /// Locks the shard holding `key` for shared access.
+    fn read_key(&self, key: &[u8]) -> Result<RwLockReadGuard<'_, Store>> {
+        self.shards.read(self.shards.index(key))
+    }
+
+    /// Runs `write` on the shard holding `key`, see `with_shard_mut`.
+    fn write_key<T, F>(&self, key: &[u8], write: F) -> Result<T>
+    where
+        F: FnOnce(&mut Store) -> Result<T>,
+    {
+        self.with_shard_mut(self.shards.index(key), write)
+    }
+
+    /// Inserts a new key-value pair, see `Store::insert`.
+    pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<()> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.insert(key, value))
+    }
+
+    /// Inserts a new key-value pair expiring after `ttl`, see `Store::insert_with_ttl`.
+    pub fn insert_with_ttl<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<()> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.insert_with_ttl(key, value, ttl))
+    }
+
+    /// Makes an existing key expire after `ttl`, see `Store::set_expiry`.
+    pub fn set_expiry<K: AsRef<[u8]>>(&self, key: K, ttl: Duration) -> Result<()> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.set_expiry(key, ttl))
+    }
+
+    /// Removes the expiry of an existing key, see `Store::persist`.
+    pub fn persist<K: AsRef<[u8]>>(&self, key: K) -> Result<()> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.persist(key))
+    }
+
+    /// Returns how long a key has left to live, see `Store::ttl`.
+    pub fn ttl<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Duration>> {
+        let key = key.as_ref();
+        self.read_key(key)?.ttl(key)
+    }
+
+    /// Returns a copy of the value associated with `key`, if any.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Value))` - The value if the key exists.
+    /// * `Ok(None)` - If the key doesn't exist or has expired.
+    /// * `Err(Error::Merge)` - If the operands merged into the key can't be folded.
+    /// * `Err(Error::Poisoned)` - If the shard lock is poisoned.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Value>> {
+        let key = key.as_ref();
+        self.read_key(key)?.get(key)
+    }
+
+    /// Returns the value associated with `key` as text, if any.
+    ///
+    /// Invalid UTF-8 sequences in the value are replaced by `U+FFFD`, use `get` to read
+    /// binary values.
+    pub fn get_string<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<String>> {
+        let key = key.as_ref();
+        Ok(self
+            .read_key(key)?
+            .get(key)?
+            .map(|value| String::from_utf8_lossy(&value).into_owned()))
+    }
+
+    /// Returns the version of a key, see `Store::version`.
+    pub fn version<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<u64>> {
+        let key = key.as_ref();
+        Ok(self.read_key(key)?.version(key))
+    }
+
+    /// Returns a copy of the value associated with `key` along with its version, see
+    /// `Store::get_with_version`.
+    pub fn get_with_version<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<(Value, u64)>> {
+        let key = key.as_ref();
+        self.read_key(key)?.get_with_version(key)
+    }
+
+    /// Updates the value of an existing key, see `Store::update`.
+    pub fn update<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<()> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.update(key, value))
+    }
+
+    /// Changes the value of an existing key in place, see `Store::update_with`.
+    pub fn update_with<K, F>(&self, key: K, change: F) -> Result<Value>
+    where
+        K: AsRef<[u8]>,
+        F: FnOnce(&mut Value),
+    {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.update_with(key, change))
+    }
+
+    /// Updates the value of a key if its version is still `version`, see
+    /// `Store::update_if_version`.
+    pub fn update_if_version<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        version: u64,
+        value: V,
+    ) -> Result<Option<u64>> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.update_if_version(key, version, value))
+    }
+
+    /// Replaces the value of a key if it is still `expected`, see `Store::compare_and_swap`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::{Backend, Database, Options};
+    ///
+    /// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+    /// db.insert("balance", "100").unwrap();
+    /// assert!(db.compare_and_swap("balance", Some("100"), Some("70")).unwrap());
+    /// assert!(!db.compare_and_swap("balance", Some("100"), Some("40")).unwrap());
+    /// assert_eq!(db.get_string("balance").unwrap(), Some("70".to_string()));
+    /// ```
+    pub fn compare_and_swap<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        expected: Option<V>,
+        new: Option<V>,
+    ) -> Result<bool> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.compare_and_swap(key, expected, new))
+    }
+
+    /// Merges `operand` into the value of a key with one of the `Options::merge_operators`,
+    /// see `Store::merge`.
+    pub fn merge<K: AsRef<[u8]>, O: AsRef<[u8]>>(
+        &self,
+        key: K,
+        operator: &str,
+        operand: O,
+    ) -> Result<()> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.merge(key, operator, operand))
+    }
+
+    /// Adds `delta` to the counter stored at a key, see `Store::incr_by`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::{Backend, Database, Options};
+    ///
+    /// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+    /// db.incr_by("stock", 5).unwrap();
+    /// db.decr("stock").unwrap();
+    /// assert_eq!(db.incr("stock").unwrap(), 5);
+    /// ```
+    pub fn incr_by<K: AsRef<[u8]>>(&self, key: K, delta: i64) -> Result<i64> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.incr_by(key, delta))
+    }
+
+    /// Adds one to the counter stored at a key, see `Store::incr_by`.
+    pub fn incr<K: AsRef<[u8]>>(&self, key: K) -> Result<i64> {
+        self.incr_by(key, 1)
+    }
+
+    /// Subtracts one from the counter stored at a key, see `Store::incr_by`.
+    pub fn decr<K: AsRef<[u8]>>(&self, key: K) -> Result<i64> {
+        self.incr_by(key, -1)
+    }
+
+    /// Deletes a key if it exists, see `Store::delete`.
+    pub fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<()> {
+        let key = key.as_ref();
+        self.write_key(key, |store| store.delete(key))
+    }
+
+    /// Applies the writes of `batch` with a single persistence step per shard, see
+    /// `WriteBatch`.
+    ///
+    /// The shards the batch touches are locked together, so no other write lands between
+    /// the parts of the batch. A batch spanning several shards is logged to `<path>.txn`
+    /// before any shard persists its part, so after a crash either every part is recovered
+    /// or none is.
+    ///
+    /// # Returns
+    /// * `Ok(())` - Once every write is persisted and applied.
+    /// * `Err(Error)` - If the batch could not be persisted. If a shard fails after others
+    ///   persisted their part, the remaining parts are applied when the database is next
+    ///   opened.
+    pub fn write_batch(&self, batch: WriteBatch) -> Result<()> {
+        let mut parts: BTreeMap<usize, Vec<Mutation>> = BTreeMap::new();
+        for mutation in batch.mutations {
+            let index = mutation.key().map_or(0, |key| self.shards.index(key));
+            parts.entry(index).or_default().push(mutation);
+        }
+
+        let mut stores = Vec::with_capacity(parts.len());
+        for &index in parts.keys() {
+            stores.push(self.shards.write(index)?); // In index order, see `Shards`
+        }
+        let result = self.shards.commit(
+            stores
+                .iter_mut()
+                .zip(parts)
+                .map(|(store, (index, mutations))| (&mut **store, index, mutations))
+                .collect(),
+        );
+        let syncs: Vec<_> = stores.iter_mut().flat_map(|store| store.take_syncs()).collect();
+        drop(stores);
+        for ticket in syncs {
+            ticket.wait()?; // The shards are unlocked, other writers can join the sync
+        }
+        result.and_then(|_| self.shards.trim_commit_log())
+    }
+
+    /// Returns a copy of the pairs within `range`, in key order, see `Store::scan`.
+    pub fn scan<'k, R: RangeBounds<&'k str>>(&self, range: R) -> Result<Vec<KV>> {
+        self.scan_bytes((
+            range.start_bound().map(|key| key.as_bytes()),
+            range.end_bound().map(|key| key.as_bytes()),
+        ))
+    }
+
+    /// Returns a copy of the pairs within a range of byte string keys, see `Store::scan_bytes`.
+    ///
+    /// The keys of the other keyspaces are skipped, unless the range starts among them.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<KV>)` - The pairs, empty if none is found.
+    /// * `Err(Error::Poisoned)` - If a shard lock is poisoned.
+    pub fn scan_bytes<'k, R: RangeBounds<&'k [u8]>>(&self, range: R) -> Result<Vec<KV>> {
+        let bounds = byte_bounds(&range);
+        let hide = keyspace::hides_reserved(bounds.0);
+        self.shards
+            .merge(|_, store| {
+                Ok(store
+                    .scan_bytes(bounds)?
+                    .filter(|pair| !hide || !keyspace::is_reserved(&pair.key))
+                    .cloned()
+                    .collect())
+            })
+    }
+
+    /// Returns a copy of the pairs whose key starts with `prefix`, in key order.
+    ///
+    /// The keys of the other keyspaces are skipped, unless `prefix` is among them.
+    pub fn scan_prefix<P: AsRef<[u8]>>(&self, prefix: P) -> Result<Vec<KV>> {
+        let prefix = prefix.as_ref();
+        let hide = !keyspace::is_reserved(prefix);
+        self.shards
+            .merge(|_, store| {
+                Ok(store
+                    .scan_prefix(prefix)?
+                    .filter(|pair| !hide || !keyspace::is_reserved(&pair.key))
+                    .cloned()
+                    .collect())
+            })
+    }
+
+    /// Creates keyspace `name`, see `Keyspace`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the keyspace, not empty and without zero bytes.
+    /// * `options` - The default TTL and compression of the keyspace, kept across restarts.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Keyspace)` - A handle on the new keyspace.
+    /// * `Err(Error::KeyspaceExists)` - If the keyspace already exists.
+    /// * `Err(Error::InvalidKeyspaceName)` - If `name` can't name a keyspace.
+    /// * `Err(Error)` - An error message if the catalog could not be persisted.
+    pub fn create_keyspace(&self, name: &str, options: KeyspaceOptions) -> Result<Keyspace<'_>> {
+        keyspace::check_name(name)?;
+        let mut keyspaces = self.keyspaces.write().map_err(|_| Error::Poisoned)?;
+        if keyspaces.contains_key(name) {
+            return Err(Error::KeyspaceExists(name.to_string()));
+        }
+        let mut catalog = keyspaces.clone();
+        catalog.insert(name.to_string(), options.clone());
+        let mut batch = WriteBatch::new();
+        batch.put(CATALOG_KEY, keyspace::encode_catalog(&catalog)?);
+        self.write_batch(batch)?;
+        self.compressed.update(&catalog);
+        *keyspaces = catalog;
+        Ok(Keyspace::new(self, name, options))
+    }
+
+    /// Returns a handle on keyspace `name`, or on the default keyspace for `"default"`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Keyspace)` - The handle.
+    /// * `Err(Error::KeyspaceNotFound)` - If the keyspace doesn't exist.
+    pub fn keyspace(&self, name: &str) -> Result<Keyspace<'_>> {
+        if name == DEFAULT_KEYSPACE {
+            return Ok(Keyspace::new(self, name, KeyspaceOptions::default()));
+        }
+        match self.keyspaces.read().map_err(|_| Error::Poisoned)?.get(name) {
+            Some(options) => Ok(Keyspace::new(self, name, options.clone())),
+            None => Err(Error::KeyspaceNotFound(name.to_string())),
+        }
+    }
+
+    /// Deletes keyspace `name` and every pair it holds, in a single batch with the removal
+    /// from the catalog: after a crash either the keyspace and all of its pairs are gone, or
+    /// none is, see `write_batch`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the keyspace is gone. Its handles can no longer write.
+    /// * `Err(Error::KeyspaceNotFound)` - If the keyspace doesn't exist.
+    /// * `Err(Error::InvalidKeyspaceName)` - For the default keyspace, which can't be dropped.
+    /// * `Err(Error)` - An error message if the deletions could not be persisted.
+    pub fn drop_keyspace(&self, name: &str) -> Result<()> {
+        if name == DEFAULT_KEYSPACE {
+            return Err(Error::InvalidKeyspaceName(name.to_string()));
+        }
+        let mut keyspaces = self.keyspaces.write().map_err(|_| Error::Poisoned)?; // Writes through handles wait for the drop
+        if !keyspaces.contains_key(name) {
+            return Err(Error::KeyspaceNotFound(name.to_string()));
+        }
+        let mut catalog = keyspaces.clone();
+        catalog.remove(name);
+        let mut batch = WriteBatch::new();
+        for pair in self.scan_prefix(keyspace::key_prefix(name))? {
+            batch.delete(pair.key);
+        }
+        batch.put(CATALOG_KEY, keyspace::encode_catalog(&catalog)?);
+        self.write_batch(batch)?;
+        self.compressed.update(&catalog);
+        *keyspaces = catalog;
+        Ok(())
+    }
+
+    /// Returns the names of the keyspaces, the default one first, then in name order.
+    pub fn keyspaces(&self) -> Result<Vec<String>> {
+        let keyspaces = self.read_keyspaces()?;
+        Ok(std::iter::once(DEFAULT_KEYSPACE.to_string())
+            .chain(keyspaces.keys().cloned())
+            .collect())
+    }
+
+    /// Creates a secondary index in every shard, see `Store::create_index`.
+    ///
+    /// The definitions of the indexes are persisted, and the indexes with a `JsonPath`
+    /// extractor are rebuilt from the data each time the database is opened. Closures can't
+    /// be persisted: an index using one has to be created again, with the same name, after
+    /// each opening, and until it is `find` fails with `Error::IndexUnregistered`.
+    ///
+    /// The values of compressed keyspaces are decompressed before they are indexed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name `find` looks the index up by.
+    /// * `extractor` - How the values a pair is found by are computed, see `Extractor`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the index is built.
+    /// * `Err(Error::IndexExists)` - If an index with this name already exists, unless it is
+    ///   a closure index waiting to be created again.
+    /// * `Err(Error::InvalidIndex)` - If the name is empty or the JSON path is malformed.
+    /// * `Err(Error)` - An error message if the definition could not be persisted.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::index::Extractor;
+    /// use safina_db::{Backend, Database, Options};
+    ///
+    /// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+    /// db.create_index("email", Extractor::json_path("$.email")).unwrap();
+    /// db.insert("user:1", r#"{"name": "Alice", "email": "alice@example.com"}"#).unwrap();
+    /// let found = db.find("email", "alice@example.com").unwrap();
+    /// assert_eq!(found[0].key, b"user:1");
+    /// ```
+    pub fn create_index(&self, name: &str, extractor: Extractor) -> Result<()> {
+        if name.is_empty() {
+            return Err(Error::InvalidIndex("the name is empty".to_string()));
+        }
+        let mut indexes = self.indexes.write().map_err(|_| Error::Poisoned)?;
+        if let Some(Some(_)) = indexes.get(name) {
+            return Err(Error::IndexExists(name.to_string()));
+        }
+        Index::new(extractor.clone(), None)?; // Checks the path before it is persisted
+        let previous = indexes.insert(name.to_string(), Some(extractor.clone()));
+        let written = self.write_index_catalog(&indexes);
+        if written.is_err() {
+            match previous {
+                Some(previous) => indexes.insert(name.to_string(), previous),
+                None => indexes.remove(name),
+            };
+            return written;
+        }
+        for index in 0..self.shards.len() {
+            self.shards.write(index)?.create_index(name, extractor.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a secondary index from every shard, and from the persisted definitions.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the index is gone.
+    /// * `Err(Error::IndexNotFound)` - If no index has this name.
+    /// * `Err(Error)` - An error message if the definitions could not be persisted.
+    pub fn drop_index(&self, name: &str) -> Result<()> {
+        let mut indexes = self.indexes.write().map_err(|_| Error::Poisoned)?;
+        let extractor = indexes
+            .remove(name)
+            .ok_or_else(|| Error::IndexNotFound(name.to_string()))?;
+        let written = self.write_index_catalog(&indexes);
+        if written.is_err() {
+            indexes.insert(name.to_string(), extractor);
+            return written;
+        }
+        if extractor.is_some() {
+            for index in 0..self.shards.len() {
+                self.shards.write(index)?.drop_index(name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the secondary indexes, in name order, those waiting to be
+    /// created again included.
+    pub fn indexes(&self) -> Result<Vec<String>> {
+        let indexes = self.indexes.read().map_err(|_| Error::Poisoned)?;
+        Ok(indexes.keys().cloned().collect())
+    }
+
+    /// Returns a copy of the pairs an index finds by `value`, in key order, see
+    /// `Store::find`. Pairs of the other keyspaces are skipped, see `Keyspace::find`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<KV>)` - The pairs, empty if none is found.
+    /// * `Err(Error::IndexNotFound)` - If no index has this name.
+    /// * `Err(Error::IndexUnregistered)` - If the index uses a closure and wasn't created
+    ///   again since the database was opened, see `create_index`.
+    pub fn find<V: AsRef<[u8]>>(&self, index: &str, value: V) -> Result<Vec<KV>> {
+        let mut pairs = self.find_all(index, value.as_ref())?;
+        pairs.retain(|pair| !keyspace::is_reserved(&pair.key));
+        Ok(pairs)
+    }
+
+    /// Returns a copy of the pairs an index finds by `value` in every shard and keyspace, in
+    /// key order.
+    pub(crate) fn find_all(&self, index: &str, value: &[u8]) -> Result<Vec<KV>> {
+        match self.indexes.read().map_err(|_| Error::Poisoned)?.get(index) {
+            Some(Some(_)) => {}
+            Some(None) => return Err(Error::IndexUnregistered(index.to_string())),
+            None => return Err(Error::IndexNotFound(index.to_string())),
+        }
+        let mut pairs = Vec::new();
+        for shard in 0..self.shards.len() {
+            pairs.extend(self.shards.read(shard)?.find(index, value)?);
+        }
+        pairs.sort_unstable_by(|a, b| a.key.cmp(&b.key)); // Each shard holds distinct keys
+        Ok(pairs)
+    }
+
+    /// Persists the definitions of `indexes`: the JSON path of each, none for closures.
+    fn write_index_catalog(&self, indexes: &BTreeMap<String, Option<Extractor>>) -> Result<()> {
+        let catalog: BTreeMap<String, Option<String>> = indexes
+            .iter()
+            .map(|(name, extractor)| match extractor {
+                Some(Extractor::JsonPath(path)) => (name.clone(), Some(path.clone())),
+                _ => (name.clone(), None),
+            })
+            .collect();
+        let mut batch = WriteBatch::new();
+        batch.put(index::CATALOG_KEY, index::encode_catalog(&catalog)?);
+        self.write_batch(batch)
+    }
+
+    /// Locks the catalog of keyspaces for shared access, so writes through a keyspace handle
+    /// can't race with its drop.
+    pub(crate) fn read_keyspaces(
+        &self,
+    ) -> Result<RwLockReadGuard<'_, BTreeMap<String, KeyspaceOptions>>> {
+        self.keyspaces.read().map_err(|_| Error::Poisoned)
+    }
+
+    /// Starts a serializable transaction, see `Transaction`.
+    pub fn begin(&self) -> Result<Transaction> {
+        self.begin_with(IsolationLevel::default())
+    }
+
+    /// Starts a transaction at the given isolation level, see `IsolationLevel`.
+    pub fn begin_with(&self, isolation: IsolationLevel) -> Result<Transaction> {
+        Transaction::begin(Arc::clone(&self.shards), isolation)
+    }
+
+    /// Subscribes to the changes of every key starting with `prefix`, see `Store::watch`.
+    ///
+    /// The watcher receives the changes of every shard. The events of a key arrive in order,
+    /// but events of keys held by different shards may arrive in any order, and their
+    /// numbers are only comparable within a shard.
+    ///
+    /// Like `scan_prefix`, the watcher skips the keys reserved for keyspaces and the catalog,
+    /// unless `prefix` itself starts among them.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::{Backend, Database, EventKind, Options};
+    ///
+    /// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+    /// let watcher = db.watch("cache:").unwrap();
+    /// db.insert("cache:home", "<html>").unwrap();
+    /// db.delete("cache:home").unwrap();
+    /// let kinds: Vec<EventKind> = watcher.take(2).map(|event| event.kind).collect();
+    /// assert_eq!(kinds, [EventKind::Put, EventKind::Delete]);
+    /// ```
+    pub fn watch<P: AsRef<[u8]>>(&self, prefix: P) -> Result<Watcher> {
+        let prefix = prefix.as_ref();
+        let (watcher, subscriber) = Watcher::new(prefix, !keyspace::is_reserved(prefix));
+        for index in 0..self.shards.len() {
+            self.shards.write(index)?.subscribe(subscriber.clone());
+        }
+        Ok(watcher)
+    }
+
+    /// Takes a point-in-time view of the database, see `Snapshot`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::{Backend, Database, Options};
+    ///
+    /// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+    /// db.insert("key", "before").unwrap();
+    /// let snapshot = db.snapshot().unwrap();
+    /// db.update("key", "after").unwrap();
+    /// assert_eq!(snapshot.get("key").unwrap(), Some(b"before".to_vec()));
+    /// ```
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        Snapshot::take(Arc::clone(&self.shards))
+    }
+
+    /// Runs `f` in a serializable transaction, committing it if `f` succeeds.
+    ///
+    /// If `f` returns an error or panics, the transaction is rolled back and none of its
+    /// writes are applied.
+    ///
+    /// # Returns
+    /// * `Ok(T)` - The value returned by `f`, once the transaction is committed.
+    /// * `Err(Error)` - The error returned by `f`, or the commit error, e.g. `Error::Conflict`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::{Backend, Database, Options};
+    ///
+    /// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+    /// db.insert("alice", "100").unwrap();
+    /// db.transaction(|tx| {
+    ///     tx.update("alice", "70")?;
+    ///     tx.insert("bob", "30")
+    /// })
+    /// .unwrap();
+    /// assert_eq!(db.get_string("bob").unwrap(), Some("30".to_string()));
+    /// ```
+    pub fn transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction) -> Result<T>,
+    {
+        self.transaction_with(IsolationLevel::default(), f)
+    }
+
+    /// Runs `f` in a transaction at the given isolation level, see `Database::transaction`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::{Backend, Database, IsolationLevel, Options};
+    ///
+    /// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+    /// db.insert("hits", "0").unwrap();
+    /// db.transaction_with(IsolationLevel::ReadCommitted, |tx| tx.update("hits", "1"))
+    ///     .unwrap();
+    /// ```
+    pub fn transaction_with<T, F>(&self, isolation: IsolationLevel, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction) -> Result<T>,
+    {
+        let mut tx = self.begin_with(isolation)?;
+        let value = f(&mut tx)?; // Dropping `tx` on error or panic rolls it back
+        tx.commit()?;
+        Ok(value)
+    }
+
+    /// Asks the backends to compact what they persisted so far, see `Store::flush`, then
+    /// empties the commit log and syncs the change log. Every shard is locked meanwhile.
+    pub fn flush(&self) -> Result<()> {
+        self.shards.flush()?;
+        self.change_log.as_ref().map_or(Ok(()), |log| log.sync())
+    }
+
+    /// Closes the database, reporting any error the backend hits while flushing.
+    ///
+    /// Dropping the handle does the same but has to ignore such errors.
+    pub fn close(mut self) -> Result<()> {
+        self.closed = true;
+        if let Some(sweeper) = self.sweeper.take() {
+            sweeper.stop();
+        }
+        let mut result = Ok(());
+        for index in 0..self.shards.len() {
+            let closed = self.shards.write(index).and_then(|mut store| store.close());
+            result = result.and(closed); // Every shard is closed, the first error is reported
+        }
+        result.and(self.change_log.as_ref().map_or(Ok(()), |log| log.sync()))
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        if let Some(sweeper) = self.sweeper.take() {
+            sweeper.stop();
+        }
+        for index in 0..self.shards.len() {
+            if let Ok(mut store) = self.shards.write(index) {
+                let _ = store.close();
+            }
+        }
+        if let Some(change_log) = &self.change_log {
+            let _ = change_log.sync();
+        }
+    }
+}
+
+/// The background thread deleting the expired pairs of every shard.
+#[derive(Debug)]
+struct Sweeper {
+    stop: mpsc::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Sweeper {
+    /// Starts sweeping `shards` every `interval`, one shard at a time.
+    fn spawn(shards: Arc<Shards>, interval: Duration) -> Sweeper {
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                for index in 0..shards.len() {
+                    let sync = match shards.write(index) {
+                        Ok(mut store) => {
+                            let _ = store.purge_expired(); // Whatever is left is retried on the next round
+                            store.take_syncs()
+                        }
+                        Err(_) => return,
+                    };
+                    for ticket in sync {
+                        let _ = ticket.wait();
+                    }
+                }
+            }
+        });
+        Sweeper { stop, handle }
+    }
+
+    /// Stops the thread, waiting for a running sweep to finish.
+    fn stop(self) {
+        drop(self.stop); // Disconnects the channel the thread waits on
+        let _ = self.handle.join();
+    }
+}
+
+/// Returns where shard `index` of the database at `path` is persisted.
+fn shard_path(path: &str, index: usize, shard_count: usize) -> String {
+    match shard_count {
+        1 => path.to_string(), // The layout of databases created before sharding
+        _ => format!("{path}.shard-{index}"),
+    }
+}
+
+/// Checks the database at `path` was created with `shard_count` shards, recording the count
+/// if the database is new.
+///
+/// Keys are routed to shards by hash, so opening a database with another shard count would
+/// look every key up in the wrong shard.
+///
+/// # Returns
+/// * `Ok(())` - If the shard count matches, or the database is new.
+/// * `Err(Error::ShardCount)` - If the database was created with another shard count.
+/// * `Err(Error)` - If the record of the shard count can't be read or written.
+fn check_shard_count(path: &str, shard_count: usize) -> Result<()> {
+    let manifest = format!("{path}.shards");
+    let created = match fs::read_to_string(&manifest) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map_err(|_| Error::Corruption(format!("invalid shard count in {manifest}")))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let exists = Path::new(path).exists() || Path::new(&format!("{path}.wal")).exists();
+            if exists || shard_count == 1 {
+                1 // Single-shard databases have no record
+            } else {
+                fs::write(&manifest, shard_count.to_string())?;
+                shard_count
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if created != shard_count {
+        return Err(Error::ShardCount(created));
+    }
+    Ok(())
+}
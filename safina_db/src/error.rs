@@ -0,0 +1,134 @@
+use std::fmt;
+use std::io;
+
+/// The error type of every fallible SafinaDB operation.
+#[derive(Debug)]
+pub enum Error {
+    /// The key doesn't exist in the store.
+    KeyNotFound,
+    /// The key already exists in the store.
+    KeyExists,
+    /// A file operation failed.
+    Io(io::Error),
+    /// Data read back from disk is invalid, e.g. a truncated file or a bad checksum.
+    Corruption(String),
+    /// Data could not be encoded or decoded.
+    Serialization(String),
+    /// The storage has not been opened yet, or was already closed.
+    Closed,
+    /// The storage was given no file path to load from or save to.
+    NoPath,
+    /// A thread panicked while holding the store lock, its state can't be trusted.
+    Poisoned,
+    /// A transaction used a key that another writer changed before it committed.
+    Conflict,
+    /// The database was created with this number of shards, and can't be opened with another.
+    ShardCount(usize),
+    /// A merge operand could not be folded, e.g. there is no operator by its name.
+    Merge(String),
+    /// The change log no longer holds the changes asked for, it now starts at this number.
+    ChangeLogTruncated(u64),
+    /// A keyspace with this name already exists.
+    KeyspaceExists(String),
+    /// No keyspace has this name.
+    KeyspaceNotFound(String),
+    /// This name can't be used for a keyspace, or the keyspace can't be dropped.
+    InvalidKeyspaceName(String),
+    /// A database with this name already exists in the data directory.
+    DatabaseExists(String),
+    /// The data directory holds no database with this name.
+    DatabaseNotFound(String),
+    /// This name can't be used for a database, or the database can't be dropped.
+    InvalidDatabaseName(String),
+    /// The database can't be dropped while a handle on it is held elsewhere.
+    DatabaseInUse(String),
+    /// An index with this name already exists.
+    IndexExists(String),
+    /// No index has this name.
+    IndexNotFound(String),
+    /// An index can't be created as asked, e.g. its JSON path is malformed.
+    InvalidIndex(String),
+    /// An index computed by a closure wasn't created again since the database was opened,
+    /// see `Database::create_index`.
+    IndexUnregistered(String),
+}
+
+/// A `Result` whose error type is `safina_db::Error`.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::KeyNotFound => write!(f, "Key not found"),
+            Error::KeyExists => write!(f, "Key already exists"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Corruption(message) => write!(f, "Corrupted data: {message}"),
+            Error::Serialization(message) => write!(f, "Serialization error: {message}"),
+            Error::Closed => write!(f, "Storage is not open"),
+            Error::NoPath => write!(f, "Storage has no file path"),
+            Error::Poisoned => write!(f, "Store poisoned by a panicking thread"),
+            Error::Conflict => write!(f, "Transaction conflict, a key it used was changed"),
+            Error::ShardCount(count) => write!(f, "Database was created with {count} shards"),
+            Error::Merge(message) => write!(f, "Merge failed: {message}"),
+            Error::ChangeLogTruncated(first) => {
+                write!(f, "Change log truncated, the oldest change left is {first}")
+            }
+            Error::KeyspaceExists(name) => write!(f, "Keyspace '{name}' already exists"),
+            Error::KeyspaceNotFound(name) => write!(f, "Keyspace '{name}' not found"),
+            Error::InvalidKeyspaceName(name) => write!(f, "Invalid keyspace name '{name}'"),
+            Error::DatabaseExists(name) => write!(f, "Database '{name}' already exists"),
+            Error::DatabaseNotFound(name) => write!(f, "Database '{name}' not found"),
+            Error::InvalidDatabaseName(name) => write!(f, "Invalid database name '{name}'"),
+            Error::DatabaseInUse(name) => write!(f, "Database '{name}' is in use"),
+            Error::IndexExists(name) => write!(f, "Index '{name}' already exists"),
+            Error::IndexNotFound(name) => write!(f, "Index '{name}' not found"),
+            Error::InvalidIndex(message) => write!(f, "Invalid index: {message}"),
+            Error::IndexUnregistered(name) => write!(
+                f,
+                "Index '{name}' uses a closure, create it again after opening the database"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        match *e {
+            bincode::ErrorKind::Io(e) => Error::Io(e),
+            e => Error::Serialization(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serialization(e.to_string())
+    }
+}
+
+impl From<std::array::TryFromSliceError> for Error {
+    fn from(e: std::array::TryFromSliceError) -> Self {
+        Error::Corruption(e.to_string())
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        Error::Corruption(e.to_string())
+    }
+}
@@ -0,0 +1,245 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+
+/// Key the catalog of persisted indexes is stored under, reserved like the keyspaces.
+pub(crate) const CATALOG_KEY: &[u8] = b"\x00\x00indexes";
+
+/// A function computing the index values of a pair from its key and value.
+pub type ExtractFn = dyn Fn(&[u8], &[u8]) -> Vec<Vec<u8>> + Send + Sync;
+
+/// A function returning the value an index extracts from, given the key of a pair and its
+/// value as stored, e.g. decompressed. `None` leaves the pair out of the index.
+pub(crate) type DecodeFn = dyn for<'v> Fn(&[u8], &'v [u8]) -> Option<Cow<'v, [u8]>> + Send + Sync;
+
+/// A shared `DecodeFn`, see `Store::set_index_decoder`.
+#[derive(Clone)]
+pub(crate) struct Decoder(pub(crate) Arc<DecodeFn>);
+
+impl fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Decoder(..)")
+    }
+}
+
+/// How a secondary index computes the values a pair can be found by, see
+/// `Store::create_index`.
+#[derive(Clone)]
+pub enum Extractor {
+    /// The field at a path in values holding JSON documents, e.g. `$.email`,
+    /// `address.city` or `$.phones[0]`. Strings are indexed as they are, numbers and
+    /// booleans as their JSON text, and each such element of an array on its own, so a
+    /// document can be found by any of its tags. Values that aren't JSON, and documents
+    /// without the field, aren't indexed.
+    JsonPath(String),
+    /// A function of the key and value of a pair, returning every value the pair can be
+    /// found by, none to leave it out of the index.
+    Closure(Arc<ExtractFn>),
+}
+
+impl Extractor {
+    /// Creates an extractor indexing the field at a JSON path, see `Extractor::JsonPath`.
+    pub fn json_path(path: &str) -> Extractor {
+        Extractor::JsonPath(path.to_string())
+    }
+
+    /// Creates an extractor calling `extract` on every pair, see `Extractor::Closure`.
+    pub fn closure<F>(extract: F) -> Extractor
+    where
+        F: Fn(&[u8], &[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static,
+    {
+        Extractor::Closure(Arc::new(extract))
+    }
+}
+
+impl fmt::Debug for Extractor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Extractor::JsonPath(path) => f.debug_tuple("JsonPath").field(path).finish(),
+            Extractor::Closure(_) => f.write_str("Closure(..)"),
+        }
+    }
+}
+
+/// A step of a JSON path: a field of an object, or an element of an array.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Field(String),
+    Element(usize),
+}
+
+/// Parses a JSON path made of dot-separated field names, each followed by any number of
+/// `[index]` array subscripts, optionally starting with `$`.
+///
+/// # Returns
+/// * `Ok(Vec<Segment>)` - The steps of the path.
+/// * `Err(Error::InvalidIndex)` - If the path is empty or malformed.
+fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let invalid = || Error::InvalidIndex(format!("bad JSON path '{path}'"));
+    let rest = path.strip_prefix('$').unwrap_or(path);
+    let rest = rest.strip_prefix('.').unwrap_or(rest);
+    let mut segments = Vec::new();
+    for part in rest.split('.') {
+        let (field, mut subscripts) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if field.is_empty() && (subscripts.is_empty() || !segments.is_empty()) {
+            return Err(invalid()); // Only the first part can start with a subscript, `$[0]`
+        }
+        if !field.is_empty() {
+            segments.push(Segment::Field(field.to_string()));
+        }
+        while !subscripts.is_empty() {
+            let end = subscripts.find(']').ok_or_else(invalid)?;
+            let element = subscripts[1..end].parse().map_err(|_| invalid())?;
+            segments.push(Segment::Element(element));
+            subscripts = &subscripts[end + 1..];
+            if !subscripts.is_empty() && !subscripts.starts_with('[') {
+                return Err(invalid());
+            }
+        }
+    }
+    Ok(segments)
+}
+
+/// Returns the part of `document` at the end of `segments`, if it has one.
+fn lookup<'a>(
+    document: &'a serde_json::Value,
+    segments: &[Segment],
+) -> Option<&'a serde_json::Value> {
+    segments
+        .iter()
+        .try_fold(document, |value, segment| match segment {
+            Segment::Field(field) => value.get(field),
+            Segment::Element(element) => value.get(element),
+        })
+}
+
+/// Returns the bytes a JSON scalar is indexed as, `None` for nulls, arrays and objects.
+fn scalar_bytes(value: &serde_json::Value) -> Option<Vec<u8>> {
+    match value {
+        serde_json::Value::String(text) => Some(text.as_bytes().to_vec()),
+        serde_json::Value::Number(number) => Some(number.to_string().into_bytes()),
+        serde_json::Value::Bool(flag) => Some(flag.to_string().into_bytes()),
+        _ => None,
+    }
+}
+
+/// A secondary index of a store: for each index value, the keys of the pairs found by it.
+///
+/// The entries live in memory only, and are kept in step with the data by the store as it
+/// applies each mutation, see `Store::create_index`. The index remembers the values each key
+/// was added under, so removing a key never depends on its old value.
+#[derive(Debug)]
+pub(crate) struct Index {
+    extractor: Extractor,
+    /// The parsed path of a `JsonPath` extractor.
+    path: Vec<Segment>,
+    /// Turns the values as stored into the values extracted from, if they differ.
+    decode: Option<Decoder>,
+    entries: BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>,
+    /// The index values each key was added under.
+    keys: HashMap<Vec<u8>, Vec<Vec<u8>>>,
+}
+
+impl Index {
+    /// Creates an empty index, checking the extractor.
+    ///
+    /// # Arguments
+    /// * `extractor` - How the values a pair is found by are computed.
+    /// * `decode` - Turns the values as stored into the values `extractor` is given, if set.
+    ///
+    /// # Returns
+    /// * `Ok(Index)` - The index, to fill with `insert`.
+    /// * `Err(Error::InvalidIndex)` - If the JSON path of the extractor is malformed.
+    pub(crate) fn new(extractor: Extractor, decode: Option<Decoder>) -> Result<Index> {
+        let path = match &extractor {
+            Extractor::JsonPath(path) => parse_path(path)?,
+            Extractor::Closure(_) => Vec::new(),
+        };
+        Ok(Index {
+            extractor,
+            path,
+            decode,
+            entries: BTreeMap::new(),
+            keys: HashMap::new(),
+        })
+    }
+
+    /// Adds the entries of the pair `key`, `value`, replacing those `key` had.
+    pub(crate) fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.remove(key);
+        let value = match &self.decode {
+            Some(Decoder(decode)) => match decode(key, value) {
+                Some(value) => value,
+                None => return,
+            },
+            None => Cow::Borrowed(value),
+        };
+        let indexed = self.extract(key, &value);
+        for value in &indexed {
+            self.entries
+                .entry(value.clone())
+                .or_default()
+                .insert(key.to_vec());
+        }
+        if !indexed.is_empty() {
+            self.keys.insert(key.to_vec(), indexed);
+        }
+    }
+
+    /// Removes every entry, before the index is filled again.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.keys.clear();
+    }
+
+    /// Removes the entries of `key`, whatever its value is now.
+    pub(crate) fn remove(&mut self, key: &[u8]) {
+        for indexed in self.keys.remove(key).into_iter().flatten() {
+            if let Some(keys) = self.entries.get_mut(&indexed) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.entries.remove(&indexed);
+                }
+            }
+        }
+    }
+
+    /// Returns the keys of the pairs found by `value`, in key order.
+    pub(crate) fn find(&self, value: &[u8]) -> impl Iterator<Item = &Vec<u8>> {
+        self.entries.get(value).into_iter().flatten()
+    }
+
+    /// Returns the values the pair `key`, `value` can be found by.
+    fn extract(&self, key: &[u8], value: &[u8]) -> Vec<Vec<u8>> {
+        match &self.extractor {
+            Extractor::JsonPath(_) => {
+                let document = match serde_json::from_slice::<serde_json::Value>(value) {
+                    Ok(document) => document,
+                    Err(_) => return Vec::new(),
+                };
+                match lookup(&document, &self.path) {
+                    Some(serde_json::Value::Array(items)) => {
+                        items.iter().filter_map(scalar_bytes).collect()
+                    }
+                    Some(field) => scalar_bytes(field).into_iter().collect(),
+                    None => Vec::new(),
+                }
+            }
+            Extractor::Closure(extract) => extract(key, value),
+        }
+    }
+}
+
+/// Decodes the catalog of persisted indexes, their JSON paths by name, `None` for the
+/// indexes computed by a closure.
+pub(crate) fn decode_catalog(stored: &[u8]) -> Result<BTreeMap<String, Option<String>>> {
+    Ok(bincode::deserialize(stored)?)
+}
+
+/// Encodes the catalog of persisted indexes.
+pub(crate) fn encode_catalog(catalog: &BTreeMap<String, Option<String>>) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(catalog)?)
+}
@@ -0,0 +1,370 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+use std::time::Duration;
+
+use crate::database::Database;
+use crate::error::{Error, Result};
+use crate::kv_store::{expiry_after, prefix_successor, Value, KV};
+use crate::shards::byte_bounds;
+
+/// Name of the keyspace holding the keys written without one, which always exists.
+pub const DEFAULT_KEYSPACE: &str = "default";
+
+/// Key the catalog of keyspaces is stored under, see `is_reserved`.
+pub(crate) const CATALOG_KEY: &[u8] = b"\x00\x00keyspaces";
+
+/// How the values of a keyspace are stored.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum Compression {
+    /// Values are stored as they are.
+    #[default]
+    None,
+    /// Values are compressed with LZ4, which pays off for larger, repetitive values.
+    Lz4,
+}
+
+/// Settings of a keyspace, chosen when it is created.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct KeyspaceOptions {
+    /// How long the pairs inserted without a TTL live. `None` keeps them until deleted.
+    pub default_ttl: Option<Duration>,
+    /// How the values are stored. It can't change once the keyspace holds values.
+    pub compression: Compression,
+}
+
+/// A named group of keys within a database, see `Database::create_keyspace`.
+///
+/// Keyspaces share the shards, write-ahead logs and change log of their database: a key of
+/// keyspace `name` is stored as `\0name\0key`, so the keys of a keyspace are contiguous
+/// and can be listed or dropped without visiting the others. Keys starting with a zero
+/// byte are reserved for them, and hidden from the scans of the database and its snapshots
+/// unless the scan starts among them, e.g. at `keyspace.key("")`.
+///
+/// The handle borrows the database. To write to several keyspaces atomically, add the
+/// writes to a `WriteBatch` with `put_in` and `delete_in`. Transactions and snapshots work on
+/// the keys of the database: use `key`, `encode_value` and `decode_value` to reach a keyspace
+/// from them.
+///
+/// # Example
+/// ```rust
+/// use safina_db::keyspace::KeyspaceOptions;
+/// use safina_db::{Backend, Database, Options};
+/// use std::time::Duration;
+///
+/// let db = Database::open("example", Options { backend: Backend::Memory, ..Options::default() }).unwrap();
+/// let sessions = db
+///     .create_keyspace("sessions", KeyspaceOptions {
+///         default_ttl: Some(Duration::from_secs(3600)),
+///         ..KeyspaceOptions::default()
+///     })
+///     .unwrap();
+/// sessions.insert("alice", "token").unwrap();
+/// assert!(sessions.ttl("alice").unwrap().is_some());
+/// assert_eq!(db.get("alice").unwrap(), None); // Not in the default keyspace
+/// assert_eq!(db.keyspaces().unwrap(), ["default", "sessions"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Keyspace<'a> {
+    db: &'a Database,
+    name: String,
+    /// Prefix of the keys of the keyspace in the database, empty for the default one.
+    prefix: Vec<u8>,
+    options: KeyspaceOptions,
+}
+
+impl<'a> Keyspace<'a> {
+    /// Creates the handle of keyspace `name` of `db`.
+    pub(crate) fn new(db: &'a Database, name: &str, options: KeyspaceOptions) -> Keyspace<'a> {
+        let prefix = if name == DEFAULT_KEYSPACE {
+            Vec::new()
+        } else {
+            key_prefix(name)
+        };
+        Keyspace {
+            db,
+            name: name.to_string(),
+            prefix,
+            options,
+        }
+    }
+
+    /// Returns the name of the keyspace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the settings the keyspace was created with.
+    pub fn options(&self) -> &KeyspaceOptions {
+        &self.options
+    }
+
+    /// Returns the key `key` of the keyspace is stored under in the database.
+    pub fn key<K: AsRef<[u8]>>(&self, key: K) -> Vec<u8> {
+        let mut stored = self.prefix.clone();
+        stored.extend_from_slice(key.as_ref());
+        stored
+    }
+
+    /// Returns `value` as it is stored in the keyspace, compressed if the keyspace is.
+    pub fn encode_value<V: AsRef<[u8]>>(&self, value: V) -> Vec<u8> {
+        match self.options.compression {
+            Compression::None => value.as_ref().to_vec(),
+            Compression::Lz4 => lz4_flex::compress_prepend_size(value.as_ref()),
+        }
+    }
+
+    /// Returns a value as it was written to the keyspace, from the way it is stored.
+    ///
+    /// # Returns
+    /// * `Ok(Value)` - The value.
+    /// * `Err(Error::Corruption)` - If the stored value can't be decompressed.
+    pub fn decode_value(&self, stored: &[u8]) -> Result<Value> {
+        match self.options.compression {
+            Compression::None => Ok(stored.to_vec()),
+            Compression::Lz4 => lz4_flex::decompress_size_prepended(stored)
+                .map_err(|e| Error::Corruption(format!("bad compressed value: {e}"))),
+        }
+    }
+
+    /// Returns a copy of the value associated with `key`, if any.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Value>> {
+        self.db
+            .get(self.key(key))?
+            .map(|stored| self.decode_value(&stored))
+            .transpose()
+    }
+
+    /// Inserts a new key-value pair, expiring after the default TTL of the keyspace if it has
+    /// one, see `Store::insert`.
+    pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<()> {
+        match self.options.default_ttl {
+            Some(ttl) => self.insert_with_ttl(key, value, ttl),
+            None => {
+                let _live = self.check()?;
+                self.db.insert(self.key(key), self.encode_value(value))
+            }
+        }
+    }
+
+    /// Inserts a new key-value pair that expires once `ttl` has elapsed, see
+    /// `Store::insert_with_ttl`.
+    pub fn insert_with_ttl<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<()> {
+        let _live = self.check()?;
+        self.db
+            .insert_with_ttl(self.key(key), self.encode_value(value), ttl)
+    }
+
+    /// Updates the value of an existing key, keeping its expiry time, see `Store::update`.
+    pub fn update<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<()> {
+        let _live = self.check()?;
+        self.db.update(self.key(key), self.encode_value(value))
+    }
+
+    /// Deletes a key if it exists, see `Store::delete`.
+    pub fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<()> {
+        self.db.delete(self.key(key))
+    }
+
+    /// Returns how long a key has left to live, see `Store::ttl`.
+    pub fn ttl<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Duration>> {
+        self.db.ttl(self.key(key))
+    }
+
+    /// Adds `delta` to the counter stored at a key, see `Store::incr_by`.
+    ///
+    /// # Returns
+    /// * `Ok(i64)` - The new value of the counter.
+    /// * `Err(Error::Merge)` - If the keyspace compresses its values, which merge operators
+    ///   can't read, or the value is not an integer.
+    pub fn incr_by<K: AsRef<[u8]>>(&self, key: K, delta: i64) -> Result<i64> {
+        if self.options.compression != Compression::None {
+            return Err(Error::Merge(format!(
+                "keyspace '{}' compresses its values, they can't be counters",
+                self.name
+            )));
+        }
+        let _live = self.check()?;
+        self.db.incr_by(self.key(key), delta)
+    }
+
+    /// Returns the pairs of the keyspace within `range`, in key order, see `Store::scan`.
+    /// The keys are returned without the prefix of the keyspace.
+    pub fn scan<'k, R: RangeBounds<&'k str>>(&self, range: R) -> Result<Vec<KV>> {
+        self.scan_bytes((
+            range.start_bound().map(|key| key.as_bytes()),
+            range.end_bound().map(|key| key.as_bytes()),
+        ))
+    }
+
+    /// Returns the pairs of the keyspace within a range of byte string keys, in key order.
+    pub fn scan_bytes<'k, R: RangeBounds<&'k [u8]>>(&self, range: R) -> Result<Vec<KV>> {
+        let (start, end) = byte_bounds(&range);
+        let start = match start {
+            Bound::Unbounded => Bound::Included(self.prefix.clone()),
+            bound => bound.map(|key| self.key(key)),
+        };
+        let end = match end {
+            Bound::Unbounded => {
+                prefix_successor(&self.prefix).map_or(Bound::Unbounded, Bound::Excluded)
+            }
+            bound => bound.map(|key| self.key(key)),
+        };
+        self.db
+            .scan_bytes((
+                start.as_ref().map(Vec::as_slice),
+                end.as_ref().map(Vec::as_slice),
+            ))?
+            .into_iter()
+            .map(|pair| {
+                Ok(KV {
+                    key: pair.key[self.prefix.len()..].to_vec(),
+                    value: self.decode_value(&pair.value)?,
+                    expires_at: pair.expires_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the pairs of the keyspace whose key starts with `prefix`, in key order.
+    pub fn scan_prefix<P: AsRef<[u8]>>(&self, prefix: P) -> Result<Vec<KV>> {
+        let prefix = prefix.as_ref();
+        match prefix_successor(prefix) {
+            Some(end) => {
+                self.scan_bytes((Bound::Included(prefix), Bound::Excluded(end.as_slice())))
+            }
+            None => self.scan_bytes((Bound::Included(prefix), Bound::Unbounded)),
+        }
+    }
+
+    /// Returns a copy of the pairs of the keyspace an index finds by `value`, in key order,
+    /// see `Database::find`. The keys are returned without the prefix of the keyspace.
+    ///
+    /// Indexes cover every keyspace, and see the values as they are stored: the values of a
+    /// keyspace compressing them can only be indexed by a closure decoding them.
+    pub fn find<V: AsRef<[u8]>>(&self, index: &str, value: V) -> Result<Vec<KV>> {
+        self.db
+            .find_all(index, value.as_ref())?
+            .into_iter()
+            .filter(|pair| match self.prefix.is_empty() {
+                true => !is_reserved(&pair.key),
+                false => pair.key.starts_with(&self.prefix),
+            })
+            .map(|pair| {
+                Ok(KV {
+                    key: pair.key[self.prefix.len()..].to_vec(),
+                    value: self.decode_value(&pair.value)?,
+                    expires_at: pair.expires_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the expiry time a pair inserted now gets by default, for batches.
+    pub(crate) fn default_expiry(&self) -> Option<u64> {
+        self.options.default_ttl.map(expiry_after)
+    }
+
+    /// Fails if the keyspace was dropped, so a stale handle can't write to it. The returned
+    /// guard keeps the keyspace from being dropped until the write is done.
+    fn check(&self) -> Result<RwLockReadGuard<'a, BTreeMap<String, KeyspaceOptions>>> {
+        let keyspaces = self.db.read_keyspaces()?;
+        if self.prefix.is_empty() || keyspaces.contains_key(&self.name) {
+            Ok(keyspaces)
+        } else {
+            Err(Error::KeyspaceNotFound(self.name.clone()))
+        }
+    }
+}
+
+/// The key prefixes of the keyspaces whose values are compressed, shared with the indexes of
+/// the database so they extract from the values as written, see `Database::create_index`.
+///
+/// Its lock is only ever held to read or replace the prefixes, never while taking another.
+#[derive(Debug, Default)]
+pub(crate) struct CompressedKeyspaces {
+    prefixes: RwLock<Vec<Vec<u8>>>,
+}
+
+impl CompressedKeyspaces {
+    /// Takes the compressed keyspaces of `catalog`, e.g. once a keyspace is created.
+    pub(crate) fn update(&self, catalog: &BTreeMap<String, KeyspaceOptions>) {
+        let prefixes = catalog
+            .iter()
+            .filter(|(_, options)| options.compression == Compression::Lz4)
+            .map(|(name, _)| key_prefix(name))
+            .collect();
+        let mut current = self
+            .prefixes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        *current = prefixes;
+    }
+
+    /// Returns the value stored under `key` as it was written, decompressed if its keyspace
+    /// is compressed, `None` if it can't be decompressed.
+    pub(crate) fn decode<'v>(&self, key: &[u8], value: &'v [u8]) -> Option<Cow<'v, [u8]>> {
+        if !is_reserved(key) {
+            return Some(Cow::Borrowed(value)); // The default keyspace is never compressed
+        }
+        let prefixes = self.prefixes.read().unwrap_or_else(PoisonError::into_inner);
+        if !prefixes.iter().any(|prefix| key.starts_with(prefix)) {
+            return Some(Cow::Borrowed(value));
+        }
+        lz4_flex::decompress_size_prepended(value)
+            .ok()
+            .map(Cow::Owned)
+    }
+}
+
+/// Returns `true` for the keys reserved for keyspaces and their catalog, which start with a
+/// zero byte.
+pub(crate) fn is_reserved(key: &[u8]) -> bool {
+    key.first() == Some(&0)
+}
+
+/// Returns `true` if a scan starting at `start` skips the reserved keys, i.e. unless the
+/// scan explicitly starts among them, as the scans of a keyspace do.
+pub(crate) fn hides_reserved(start: Bound<&[u8]>) -> bool {
+    match start {
+        Bound::Included(key) | Bound::Excluded(key) => !is_reserved(key),
+        Bound::Unbounded => true,
+    }
+}
+
+/// Returns the prefix of the keys of keyspace `name` in the database.
+pub(crate) fn key_prefix(name: &str) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(name.len() + 2);
+    prefix.push(0);
+    prefix.extend_from_slice(name.as_bytes());
+    prefix.push(0);
+    prefix
+}
+
+/// Checks that `name` can name a new keyspace: not empty, without zero bytes, and not the
+/// default keyspace.
+pub(crate) fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(Error::InvalidKeyspaceName(name.to_string()));
+    }
+    if name == DEFAULT_KEYSPACE {
+        return Err(Error::KeyspaceExists(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Decodes the catalog of keyspaces, as stored under `CATALOG_KEY`.
+pub(crate) fn decode_catalog(stored: &[u8]) -> Result<BTreeMap<String, KeyspaceOptions>> {
+    Ok(bincode::deserialize(stored)?)
+}
+
+/// Encodes the catalog of keyspaces, to store it under `CATALOG_KEY`.
+pub(crate) fn encode_catalog(catalog: &BTreeMap<String, KeyspaceOptions>) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(catalog)?)
+}
@@ -1,30 +1,278 @@
-use super::STORAGE_MUTEX;
+use crate::batch::WriteBatch;
+use crate::error::{Error, Result};
+use crate::index::{Decoder, Extractor, Index};
+use crate::merge::{self, MergeOperators};
+use crate::storage::durability::Watermark;
+use crate::storage::{self, ChangeLog, MemoryBackend, Mutation, StorageBackend, SyncTicket};
+use crate::transaction::Write;
+use crate::watch::{Event, EventKind, Subscriber, Watcher};
 use serde;
+use std::borrow::Cow;
+use std::collections::{btree_set, BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A value stored in the database, as returned by `Store::get`.
+pub type Value = Vec<u8>;
 
 /// Represents a key-value pair.
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+///
+/// Keys and values are arbitrary bytes. A pair can carry an expiry time, past which the
+/// store treats it as deleted.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
 pub struct KV {
-    pub key: String,
-    pub value: String,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    /// When the pair expires, in milliseconds since the Unix epoch. `None` never expires.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl KV {
+    /// Creates a pair from anything convertible to bytes, e.g. `&str`, `String` or `Vec<u8>`.
+    /// The pair never expires.
+    pub fn new<K: Into<Vec<u8>>, V: Into<Vec<u8>>>(key: K, value: V) -> Self {
+        KV {
+            key: key.into(),
+            value: value.into(),
+            expires_at: None,
+        }
+    }
+
+    /// Returns `true` if the pair has expired at `now`, in milliseconds since the Unix epoch.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Returns the key as text, with invalid UTF-8 sequences replaced by `U+FFFD`.
+    pub fn key_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.key)
+    }
+
+    /// Returns the value as text, with invalid UTF-8 sequences replaced by `U+FFFD`.
+    pub fn value_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.value)
+    }
+}
+
+/// What the store holds for a key: a pair, or the value a pair had before merges along with
+/// the operands merged into it since, see `Store::merge`.
+#[derive(Debug, Clone)]
+enum Entry {
+    Pair(KV),
+    Merged(Box<Merged>),
+}
+
+/// The merge operands written to a key, not folded into its value yet. They are folded the
+/// first time the key is read, and the result is kept until the key changes again.
+#[derive(Debug, Clone)]
+struct Merged {
+    key: Vec<u8>,
+    /// The value before the first operand, `None` if the key didn't exist.
+    base: Option<Value>,
+    expires_at: Option<u64>,
+    /// The operator names and operands, oldest first.
+    operands: Vec<(String, Vec<u8>)>,
+    /// The pair the operands fold to, or why they don't.
+    folded: OnceLock<std::result::Result<KV, String>>,
 }
 
 /// Represents the in-memory key-value store.
+///
+/// Pairs are indexed by key in a hash map, so lookups, duplicate checks and deletes
+/// are O(1) on average. An ordered set of the same keys backs the range and prefix scans.
+/// Every mutation is persisted through the store's `StorageBackend` before it is applied.
+///
+/// Keys and values are byte strings: every method takes anything implementing
+/// `AsRef<[u8]>`, so `&str`, `String`, `&[u8]` and `Vec<u8>` can all be passed directly.
+/// Keys are ordered bytewise, which for UTF-8 text is the same as ordering by code point.
+///
+/// Expired pairs are hidden from reads and scans as soon as their time is up, but stay in
+/// `data` until `purge_expired` deletes them.
+///
+/// Merges are applied as their operand and folded into the value of their key when it is
+/// read (see `merge`), which is why reads can fail with `Error::Merge`.
+///
+/// Every applied mutation is numbered. While transactions are open, the store remembers the
+/// number of the last change of each key, so a transaction can tell at commit whether a
+/// key it used was changed after it began.
+///
+/// The number of the last change of a key is also its version, see `version`. Writers can
+/// make a change conditional on the version they read (`update_if_version`), or on the
+/// value they expect (`compare_and_swap`), for read-modify-write cycles without a
+/// transaction.
+///
+/// Readers can also take a point-in-time `Snapshot` at the current number. While snapshots
+/// are open, every change keeps the version of the key it replaced, so a snapshot reads the
+/// data as it was when it was taken. Versions are dropped once no open snapshot can see them.
+///
+/// Pairs can also be found by the fields of their values through secondary indexes, see
+/// `create_index`.
 #[derive(Debug)]
 pub struct Store {
-    pub data: Vec<KV>,
+    data: HashMap<Vec<u8>, Entry>,
+    keys: BTreeSet<Vec<u8>>,
+    /// The keys holding merge operands not folded into their value yet.
+    merged: BTreeSet<Vec<u8>>,
+    backend: Box<dyn StorageBackend>,
+    /// Number of the last applied mutation.
+    seq: u64,
+    /// Start numbers of the open transactions, with how many started at each.
+    transactions: BTreeMap<u64, usize>,
+    /// Number of the last change of each key changed while a transaction was open.
+    modified: HashMap<Vec<u8>, u64>,
+    /// Numbers the open snapshots were taken at, with how many were taken at each.
+    snapshots: BTreeMap<u64, usize>,
+    /// Versions replaced while a snapshot was open, per key, oldest first.
+    history: BTreeMap<Vec<u8>, Vec<Version>>,
+    /// Number of the last change of each key changed since the content was loaded.
+    changed: HashMap<Vec<u8>, u64>,
+    /// Number the content was loaded at, the version of the keys unchanged since.
+    loaded_at: u64,
+    /// Leave the syncs the backend defers to the caller, see `take_sync`.
+    defer_syncs: bool,
+    /// The syncs the applied mutations still wait for.
+    pending: Vec<SyncTicket>,
+    /// The watchers to notify of every change, see `watch`.
+    watchers: Vec<Subscriber>,
+    /// The syncs the last persisted mutation waits for, before watchers may see it.
+    durable_at: Vec<Watermark>,
+    /// The log every persisted mutation is also appended to, see `ChangeLog`.
+    change_log: Option<Arc<ChangeLog>>,
+    /// The secondary indexes by name, see `create_index`.
+    indexes: BTreeMap<String, Index>,
+    /// Turns the values as stored into the values the indexes extract from, see
+    /// `set_index_decoder`.
+    index_decoder: Option<Decoder>,
+    /// The operators merges are folded with, see `merge`.
+    merge_operators: MergeOperators,
+}
+
+/// A replaced version of a key: the number of the mutation that replaced it, and the entry
+/// as it was before, `None` if the key didn't exist.
+type Version = (u64, Option<Entry>);
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Store {
-    /// Creates a new, empty `Store`.
+    /// Creates a new, empty `Store` that only lives in memory.
     ///
     /// # Returns
-    /// A new instance of `Store`.
+    /// A new instance of `Store` backed by a `MemoryBackend`.
     pub fn new() -> Self {
-        Store { data: Vec::new() }
+        Self::with_backend(Box::new(MemoryBackend::new()))
+    }
+
+    /// Opens a `Store` on top of the given backend, loading the data it persisted. The merge
+    /// operands the backend holds are left to fold when their key is read, see
+    /// `StorageBackend::load_unfolded`.
+    ///
+    /// # Arguments
+    /// * `backend` - The backend every mutation will be persisted through.
+    ///
+    /// # Returns
+    /// * `Ok(Store)` holding the persisted data.
+    /// * `Err(Error)` if the backend fails to load.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::storage::MemoryBackend;
+    ///
+    /// let mut store = safina_db::Store::open(Box::new(MemoryBackend::new())).unwrap();
+    /// store.insert("key", "value").unwrap();
+    /// ```
+    pub fn open(mut backend: Box<dyn StorageBackend>) -> Result<Self> {
+        let (data, merges) = backend.load_unfolded()?;
+        let mut store = Self::with_backend(backend);
+        store.seq = now_micros(); // Versions are never reused across reopens, see `version`
+        store.load(data);
+        for merge in merges {
+            store.apply(merge);
+        }
+        Ok(store)
+    }
+
+    /// Creates an empty store writing through `backend`.
+    fn with_backend(backend: Box<dyn StorageBackend>) -> Self {
+        Store {
+            data: HashMap::new(),
+            keys: BTreeSet::new(),
+            merged: BTreeSet::new(),
+            backend,
+            seq: 0,
+            transactions: BTreeMap::new(),
+            modified: HashMap::new(),
+            snapshots: BTreeMap::new(),
+            history: BTreeMap::new(),
+            changed: HashMap::new(),
+            loaded_at: 0,
+            defer_syncs: false,
+            pending: Vec::new(),
+            watchers: Vec::new(),
+            durable_at: Vec::new(),
+            change_log: None,
+            indexes: BTreeMap::new(),
+            index_decoder: None,
+            merge_operators: MergeOperators::default(),
+        }
+    }
+
+    /// Replaces the content of the store with pairs loaded from storage.
+    ///
+    /// # Arguments
+    /// * `data` - The pairs returned by `Storage::load_file`.
+    pub fn load(&mut self, data: Vec<KV>) {
+        self.data = data
+            .into_iter()
+            .map(|pair| (pair.key.clone(), Entry::Pair(pair)))
+            .collect();
+        self.keys = self.data.keys().cloned().collect();
+        self.merged.clear();
+        self.changed.clear();
+        self.loaded_at = self.seq;
+        for index in self.indexes.values_mut() {
+            index.clear();
+            for pair in self.data.values().filter_map(Entry::folded) {
+                index.insert(&pair.key, &pair.value);
+            }
+        }
+    }
+
+    /// Returns the content of the store in the on-disk snapshot format, with the merge
+    /// operands folded into their values.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<KV>)` with every pair, expired or not.
+    /// * `Err(Error::Merge)` if the operands merged into a key can't be folded.
+    pub fn to_vec(&self) -> Result<Vec<KV>> {
+        self.data
+            .values()
+            .map(|entry| entry.pair(&self.merge_operators).cloned())
+            .collect()
+    }
+
+    /// Asks the backend to compact what it persisted so far into the current content,
+    /// e.g. by folding its log into a snapshot. Fails with `Error::Merge` if the operands
+    /// merged into a key can't be folded, see `to_vec`.
+    pub fn flush(&mut self) -> Result<()> {
+        let data = self.to_vec()?;
+        self.backend.flush(data)
+    }
+
+    /// Flushes the store and releases the backend's files. The store must not be
+    /// mutated after it is closed.
+    pub fn close(&mut self) -> Result<()> {
+        let data = self.to_vec()?;
+        self.backend.close(data)
     }
 
     /// Inserts a key-value pair into the store.
-    /// and save the new data into file
+    /// The insertion is logged to storage before it is applied in memory.
     ///
     /// # Arguments
     /// * `key` - The key to insert.
@@ -32,43 +280,114 @@ impl Store {
     ///
     /// # Returns
     /// * `Ok(())` if the insertion is successful.
-    /// * `Err(&str)` if the key already exists.
-    pub fn insert(&mut self, key: &str, value: &str) -> Result<(), String> {
-        let pair = self.get(&key);
-        match pair {
-            Ok(_) => Err("Key already exists".to_string()),
-            Err(_) => {
-                self.data.push(KV {
-                    key: key.to_string(),
-                    value: value.to_string(),
-                });
-                if let Err(e) = self.persist_data() {
-                    return Err(e.to_string());
-                }
-                Ok(())
-            }
+    /// * `Err(Error::KeyExists)` if the key already exists.
+    /// * `Err(Error)` if the insertion could not be persisted, the store is then left unchanged.
+    pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        self.insert_expiring(key.as_ref(), value.as_ref(), None)
+    }
+
+    /// Inserts a key-value pair that expires once `ttl` has elapsed.
+    ///
+    /// # Arguments
+    /// * `key` - The key to insert.
+    /// * `value` - The value to associate with the key.
+    /// * `ttl` - How long the pair lives.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the insertion is successful.
+    /// * `Err(Error::KeyExists)` if the key already exists.
+    /// * `Err(Error)` if the insertion could not be persisted, the store is then left unchanged.
+    pub fn insert_with_ttl<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<()> {
+        self.insert_expiring(key.as_ref(), value.as_ref(), Some(expiry_after(ttl)))
+    }
+
+    /// Inserts a pair expiring at `expires_at`, or never for `None`.
+    fn insert_expiring(&mut self, key: &[u8], value: &[u8], expires_at: Option<u64>) -> Result<()> {
+        if self.entry(key).is_some() {
+            return Err(Error::KeyExists); // An expired pair can be replaced
         }
+        let mutation = Mutation::put(key, value, expires_at);
+        self.persist_data(&mutation)?;
+        self.apply(mutation);
+        Ok(())
     }
 
-    /// Retrieves a mutable reference to the key-value pair associated with the given key.
+    /// Returns a copy of the value associated with the given key.
+    ///
+    /// Reading doesn't change the store, so any number of readers can share it.
     ///
     /// # Arguments
     /// * `key` - The key to search for.
     ///
     /// # Returns
-    /// * `Ok(&mut KV)` if the key is found.
-    /// * `Err(&str)` if the key is not found.
-    pub fn get(&mut self, key: &str) -> Result<&mut KV, &str> {
-        for pair in &mut self.data {
-            if pair.key == key {
-                return Ok(pair);
-            }
-        }
-        Err("Key not found")
+    /// * `Ok(Some(Value))` if the key is found.
+    /// * `Ok(None)` if the key is not found or has expired.
+    /// * `Err(Error::Merge)` if the operands merged into the key can't be folded.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Value>> {
+        Ok(self.live(key.as_ref())?.map(|pair| pair.value.clone()))
+    }
+
+    /// Returns the number of pairs held in memory, counting the expired pairs not deleted yet.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if the store holds no pair, not even an expired one.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
     }
 
-    /// Updates the value associated with the given key.
-    /// and save the new data into file
+    /// Returns the version of the given key: the number of the mutation that last changed it.
+    ///
+    /// Versions only grow, and a key deleted and inserted again gets a new version, so a
+    /// version identifies one state of the key. Versions aren't persisted: a store opened on
+    /// a backend numbers its mutations from the current time in microseconds, which gives
+    /// every key a version greater than any handed out before, as long as the store applied
+    /// less than a million mutations per second on average since the Unix epoch.
+    ///
+    /// # Returns
+    /// * `Some(u64)` if the key is found.
+    /// * `None` if the key is not found or has expired.
+    pub fn version<K: AsRef<[u8]>>(&self, key: K) -> Option<u64> {
+        let key = key.as_ref();
+        self.entry(key)?;
+        Some(self.changed.get(key).copied().unwrap_or(self.loaded_at))
+    }
+
+    /// Returns a copy of the value associated with the given key, along with its version.
+    ///
+    /// # Returns
+    /// * `Ok(Some((Value, u64)))` if the key is found.
+    /// * `Ok(None)` if the key is not found or has expired.
+    /// * `Err(Error::Merge)` if the operands merged into the key can't be folded.
+    pub fn get_with_version<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<(Value, u64)>> {
+        let key = key.as_ref();
+        Ok(self.get(key)?.zip(self.version(key)))
+    }
+
+    /// Returns the pair associated with the given key, unless it is missing or has expired,
+    /// folding the operands merged into it. Fails with `Error::Merge` if they can't be folded.
+    fn live(&self, key: &[u8]) -> Result<Option<&KV>> {
+        self.entry(key)
+            .map(|entry| entry.pair(&self.merge_operators))
+            .transpose()
+    }
+
+    /// Returns the entry of the given key, unless it is missing or has expired, without
+    /// folding the operands merged into it.
+    fn entry(&self, key: &[u8]) -> Option<&Entry> {
+        self.data
+            .get(key)
+            .filter(|entry| !entry.is_expired(now_millis()))
+    }
+
+    /// Updates the value associated with the given key, keeping its expiry time.
+    /// The update is logged to storage before it is applied in memory.
     ///
     /// # Arguments
     /// * `key` - The key to update.
@@ -76,53 +395,1184 @@ impl Store {
     ///
     /// # Returns
     /// * `Ok(())` if the update is successful.
-    /// * `Err(String)` if the key is not found.
-    pub fn update(&mut self, key: &str, value: &str) -> Result<(), String> {
-        let entity = self.get(key);
-        match entity {
-            Ok(pair) => {
-                pair.value = value.to_string();
-                self.persist_data().unwrap();
-                Ok(())
+    /// * `Err(Error::KeyNotFound)` if the key is not found.
+    /// * `Err(Error)` if the update could not be persisted, the store is then left unchanged.
+    pub fn update<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        let (key, value) = (key.as_ref(), value.as_ref());
+        let expires_at = self.entry(key).ok_or(Error::KeyNotFound)?.expires_at();
+        let mutation = Mutation::put(key, value, expires_at);
+        self.persist_data(&mutation)?;
+        self.apply(mutation);
+        Ok(())
+    }
+
+    /// Changes the value associated with the given key in place, keeping its expiry time.
+    ///
+    /// `change` edits a copy of the current value, which is then persisted and applied like
+    /// an `update`: indexes, watchers and the change log see the new value.
+    ///
+    /// # Arguments
+    /// * `key` - The key to change.
+    /// * `change` - Edits the value.
+    ///
+    /// # Returns
+    /// * `Ok(Value)` the new value of the key.
+    /// * `Err(Error::KeyNotFound)` if the key is not found or has expired.
+    /// * `Err(Error::Merge)` if the operands merged into the key can't be folded.
+    /// * `Err(Error)` if the change could not be persisted, the store is then left unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut store = safina_db::Store::new();
+    /// store.insert("greeting", "hello").unwrap();
+    /// let value = store.update_with("greeting", |value| value.extend_from_slice(b", world")).unwrap();
+    /// assert_eq!(value, b"hello, world");
+    /// ```
+    pub fn update_with<K, F>(&mut self, key: K, change: F) -> Result<Value>
+    where
+        K: AsRef<[u8]>,
+        F: FnOnce(&mut Value),
+    {
+        let key = key.as_ref();
+        let pair = self.live(key)?.ok_or(Error::KeyNotFound)?;
+        let (mut value, expires_at) = (pair.value.clone(), pair.expires_at);
+        change(&mut value);
+        let mutation = Mutation::put(key, &value, expires_at);
+        self.persist_data(&mutation)?;
+        self.apply(mutation);
+        Ok(value)
+    }
+
+    /// Updates the value of the given key if its version is still `version`, keeping its
+    /// expiry time.
+    ///
+    /// # Arguments
+    /// * `key` - The key to update.
+    /// * `version` - The version the key must have, as returned by `version` or
+    ///   `get_with_version`.
+    /// * `value` - The new value to associate with the key.
+    ///
+    /// # Returns
+    /// * `Ok(Some(u64))` with the new version of the key if the update is successful.
+    /// * `Ok(None)` if the key changed since `version`, nothing is written.
+    /// * `Err(Error::KeyNotFound)` if the key is not found.
+    /// * `Err(Error)` if the update could not be persisted, the store is then left unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut store = safina_db::Store::new();
+    /// store.insert("counter", "1").unwrap();
+    /// let (_, version) = store.get_with_version("counter").unwrap().unwrap();
+    /// assert!(store.update_if_version("counter", version, "2").unwrap().is_some());
+    /// assert!(store.update_if_version("counter", version, "3").unwrap().is_none());
+    /// ```
+    pub fn update_if_version<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        version: u64,
+        value: V,
+    ) -> Result<Option<u64>> {
+        let key = key.as_ref();
+        if self.version(key).ok_or(Error::KeyNotFound)? != version {
+            return Ok(None);
+        }
+        self.update(key, value)?;
+        Ok(Some(self.seq))
+    }
+
+    /// Replaces the value of the given key if it is still `expected`.
+    ///
+    /// `None` stands for a missing key on both sides: `expected` set to `None` only swaps if
+    /// the key doesn't exist (it is then inserted), and `new` set to `None` deletes the key.
+    /// An existing key keeps its expiry time.
+    ///
+    /// # Arguments
+    /// * `key` - The key to swap.
+    /// * `expected` - The value the key must have, or `None` if it must not exist.
+    /// * `new` - The value to set, or `None` to delete the key.
+    ///
+    /// # Returns
+    /// * `Ok(true)` if the key had the expected value and was swapped.
+    /// * `Ok(false)` if it didn't, nothing is written.
+    /// * `Err(Error::Merge)` if the operands merged into the key can't be folded.
+    /// * `Err(Error)` if the swap could not be persisted, the store is then left unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut store = safina_db::Store::new();
+    /// let acquired = store.compare_and_swap("lock", None::<&str>, Some("owner-1")).unwrap();
+    /// assert!(acquired);
+    /// assert!(!store.compare_and_swap("lock", None::<&str>, Some("owner-2")).unwrap());
+    /// assert!(store.compare_and_swap("lock", Some("owner-1"), None::<&str>).unwrap());
+    /// ```
+    pub fn compare_and_swap<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        expected: Option<V>,
+        new: Option<V>,
+    ) -> Result<bool> {
+        let key = key.as_ref();
+        let current = self.live(key)?;
+        if current.map(|pair| pair.value.as_slice()) != expected.as_ref().map(AsRef::as_ref) {
+            return Ok(false);
+        }
+        let expires_at = current.and_then(|pair| pair.expires_at);
+        let mutation = match new {
+            Some(value) => Mutation::put(key, value.as_ref(), expires_at),
+            None if current.is_some() => Mutation::Delete { key: key.to_vec() },
+            None => return Ok(true), // Already missing
+        };
+        self.persist_data(&mutation)?;
+        self.apply(mutation);
+        Ok(true)
+    }
+
+    /// Merges `operand` into the value of `key` with the merge operator named `operator` in
+    /// the store's `MergeOperators`, see `set_merge_operators`.
+    ///
+    /// The value isn't read: only the operand is persisted and kept in memory, and the
+    /// operands merged into a key are folded into its value the first time it is read, or
+    /// when the backend compacts them. A missing or expired key is merged as if it didn't
+    /// exist, and the pair keeps its expiry time otherwise. While the store has an index, a
+    /// watcher or a change log, which see the new value of every change, the operand is
+    /// folded right away instead.
+    ///
+    /// # Arguments
+    /// * `key` - The key to merge into.
+    /// * `operator` - The name of the operator.
+    /// * `operand` - The operand to fold in.
+    ///
+    /// # Returns
+    /// * `Ok(())` once the operand is merged. Reading the key fails with `Error::Merge`
+    ///   if the operator can't fold it, until the key is replaced or deleted.
+    /// * `Err(Error::Merge)` if the store has no operator named `operator`, or it has to fold
+    ///   the operand right away and can't; nothing is written.
+    /// * `Err(Error)` if the merge could not be persisted, the store is then left unchanged.
+    pub fn merge<K: AsRef<[u8]>, O: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        operator: &str,
+        operand: O,
+    ) -> Result<()> {
+        let (key, operand) = (key.as_ref(), operand.as_ref());
+        let merge = Mutation::Merge {
+            key: key.to_vec(),
+            operator: operator.to_string(),
+            operand: operand.to_vec(),
+        };
+        let mutation = match self.data.get(key) {
+            Some(entry) if entry.is_expired(now_millis()) => Mutation::Batch {
+                mutations: vec![Mutation::Delete { key: key.to_vec() }, merge],
+            }, // Not folded into the expired value
+            _ => merge,
+        };
+        self.write_merges(mutation)
+    }
+
+    /// Replaces the operators merges are folded with, the built-in ones by default. They
+    /// must include every operator the operands persisted by the backend were merged with.
+    pub fn set_merge_operators(&mut self, operators: MergeOperators) {
+        self.merge_operators = operators;
+    }
+
+    /// Adds `delta` to the counter stored at `key`, atomically and without rewriting its
+    /// value on disk, see `merge::Counter`. A missing key counts as `0`.
+    ///
+    /// # Arguments
+    /// * `key` - The counter to change.
+    /// * `delta` - The amount to add, negative to subtract.
+    ///
+    /// # Returns
+    /// * `Ok(i64)` the new value of the counter.
+    /// * `Err(Error::Merge)` if the value is not an integer or the counter overflows.
+    /// * `Err(Error)` if the change could not be persisted, the store is then left unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut store = safina_db::Store::new();
+    /// assert_eq!(store.incr_by("visits", 10).unwrap(), 10);
+    /// assert_eq!(store.incr("visits").unwrap(), 11);
+    /// assert_eq!(store.decr("visits").unwrap(), 10);
+    /// assert_eq!(store.get("visits").unwrap(), Some(b"10".to_vec()));
+    /// ```
+    pub fn incr_by<K: AsRef<[u8]>>(&mut self, key: K, delta: i64) -> Result<i64> {
+        let key = key.as_ref();
+        let operand = delta.to_string();
+        let current = self.live(key)?.map(|pair| pair.value.as_slice());
+        let value = self
+            .merge_operators
+            .fold(merge::COUNTER, key, current, operand.as_bytes())?;
+        let counter = merge::parse_integer(key, &value)?; // The operator may have been replaced
+        self.merge(key, merge::COUNTER, operand)?;
+        Ok(counter)
+    }
+
+    /// Adds one to the counter stored at `key`, see `incr_by`.
+    pub fn incr<K: AsRef<[u8]>>(&mut self, key: K) -> Result<i64> {
+        self.incr_by(key, 1)
+    }
+
+    /// Subtracts one from the counter stored at `key`, see `incr_by`.
+    pub fn decr<K: AsRef<[u8]>>(&mut self, key: K) -> Result<i64> {
+        self.incr_by(key, -1)
+    }
+
+    /// Subscribes to the changes of every key starting with `prefix`, an empty prefix
+    /// watching the whole store.
+    ///
+    /// Each change is sent to the watcher once it has been persisted, by the same call that
+    /// persisted it: the watcher sees the changes made by `insert`, `update`, `delete`,
+    /// merges, batches and committed transactions, along with the deletions of expired
+    /// pairs by `purge_expired`. Changes to the expiry time alone are not reported.
+    ///
+    /// # Arguments
+    /// * `prefix` - The prefix of the keys to watch.
+    ///
+    /// # Returns
+    /// A `Watcher` receiving an `Event` for every change, see `Watcher`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::watch::EventKind;
+    ///
+    /// let mut store = safina_db::Store::new();
+    /// let watcher = store.watch("user:");
+    /// store.insert("user:1", "alice").unwrap();
+    /// store.insert("order:1", "book").unwrap();
+    /// store.delete("user:1").unwrap();
+    ///
+    /// let event = watcher.try_recv().unwrap();
+    /// assert_eq!((event.kind, event.value), (EventKind::Put, Some(b"alice".to_vec())));
+    /// assert_eq!(watcher.try_recv().unwrap().kind, EventKind::Delete);
+    /// assert!(watcher.try_recv().is_none());
+    /// ```
+    pub fn watch<P: AsRef<[u8]>>(&mut self, prefix: P) -> Watcher {
+        let (watcher, subscriber) = Watcher::new(prefix.as_ref(), false);
+        self.subscribe(subscriber);
+        watcher
+    }
+
+    /// Sends the changes the subscriber watches to its watcher from now on, see `watch`.
+    pub(crate) fn subscribe(&mut self, subscriber: Subscriber) {
+        self.watchers.push(subscriber);
+    }
+
+    /// Creates a secondary index, finding pairs by the values `extractor` computes from
+    /// them, e.g. a field of the JSON documents they hold.
+    ///
+    /// The index is built from the current data, then kept in step with it: every mutation
+    /// updates the index as it is applied, so inserts, updates, deletes, merges, batches and
+    /// committed transactions are reflected at once and together with the data. The
+    /// entries only live in memory; `Database` persists the definitions of its indexes and
+    /// rebuilds the JSON path ones when it is opened.
+    ///
+    /// # Arguments
+    /// * `name` - The name `find` looks the index up by.
+    /// * `extractor` - How the values a pair is found by are computed, see `Extractor`.
+    ///
+    /// # Returns
+    /// * `Ok(())` once the index is built.
+    /// * `Err(Error::IndexExists)` if an index with this name already exists.
+    /// * `Err(Error::InvalidIndex)` if the JSON path of the extractor is malformed.
+    /// * `Err(Error::Merge)` if the operands merged into a key can't be folded.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::index::Extractor;
+    ///
+    /// let mut store = safina_db::Store::new();
+    /// store.insert("user:1", r#"{"email": "alice@example.com"}"#).unwrap();
+    /// store.create_index("email", Extractor::json_path("$.email")).unwrap();
+    /// store.insert("user:2", r#"{"email": "bob@example.com"}"#).unwrap();
+    ///
+    /// let found = store.find("email", "bob@example.com").unwrap();
+    /// assert_eq!(found[0].key, b"user:2");
+    /// ```
+    pub fn create_index(&mut self, name: &str, extractor: Extractor) -> Result<()> {
+        if self.indexes.contains_key(name) {
+            return Err(Error::IndexExists(name.to_string()));
+        }
+        let mut index = Index::new(extractor, self.index_decoder.clone())?;
+        for entry in self.data.values() {
+            let pair = entry.pair(&self.merge_operators)?; // Merges are folded from now on
+            index.insert(&pair.key, &pair.value);
+        }
+        self.indexes.insert(name.to_string(), index);
+        Ok(())
+    }
+
+    /// Deletes a secondary index, see `create_index`.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the index is deleted.
+    /// * `Err(Error::IndexNotFound)` if no index has this name.
+    pub fn drop_index(&mut self, name: &str) -> Result<()> {
+        self.indexes
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Error::IndexNotFound(name.to_string()))
+    }
+
+    /// Returns the names of the secondary indexes, in name order.
+    pub fn index_names(&self) -> Vec<String> {
+        self.indexes.keys().cloned().collect()
+    }
+
+    /// Returns a copy of the pairs an index finds by `value`, in key order, without scanning
+    /// the store. Expired pairs are skipped.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<KV>)` with the pairs, empty if none is found.
+    /// * `Err(Error::IndexNotFound)` if no index has this name.
+    pub fn find<V: AsRef<[u8]>>(&self, index: &str, value: V) -> Result<Vec<KV>> {
+        let value = value.as_ref();
+        let index = self
+            .indexes
+            .get(index)
+            .ok_or_else(|| Error::IndexNotFound(index.to_string()))?;
+        let mut pairs = Vec::new();
+        for key in index.find(value) {
+            if let Some(pair) = self.live(key)? {
+                pairs.push(pair.clone());
             }
-            Err(e) => Err(e.to_string()),
         }
+        Ok(pairs)
     }
 
-    /// Deletes the key-value pair associated with the given key.
+    /// Makes the given key expire once `ttl` has elapsed, replacing any previous expiry.
+    ///
+    /// # Arguments
+    /// * `key` - The key to expire.
+    /// * `ttl` - How long the pair has left to live.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the expiry is set.
+    /// * `Err(Error::KeyNotFound)` if the key is not found or has already expired.
+    /// * `Err(Error)` if the change could not be persisted, the store is then left unchanged.
+    pub fn set_expiry<K: AsRef<[u8]>>(&mut self, key: K, ttl: Duration) -> Result<()> {
+        self.set_expires_at(key.as_ref(), Some(expiry_after(ttl)))
+    }
+
+    /// Removes the expiry of the given key, so it lives until it is deleted.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the key no longer expires.
+    /// * `Err(Error::KeyNotFound)` if the key is not found or has already expired.
+    /// * `Err(Error)` if the change could not be persisted, the store is then left unchanged.
+    pub fn persist<K: AsRef<[u8]>>(&mut self, key: K) -> Result<()> {
+        self.set_expires_at(key.as_ref(), None)
+    }
+
+    /// Returns how long the given key has left to live.
+    ///
+    /// # Returns
+    /// * `Ok(Some(Duration))` if the key expires.
+    /// * `Ok(None)` if the key never expires.
+    /// * `Err(Error::KeyNotFound)` if the key is not found or has already expired.
+    pub fn ttl<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Duration>> {
+        let now = now_millis();
+        Ok(self
+            .entry(key.as_ref())
+            .ok_or(Error::KeyNotFound)?
+            .expires_at()
+            .map(|expires_at| Duration::from_millis(expires_at.saturating_sub(now))))
+    }
+
+    /// Deletes every expired pair, persisting each deletion.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` the number of pairs deleted.
+    /// * `Err(Error)` if a deletion could not be persisted; the pairs deleted before it stay deleted.
+    pub fn purge_expired(&mut self) -> Result<usize> {
+        let now = now_millis();
+        let expired: Vec<Vec<u8>> = self
+            .data
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.delete(key)?;
+        }
+        Ok(expired.len())
+    }
+
+    /// Sets the expiry time of a live key and logs it.
+    fn set_expires_at(&mut self, key: &[u8], expires_at: Option<u64>) -> Result<()> {
+        self.entry(key).ok_or(Error::KeyNotFound)?;
+        let mutation = Mutation::Expire {
+            key: key.to_vec(),
+            expires_at,
+        };
+        self.persist_data(&mutation)?;
+        self.apply(mutation);
+        Ok(())
+    }
+
+    /// Deletes the key-value pair associated with the given key, even if it has expired.
     ///
     /// # Arguments
     /// * `key` - The key to delete.
-    pub fn delete(&mut self, key: &str) {
-        if let Some(index) = self.data.iter().position(|x| x.key == key) {
-            self.data.remove(index);
-            self.persist_data().unwrap();
+    ///
+    /// # Returns
+    /// * `Ok(())` if the key was deleted or didn't exist.
+    /// * `Err(Error)` if the deletion could not be persisted, the store is then left unchanged.
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<()> {
+        let key = key.as_ref();
+        if self.data.contains_key(key) {
+            let mutation = Mutation::Delete { key: key.to_vec() };
+            self.persist_data(&mutation)?;
+            self.apply(mutation);
+        }
+        Ok(())
+    }
+
+    /// Persists several mutations as one atomic unit, then applies them in order.
+    ///
+    /// After a crash either every mutation of the batch is recovered, or none is. Unlike
+    /// `insert` or `update`, the mutations are applied as they are, without checking whether
+    /// their keys exist.
+    ///
+    /// # Arguments
+    /// * `mutations` - The mutations to apply, in order.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the batch is persisted and applied.
+    /// * `Err(Error)` if the batch could not be persisted, the store is then left unchanged.
+    pub fn apply_batch(&mut self, mutations: Vec<Mutation>) -> Result<()> {
+        if mutations.is_empty() {
+            return Ok(());
+        }
+        self.write_merges(Mutation::Batch { mutations })
+    }
+
+    /// Persists and applies a mutation that may hold merges. Their operands are applied as
+    /// they are, to fold when their key is read, unless an index, a watcher or the change
+    /// log needs the values they fold to, see `merge`.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the mutation is persisted and applied.
+    /// * `Err(Error::Merge)` if a merge names no operator of the store, or has to be folded
+    ///   right away and can't; nothing is written.
+    /// * `Err(Error)` if the mutation could not be persisted, the store is then left unchanged.
+    fn write_merges(&mut self, mutation: Mutation) -> Result<()> {
+        self.check_operators(&mutation)?;
+        if self.indexes.is_empty() && self.watchers.is_empty() && self.change_log.is_none() {
+            self.persist_data(&mutation)?;
+            self.apply(mutation);
+            return Ok(());
+        }
+        let folded = self.resolve_merges(&mutation)?.into_owned(); // Nothing is written if a merge can't be folded
+        self.persist_change(&mutation, &folded)?;
+        self.apply(folded);
+        Ok(())
+    }
+
+    /// Checks that the store has an operator for every merge of `mutation`.
+    fn check_operators(&self, mutation: &Mutation) -> Result<()> {
+        match mutation {
+            Mutation::Batch { mutations } => mutations
+                .iter()
+                .try_for_each(|mutation| self.check_operators(mutation)),
+            Mutation::Merge { operator, .. } => self.merge_operators.get(operator).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `mutation` with every merge replaced by a put of the value it folds to, as
+    /// the store applies it and the change log records it (so consumers don't need to know
+    /// the merge operators).
+    ///
+    /// # Returns
+    /// * `Ok(Cow<Mutation>)` the mutation with its merges folded.
+    /// * `Err(Error::Merge)` if a merge operand can't be folded.
+    fn resolve_merges<'m>(&self, mutation: &'m Mutation) -> Result<Cow<'m, Mutation>> {
+        let mutations = match mutation {
+            Mutation::Merge { .. } => std::slice::from_ref(mutation),
+            Mutation::Batch { mutations }
+                if mutations
+                    .iter()
+                    .any(|mutation| matches!(mutation, Mutation::Merge { .. })) =>
+            {
+                mutations.as_slice()
+            }
+            _ => return Ok(Cow::Borrowed(mutation)),
+        };
+        let mut pairs = self.touched(mutations)?;
+        let mut resolved = Vec::with_capacity(mutations.len());
+        for mutation in mutations {
+            storage::apply(&mut pairs, mutation.clone(), &self.merge_operators)?; // Folds like `apply` will
+            resolved.push(match mutation {
+                Mutation::Merge { key, .. } => {
+                    let pair = &pairs[key];
+                    Mutation::put(key, &pair.value, pair.expires_at)
+                }
+                mutation => mutation.clone(),
+            });
+        }
+        Ok(Cow::Owned(match mutation {
+            Mutation::Batch { .. } => Mutation::Batch { mutations: resolved },
+            _ => resolved.remove(0),
+        }))
+    }
+
+    /// Returns a copy of the pairs the keys of `mutations` hold, but for the keys they
+    /// replace before reading them, see `replaced_keys`.
+    ///
+    /// # Returns
+    /// * `Ok(HashMap<Vec<u8>, KV>)` the pairs by key.
+    /// * `Err(Error::Merge)` if the operands merged into one of them can't be folded.
+    fn touched(&self, mutations: &[Mutation]) -> Result<HashMap<Vec<u8>, KV>> {
+        let replaced = replaced_keys(mutations);
+        let mut pairs = HashMap::new();
+        for key in mutations.iter().filter_map(Mutation::key) {
+            if let Some(entry) = self.data.get(key).filter(|_| !replaced.contains(key)) {
+                pairs.insert(key.to_vec(), entry.pair(&self.merge_operators)?.clone());
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Makes the store return from a mutation before the sync its backend defers (see
+    /// `Durability::GroupCommit`), keeping it for `take_syncs`. By default the store waits
+    /// for the sync itself, which serializes the syncs of writers sharing the store.
+    pub(crate) fn defer_syncs(&mut self) {
+        self.defer_syncs = true;
+    }
+
+    /// Takes the syncs the mutations applied so far still wait for. The caller waits on them
+    /// once it has released the store, before acknowledging the mutations.
+    pub(crate) fn take_syncs(&mut self) -> Vec<SyncTicket> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Appends every mutation persisted from now on to `change_log`, see `ChangeLog`.
+    pub(crate) fn set_change_log(&mut self, change_log: Arc<ChangeLog>) {
+        self.change_log = Some(change_log);
+    }
+
+    /// Makes the indexes created from now on extract from the values `decoder` returns,
+    /// rather than from the values as stored, e.g. to decompress them.
+    pub(crate) fn set_index_decoder(&mut self, decoder: Decoder) {
+        self.index_decoder = Some(decoder);
+    }
+
+    /// Applies the writes of `batch` atomically, persisting all of them in a single step.
+    ///
+    /// # Arguments
+    /// * `batch` - The puts and deletes to apply, in order.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the batch is persisted and applied.
+    /// * `Err(Error)` if the batch could not be persisted, the store is then left unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::{Store, WriteBatch};
+    ///
+    /// let mut store = Store::new();
+    /// let mut batch = WriteBatch::new();
+    /// for i in 0..1000 {
+    ///     batch.put(format!("key{i}"), "value");
+    /// }
+    /// store.write_batch(batch).unwrap();
+    /// assert_eq!(store.len(), 1000);
+    /// ```
+    pub fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        self.apply_batch(batch.mutations)
+    }
+
+    /// Registers a transaction starting now, returning its start number.
+    pub(crate) fn begin_transaction(&mut self) -> u64 {
+        *self.transactions.entry(self.seq).or_insert(0) += 1;
+        self.seq
+    }
+
+    /// Checks that no key in `checked` changed since the transaction started at `start`.
+    ///
+    /// # Returns
+    /// * `Ok(())` if none did.
+    /// * `Err(Error::Conflict)` if a key in `checked` changed since `start`.
+    pub(crate) fn check_conflicts(&self, start: u64, checked: &HashSet<Vec<u8>>) -> Result<()> {
+        let changed = |key: &Vec<u8>| self.modified.get(key).is_some_and(|seq| *seq > start);
+        if checked.iter().any(changed) {
+            return Err(Error::Conflict);
+        }
+        Ok(())
+    }
+
+    /// Turns the buffered writes of a transaction into the batch committing them.
+    pub(crate) fn commit_mutations(&self, writes: BTreeMap<Vec<u8>, Write>) -> Vec<Mutation> {
+        writes
+            .into_iter()
+            .map(|(key, write)| match write {
+                Write::Insert(value, expires_at) => Mutation::put(&key, &value, expires_at),
+                Write::Update(value) => {
+                    let expires_at = self.data.get(&key).and_then(Entry::expires_at);
+                    Mutation::put(&key, &value, expires_at) // Updates keep the expiry time
+                }
+                Write::Delete => Mutation::Delete { key },
+            })
+            .collect()
+    }
+
+    /// Unregisters a transaction started at `start`, committed or not, and forgets the
+    /// changes no open transaction can conflict with anymore.
+    pub(crate) fn end_transaction(&mut self, start: u64) {
+        unregister(&mut self.transactions, start);
+        match self.transactions.keys().next() {
+            Some(&oldest) => self.modified.retain(|_, seq| *seq > oldest),
+            None => self.modified.clear(),
+        }
+    }
+
+    /// Returns the number of the last applied mutation.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns how many replaced versions are kept for the open snapshots.
+    pub fn versions(&self) -> usize {
+        self.history.values().map(Vec::len).sum()
+    }
+
+    /// Registers a snapshot of the data as it is now, returning its number.
+    pub(crate) fn take_snapshot(&mut self) -> u64 {
+        *self.snapshots.entry(self.seq).or_insert(0) += 1;
+        self.seq
+    }
+
+    /// Unregisters a snapshot taken at `seq`, and drops the versions no open snapshot can
+    /// see anymore.
+    pub(crate) fn release_snapshot(&mut self, seq: u64) {
+        unregister(&mut self.snapshots, seq);
+        match self.snapshots.keys().next() {
+            Some(&oldest) => self.history.retain(|_, versions| {
+                versions.retain(|(replaced_at, _)| *replaced_at > oldest);
+                !versions.is_empty()
+            }),
+            None => self.history.clear(),
+        }
+    }
+
+    /// Returns the pair stored under `key` as of mutation `seq`, expired or not. Fails with
+    /// `Error::Merge` if the operands merged into it can't be folded.
+    ///
+    /// `seq` must be the number of an open snapshot, older versions may be gone.
+    pub(crate) fn get_at(&self, key: &[u8], seq: u64) -> Result<Option<&KV>> {
+        let replaced = self
+            .history
+            .get(key)
+            .and_then(|versions| versions.iter().find(|(replaced_at, _)| *replaced_at > seq));
+        let entry = match replaced {
+            Some((_, previous)) => previous.as_ref(), // The first version replaced after `seq`
+            None => self.data.get(key),
+        };
+        entry
+            .map(|entry| entry.pair(&self.merge_operators))
+            .transpose()
+    }
+
+    /// Returns the pairs whose key falls within `bounds` as of mutation `seq`, in key order,
+    /// expired or not. Fails with `Error::Merge` like `get_at`.
+    pub(crate) fn scan_at(&self, bounds: (Bound<&[u8]>, Bound<&[u8]>), seq: u64) -> Result<Vec<&KV>> {
+        if is_empty_range(&bounds) {
+            return Ok(Vec::new());
+        }
+        let mut keys: BTreeSet<&[u8]> = self
+            .keys
+            .range::<[u8], _>(bounds)
+            .map(Vec::as_slice)
+            .collect();
+        keys.extend(
+            self.history
+                .range::<[u8], _>(bounds)
+                .map(|(key, _)| key.as_slice()), // Keys deleted since `seq`
+        );
+        keys.into_iter()
+            .filter_map(|key| self.get_at(key, seq).transpose())
+            .collect()
+    }
+
+    /// Applies a persisted mutation to the in-memory data. A merge only adds its operand to
+    /// the key, see `merge`: folding can fail, applying can't.
+    fn apply(&mut self, mutation: Mutation) {
+        let key = match mutation.key() {
+            Some(key) => key.to_vec(),
+            None => {
+                if let Mutation::Batch { mutations } = mutation {
+                    for mutation in mutations {
+                        self.apply(mutation);
+                    }
+                }
+                return;
+            }
+        };
+        self.seq += 1;
+        match mutation {
+            Mutation::Delete { .. } => self.changed.remove(&key),
+            _ => self.changed.insert(key.clone(), self.seq),
+        };
+        let kind = match mutation {
+            Mutation::Expire { .. } => None, // The value doesn't change
+            Mutation::Delete { .. } => match self.data.get(&key) {
+                Some(entry) if entry.is_expired(now_millis()) => Some(EventKind::Expire),
+                Some(_) => Some(EventKind::Delete),
+                None => None,
+            },
+            _ => Some(EventKind::Put),
+        };
+        if !self.snapshots.is_empty() {
+            let previous = self.data.get(&key).cloned(); // Open snapshots may still read it
+            self.history
+                .entry(key.clone())
+                .or_default()
+                .push((self.seq, previous));
+        }
+        let reindex = !self.indexes.is_empty() && !matches!(mutation, Mutation::Expire { .. });
+        if reindex {
+            for index in self.indexes.values_mut() {
+                index.remove(&key); // Re-added below with the new value, if any
+            }
+        }
+        match mutation {
+            Mutation::Put { key, value } => self.put(key, value, None),
+            Mutation::PutExpiring {
+                key,
+                value,
+                expires_at,
+            } => self.put(key, value, Some(expires_at)),
+            Mutation::Delete { key } => {
+                self.data.remove(&key);
+                self.keys.remove(&key);
+                self.merged.remove(&key);
+            }
+            Mutation::Expire { key, expires_at } => {
+                if let Some(entry) = self.data.get_mut(&key) {
+                    entry.set_expires_at(expires_at);
+                }
+            }
+            Mutation::Merge {
+                key,
+                operator,
+                operand,
+            } => self.add_operand(key, operator, operand), // Only without an index, see `write_merges`
+            Mutation::Batch { .. } => unreachable!("batches have no key"),
+        }
+        if let Some(pair) = self.data.get(&key).filter(|_| reindex).and_then(Entry::folded) {
+            for index in self.indexes.values_mut() {
+                index.insert(&key, &pair.value);
+            }
+        }
+        if let Some(kind) = kind.filter(|_| !self.watchers.is_empty()) {
+            self.notify(kind, &key);
+        }
+        if !self.transactions.is_empty() {
+            self.modified.insert(key, self.seq); // Only open transactions can conflict
+        }
+    }
+
+    /// Sends the change of `key` by the last applied mutation to the watchers of the key,
+    /// dropping the watchers that went away.
+    fn notify(&mut self, kind: EventKind, key: &[u8]) {
+        let value = match kind {
+            EventKind::Put => self
+                .data
+                .get(key)
+                .and_then(Entry::folded)
+                .map(|pair| pair.value.clone()),
+            EventKind::Delete | EventKind::Expire => None,
+        };
+        let seq = self.seq;
+        let durable_at = &self.durable_at;
+        self.watchers.retain(|subscriber| {
+            !subscriber.watches(key)
+                || subscriber.notify(
+                    Event {
+                        kind,
+                        key: key.to_vec(),
+                        value: value.clone(),
+                        seq,
+                    },
+                    durable_at.clone(),
+                )
+        });
+    }
+
+    /// Sets `key` to `value` in memory.
+    fn put(&mut self, key: Vec<u8>, value: Vec<u8>, expires_at: Option<u64>) {
+        self.keys.insert(key.clone());
+        self.merged.remove(&key);
+        self.data.insert(
+            key.clone(),
+            Entry::Pair(KV {
+                key,
+                value,
+                expires_at,
+            }),
+        );
+    }
+
+    /// Adds a merge operand to `key` in memory, to fold when the key is read.
+    fn add_operand(&mut self, key: Vec<u8>, operator: String, operand: Vec<u8>) {
+        self.keys.insert(key.clone());
+        self.merged.insert(key.clone());
+        let mut merged = match self.data.remove(&key) {
+            Some(Entry::Merged(mut merged)) => match merged.folded.take() {
+                Some(Ok(pair)) => Merged::new(key.clone(), Some(pair)), // Start over from the folded value
+                _ => merged,
+            },
+            Some(Entry::Pair(pair)) => Merged::new(key.clone(), Some(pair)),
+            None => Merged::new(key.clone(), None),
+        };
+        merged.operands.push((operator, operand));
+        self.data.insert(key, Entry::Merged(merged));
+    }
+
+    /// Returns the pairs whose key falls within `range`, in lexicographic key order.
+    ///
+    /// This is the text flavour of `scan_bytes`, for ranges written with string literals.
+    /// The returned iterator is double-ended: call `.rev()` on it to walk the range
+    /// from the greatest key down.
+    ///
+    /// # Arguments
+    /// * `range` - Any range of keys, e.g. `"a".."c"`, `"b"..` or `..`.
+    ///
+    /// # Returns
+    /// * `Ok(Scan)` an iterator over the matching pairs. An inverted range yields no pairs.
+    /// * `Err(Error::Merge)` if the operands merged into a key in the range can't be folded.
+    ///
+    /// # Example
+    /// ```rust
+    /// let store = safina_db::Store::new();
+    /// let first_ten: Vec<_> = store.scan("user:".."user;").unwrap().take(10).collect();
+    /// let last: Option<_> = store.scan(..).unwrap().rev().next();
+    /// ```
+    pub fn scan<'k, R: RangeBounds<&'k str>>(&self, range: R) -> Result<Scan<'_>> {
+        self.scan_bytes((
+            range.start_bound().map(|key| key.as_bytes()),
+            range.end_bound().map(|key| key.as_bytes()),
+        ))
+    }
+
+    /// Returns the pairs whose key falls within `range`, in bytewise key order.
+    ///
+    /// # Arguments
+    /// * `range` - Any range of byte string keys, e.g. `&b"a"[..]..&b"c"[..]`.
+    ///
+    /// # Returns
+    /// * `Ok(Scan)` a double-ended iterator over the matching pairs. An inverted range yields no pairs.
+    /// * `Err(Error::Merge)` if the operands merged into a key in the range can't be folded.
+    pub fn scan_bytes<'k, R: RangeBounds<&'k [u8]>>(&self, range: R) -> Result<Scan<'_>> {
+        let bounds = (
+            range.start_bound().map(|key| *key),
+            range.end_bound().map(|key| *key),
+        );
+        let now = now_millis();
+        let keys = if is_empty_range(&bounds) {
+            None
+        } else {
+            for key in self.merged.range::<[u8], _>(bounds) {
+                if let Some(entry) = self.data.get(key).filter(|entry| !entry.is_expired(now)) {
+                    entry.pair(&self.merge_operators)?; // Folded up front, the scan can't fail midway
+                }
+            }
+            Some(self.keys.range::<[u8], _>(bounds))
+        };
+        Ok(Scan {
+            data: &self.data,
+            keys,
+            now,
+        })
+    }
+
+    /// Returns the pairs whose key starts with `prefix`, in lexicographic key order.
+    ///
+    /// # Arguments
+    /// * `prefix` - The prefix every returned key starts with.
+    ///
+    /// # Returns
+    /// * `Ok(Scan)` a double-ended iterator over the matching pairs.
+    /// * `Err(Error::Merge)` if the operands merged into a key with the prefix can't be folded.
+    pub fn scan_prefix<P: AsRef<[u8]>>(&self, prefix: P) -> Result<Scan<'_>> {
+        let prefix = prefix.as_ref();
+        match prefix_successor(prefix) {
+            Some(end) => {
+                self.scan_bytes((Bound::Included(prefix), Bound::Excluded(end.as_slice())))
+            }
+            None => self.scan_bytes((Bound::Included(prefix), Bound::Unbounded)),
         }
     }
 
-    /// Persists the current data to the storage by serializing it and writing it to a file.
+    /// Persists a mutation through the store's backend.
     ///
-    /// This method clones the current data in the storage, serializes it into a binary format,
-    /// and writes it to the associated file. If the file operation is successful, it returns `Ok(())`.
-    /// If there is an error during the file operation, it returns an error message.
+    /// The backend is handed a way to clone the current data, for backends that need the
+    /// whole dataset, e.g. to checkpoint their log into a snapshot. The keys the mutation
+    /// replaces are left out of it if their merge operands can't be folded, so a key that
+    /// fails to fold can still be overwritten or deleted. If the store has a change
+    /// log, the change is logged together with the backend persisting it, see
+    /// `ChangeLog::append`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mutation` - The mutation about to be applied to the in-memory data.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the data is successfully persisted to the file.
-    /// * `Err(&str)` - If there is an error during the file operation, with an error message.
+    /// * `Ok(())` - If the mutation is persisted and can be applied.
+    /// * `Err(Error)` - If there is an error during the file operation.
     ///
     /// # Errors
     ///
     /// This method returns an error in the following situations:
     ///
     /// * If there is an issue with the file operation (e.g., unable to write to the file).
-    fn persist_data(&mut self) -> Result<(), &str> {
-        let d = self.data.clone(); // Clone the current data to avoid borrowing issues.
-        let mut storage = STORAGE_MUTEX.lock().unwrap();
-        let result = storage.save_file(d); // Attempt to save the cloned data to the file.
-        match result {
-            Ok(_) => Ok(()), // Return Ok if the file operation is successful.
-            Err(_) => Err("Failed to persist data."), // Return an error message if the file operation fails.
+    fn persist_data(&mut self, mutation: &Mutation) -> Result<()> {
+        self.persist_change(mutation, mutation)
+    }
+
+    /// Persists a mutation like `persist_data`, logging `change` to the change log in its
+    /// place: the same mutation with its merges folded, see `resolve_merges`.
+    fn persist_change(&mut self, mutation: &Mutation, change: &Mutation) -> Result<()> {
+        let (data, operators) = (&self.data, &self.merge_operators);
+        let replaced = replaced_keys(std::slice::from_ref(mutation));
+        let snapshot = || {
+            let mut pairs = Vec::with_capacity(data.len());
+            for entry in data.values() {
+                match entry.pair(operators) {
+                    Ok(pair) => pairs.push(pair.clone()),
+                    Err(_) if replaced.contains(entry.key()) => {} // Its value is replaced anyway
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(pairs)
+        }; // Clone the current data on demand.
+        let backend = &mut self.backend;
+        let mut persist = || backend.persist(mutation, &snapshot); // The mutation must not be applied if this fails.
+        let mut syncs: Vec<SyncTicket> = match &self.change_log {
+            Some(change_log) => {
+                change_log.append(change, persist)?.into_iter().collect() // Both or neither
+            }
+            None => {
+                persist()?;
+                Vec::new()
+            }
+        };
+        syncs.extend(self.backend.take_sync());
+        if !self.watchers.is_empty() {
+            self.durable_at = syncs.iter().map(SyncTicket::watermark).collect();
+        }
+        if self.defer_syncs {
+            self.pending.extend(syncs);
+        } else {
+            syncs.into_iter().try_for_each(SyncTicket::wait)?;
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over a range of the store, in key order. Returned by `Store::scan`
+/// and `Store::scan_prefix`. Pairs that had expired when the scan started are skipped.
+pub struct Scan<'a> {
+    data: &'a HashMap<Vec<u8>, Entry>,
+    keys: Option<btree_set::Range<'a, Vec<u8>>>,
+    now: u64,
+}
+
+impl<'a> Iterator for Scan<'a> {
+    type Item = &'a KV;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let key = self.keys.as_mut()?.next()?;
+            match self.data.get(key) {
+                Some(entry) if entry.is_expired(self.now) => continue,
+                entry => return entry.and_then(Entry::folded), // Folded by `scan_bytes`
+            }
+        }
+    }
+}
+
+impl DoubleEndedIterator for Scan<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            let key = self.keys.as_mut()?.next_back()?;
+            match self.data.get(key) {
+                Some(entry) if entry.is_expired(self.now) => continue,
+                entry => return entry.and_then(Entry::folded), // Folded by `scan_bytes`
+            }
+        }
+    }
+}
+
+impl Entry {
+    fn key(&self) -> &[u8] {
+        match self {
+            Entry::Pair(pair) => &pair.key,
+            Entry::Merged(merged) => &merged.key,
+        }
+    }
+
+    fn expires_at(&self) -> Option<u64> {
+        match self {
+            Entry::Pair(pair) => pair.expires_at,
+            Entry::Merged(merged) => merged.expires_at,
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at().is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn set_expires_at(&mut self, expires_at: Option<u64>) {
+        match self {
+            Entry::Pair(pair) => pair.expires_at = expires_at,
+            Entry::Merged(merged) => {
+                merged.expires_at = expires_at;
+                if let Some(Ok(pair)) = merged.folded.get_mut() {
+                    pair.expires_at = expires_at;
+                }
+            }
+        }
+    }
+
+    /// Returns the pair, folding the merge operands of the key with `operators` the first
+    /// time it is called.
+    ///
+    /// # Returns
+    /// * `Ok(&KV)` the pair.
+    /// * `Err(Error::Merge)` if the operands can't be folded.
+    fn pair(&self, operators: &MergeOperators) -> Result<&KV> {
+        match self {
+            Entry::Pair(pair) => Ok(pair),
+            Entry::Merged(merged) => merged
+                .folded
+                .get_or_init(|| merged.fold(operators))
+                .as_ref()
+                .map_err(|message| Error::Merge(message.clone())),
+        }
+    }
+
+    /// Returns the pair if it needs no folding, or was already folded by `pair`.
+    fn folded(&self) -> Option<&KV> {
+        match self {
+            Entry::Pair(pair) => Some(pair),
+            Entry::Merged(merged) => merged.folded.get()?.as_ref().ok(),
+        }
+    }
+}
+
+impl Merged {
+    /// Starts merging into `pair`, or into a missing key.
+    fn new(key: Vec<u8>, pair: Option<KV>) -> Box<Merged> {
+        Box::new(Merged {
+            key,
+            expires_at: pair.as_ref().and_then(|pair| pair.expires_at),
+            base: pair.map(|pair| pair.value),
+            operands: Vec::new(),
+            folded: OnceLock::new(),
+        })
+    }
+
+    /// Folds the operands into the base value, see `MergeOperator::merge`.
+    fn fold(&self, operators: &MergeOperators) -> std::result::Result<KV, String> {
+        let mut value = self.base.clone();
+        for (operator, operand) in &self.operands {
+            value = match operators.fold(operator, &self.key, value.as_deref(), operand) {
+                Ok(value) => Some(value),
+                Err(Error::Merge(message)) => return Err(message),
+                Err(e) => return Err(e.to_string()),
+            };
+        }
+        Ok(KV {
+            key: self.key.clone(),
+            value: value.unwrap_or_default(),
+            expires_at: self.expires_at,
+        })
+    }
+}
+
+/// Returns the keys the first write of `mutations` to puts or deletes, without reading their
+/// current value.
+fn replaced_keys(mutations: &[Mutation]) -> HashSet<&[u8]> {
+    let mut first = HashMap::new();
+    first_writes(mutations, &mut first);
+    first
+        .into_iter()
+        .filter(|(_, replaces)| *replaces)
+        .map(|(key, _)| key)
+        .collect()
+}
+
+/// Records whether the first write of `mutations` to each key replaces its value, nested
+/// batches included.
+fn first_writes<'m>(mutations: &'m [Mutation], first: &mut HashMap<&'m [u8], bool>) {
+    for mutation in mutations {
+        match mutation {
+            Mutation::Batch { mutations } => first_writes(mutations, first),
+            Mutation::Put { key, .. }
+            | Mutation::PutExpiring { key, .. }
+            | Mutation::Delete { key } => {
+                first.entry(key.as_slice()).or_insert(true);
+            }
+            Mutation::Expire { key, .. } | Mutation::Merge { key, .. } => {
+                first.entry(key.as_slice()).or_insert(false);
+            }
+        }
+    }
+}
+
+/// Decrements the count of `seq` in a registry of open transactions or snapshots.
+fn unregister(registry: &mut BTreeMap<u64, usize>, seq: u64) {
+    if let Some(count) = registry.get_mut(&seq) {
+        *count -= 1;
+        if *count == 0 {
+            registry.remove(&seq);
+        }
+    }
+}
+
+/// Returns the current time in milliseconds since the Unix epoch, the unit of `KV::expires_at`.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_the_epoch| since_the_epoch.as_millis() as u64)
+}
+
+/// Returns the current time in microseconds since the Unix epoch.
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_the_epoch| since_the_epoch.as_micros() as u64)
+}
+
+/// Returns the expiry time of a pair inserted now with the given time to live.
+pub(crate) fn expiry_after(ttl: Duration) -> u64 {
+    now_millis().saturating_add(ttl.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+/// Returns `true` if no key can fall within `range`.
+///
+/// `BTreeSet::range` panics on such ranges, while a scan should simply come back empty.
+fn is_empty_range(bounds: &(Bound<&[u8]>, Bound<&[u8]>)) -> bool {
+    match *bounds {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end))
+        | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        _ => false,
+    }
+}
+
+/// Returns the smallest byte string greater than every byte string starting with `prefix`,
+/// or `None` if there is no such string (empty prefix, or only `0xFF` bytes).
+pub(crate) fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = prefix.to_vec();
+    while let Some(last) = bytes.pop() {
+        if last < u8::MAX {
+            bytes.push(last + 1);
+            return Some(bytes);
         }
     }
+    None
 }
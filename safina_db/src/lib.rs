@@ -1,31 +1,30 @@
-use once_cell::sync::Lazy;
-use std::sync::Mutex;
-
+#[cfg(feature = "async")]
+pub mod async_db;
+pub mod batch;
 pub mod cli;
+pub mod data_dir;
+pub mod database;
+pub mod error;
+pub mod index;
+pub mod keyspace;
 pub mod kv_store;
+pub mod merge;
+mod shards;
+pub mod snapshot;
 pub mod storage;
+pub mod transaction;
+pub mod watch;
 
+#[cfg(feature = "async")]
+pub use crate::async_db::AsyncDatabase;
+pub use crate::batch::WriteBatch;
+pub use crate::data_dir::DataDir;
+pub use crate::database::{Backend, Database, Options};
+pub use crate::error::{Error, Result};
+pub use crate::keyspace::{Keyspace, KeyspaceOptions};
 pub use crate::kv_store::Store;
+pub use crate::merge::MergeOperator;
+pub use snapshot::Snapshot;
 pub use storage::Storage;
-
-/// A globally accessible, thread-safe, lazily initialized instance of `Store`.
-///
-/// # Details
-/// - `static STORE`: Declares a static variable named `STORE` that is globally accessible.
-/// - `Lazy<Mutex<Store>>`: Uses the `Lazy` type from the `once_cell` crate to defer the initialization
-///   of the `Store` instance until it is first accessed. The `Mutex` ensures that access to the `Store`
-///   is thread-safe by allowing only one thread to access it at a time.
-/// - `Lazy::new(|| Mutex::new(Store::new()))`: This line initializes the `STORE` variable using a closure
-///   (`||` syntax). The closure creates a new `Mutex` which wraps a new instance of `Store`. The `Lazy::new`
-///   function ensures that this initialization happens only once, no matter how many times `STORE` is accessed.
-///
-/// # Example
-/// ```rust
-/// // Accessing the global STORE instance
-/// let mut store = safina_db::STORE_MUTEX.lock().unwrap();
-/// store.insert("key", "value");
-/// ```
-pub static STORE_MUTEX: Lazy<Mutex<Store>> = Lazy::new(|| Mutex::new(Store::new()));
-
-pub static STORAGE_MUTEX: Lazy<Mutex<Storage>> =
-    Lazy::new(|| Mutex::new(Storage::new(Some("default_db"))));
+pub use transaction::{IsolationLevel, Transaction};
+pub use watch::{Event, EventKind, Watcher};
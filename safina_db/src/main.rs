@@ -1,22 +1,31 @@
-use safina_db::{cli, STORAGE_MUTEX, STORE_MUTEX};
+use safina_db::data_dir::DEFAULT_DATABASE;
+use safina_db::{cli, DataDir, Options};
 
+/// Where the CLI kept its database before data directories.
+const LEGACY_DATABASE: &str = "db";
 
 fn main() -> Result<(), String> {
-    {
-        let mut store = STORE_MUTEX.lock().unwrap();
-        println!("- Loading data...");
-        match STORAGE_MUTEX.lock().unwrap().load_file(Some("db")) {
-            Ok(data) => store.data = data,
-            Err(err) => {
-                println!("Invalid: {}", err);
-            }
-        }
-        println!("- Data overview:");
-        for d in &store.data {
-            println!("      - \"{}\" : \"{}\"", d.key, d.value)
+    println!("- Loading data...");
+    let dir = DataDir::open("data", Options::default()).map_err(|e| format!("Invalid: {}", e))?;
+    match dir.import_legacy(LEGACY_DATABASE) {
+        Ok(true) => println!("- Imported the database '{LEGACY_DATABASE}' into data/{DEFAULT_DATABASE}"),
+        Ok(false) => {}
+        Err(e) => {
+            return Err(format!(
+                "Refusing to start: the database '{LEGACY_DATABASE}' can't be imported into data/{DEFAULT_DATABASE} ({e}), move or delete its files first"
+            ))
         }
     }
+    let db = dir
+        .database(DEFAULT_DATABASE)
+        .map_err(|e| format!("Invalid: {}", e))?;
+    println!("- Data overview:");
+    for d in db.scan(..).map_err(|e| format!("Invalid: {}", e))? {
+        println!("      - \"{}\" : \"{}\"", d.key_str(), d.value_str())
+    }
+    drop(db);
 
-    cli::run().unwrap();
-    return Ok(());
+    cli::run(&dir).map_err(|e| e.to_string())?;
+    dir.close().map_err(|e| e.to_string())?;
+    Ok(())
 }
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+
+/// Folds an operand into the value of a key, for read-modify-write updates that don't need
+/// to read the value first, e.g. incrementing a counter or appending to a list.
+///
+/// A merge is persisted and kept in memory as its operand rather than as the whole new
+/// value (the write-ahead log of `Storage` and the segments of `Bitcask` record operands),
+/// and the operands are folded into the value when the key is read, or when the backend
+/// compacts them into a snapshot or a merged segment. The fold must therefore give the same
+/// result every time it is run on the same input.
+///
+/// Operators are looked up by the name they are added under in `MergeOperators`, which is
+/// what the log records.
+pub trait MergeOperator: Debug + Send + Sync {
+    /// Folds `operand` into the current value of `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key being merged.
+    /// * `existing` - The current value, `None` if the key doesn't exist.
+    /// * `operand` - The operand to fold in.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u8>)` - The new value of the key.
+    /// * `Err(Error)` - If the operand can't be folded into the value, nothing is written.
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Name of the `Counter` operator, used by `Store::incr_by`.
+pub const COUNTER: &str = "counter";
+
+/// Adds a signed integer to the value, both written as decimal text. A missing key counts
+/// as `0`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counter;
+
+/// Appends the operand to the value, e.g. to build a list out of separated items. A missing
+/// key counts as empty.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Append;
+
+/// Keeps the greater of the value and the operand, both signed integers written as decimal
+/// text. A missing key takes the operand.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Max;
+
+impl MergeOperator for Counter {
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Result<Vec<u8>> {
+        let current = existing.map_or(Ok(0), |value| parse_integer(key, value))?;
+        let delta = parse_integer(key, operand)?;
+        let sum = current.checked_add(delta).ok_or_else(|| {
+            Error::Merge(format!(
+                "counter '{}' overflows",
+                String::from_utf8_lossy(key)
+            ))
+        })?;
+        Ok(sum.to_string().into_bytes())
+    }
+}
+
+impl MergeOperator for Append {
+    fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Result<Vec<u8>> {
+        let mut value = existing.unwrap_or_default().to_vec();
+        value.extend_from_slice(operand);
+        Ok(value)
+    }
+}
+
+impl MergeOperator for Max {
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Result<Vec<u8>> {
+        let candidate = parse_integer(key, operand)?;
+        match existing {
+            Some(value) if parse_integer(key, value)? >= candidate => Ok(value.to_vec()),
+            _ => Ok(candidate.to_string().into_bytes()),
+        }
+    }
+}
+
+/// The merge operators a store folds operands with, by the name a merge refers to them by.
+///
+/// The log records that name rather than the operator itself, so a database must be opened
+/// with every operator its log may hold operands of, see `Options::merge_operators`.
+/// `counter`, `append` and `max` are always there, unless replaced.
+#[derive(Debug, Clone)]
+pub struct MergeOperators {
+    operators: BTreeMap<String, Arc<dyn MergeOperator>>,
+}
+
+impl Default for MergeOperators {
+    fn default() -> Self {
+        let mut operators: BTreeMap<String, Arc<dyn MergeOperator>> = BTreeMap::new();
+        operators.insert(COUNTER.to_string(), Arc::new(Counter));
+        operators.insert("append".to_string(), Arc::new(Append));
+        operators.insert("max".to_string(), Arc::new(Max));
+        MergeOperators { operators }
+    }
+}
+
+impl MergeOperators {
+    /// Creates the set of the built-in operators.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `operator` under `name`, replacing any operator already there.
+    ///
+    /// # Example
+    /// ```rust
+    /// use safina_db::merge::{MergeOperator, MergeOperators};
+    /// use safina_db::Store;
+    ///
+    /// #[derive(Debug)]
+    /// struct Min;
+    ///
+    /// impl MergeOperator for Min {
+    ///     fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> safina_db::Result<Vec<u8>> {
+    ///         Ok(existing.map_or(operand, |value| value.min(operand)).to_vec())
+    ///     }
+    /// }
+    ///
+    /// let mut store = Store::new();
+    /// store.set_merge_operators(MergeOperators::new().with("min", Min));
+    /// store.merge("lowest", "min", "b").unwrap();
+    /// store.merge("lowest", "min", "a").unwrap();
+    /// assert_eq!(store.get("lowest").unwrap(), Some(b"a".to_vec()));
+    /// ```
+    pub fn with<O: MergeOperator + 'static>(mut self, name: &str, operator: O) -> Self {
+        self.operators.insert(name.to_string(), Arc::new(operator));
+        self
+    }
+
+    /// Returns the operator added under `name`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(&dyn MergeOperator)` - The operator.
+    /// * `Err(Error::Merge)` - If no operator has this name.
+    pub fn get(&self, name: &str) -> Result<&dyn MergeOperator> {
+        self.operators
+            .get(name)
+            .map(|operator| operator.as_ref())
+            .ok_or_else(|| Error::Merge(format!("no merge operator named '{name}'")))
+    }
+
+    /// Folds `operand` into `existing` with the operator added under `name`.
+    pub fn fold(
+        &self,
+        name: &str,
+        key: &[u8],
+        existing: Option<&[u8]>,
+        operand: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.get(name)?.merge(key, existing, operand)
+    }
+}
+
+/// Parses a signed integer written as decimal text, e.g. the value of a counter.
+///
+/// # Returns
+///
+/// * `Ok(i64)` - The integer.
+/// * `Err(Error::Merge)` - If the value of `key` is not an integer.
+pub(crate) fn parse_integer(key: &[u8], value: &[u8]) -> Result<i64> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .ok_or_else(|| {
+            Error::Merge(format!(
+                "value of '{}' is not an integer",
+                String::from_utf8_lossy(key)
+            ))
+        })
+}
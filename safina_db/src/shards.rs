@@ -0,0 +1,221 @@
+use std::ops::Bound;
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::error::{Error, Result};
+use crate::kv_store::{Store, KV};
+use crate::storage::commit_log::{CommitLog, Intent};
+use crate::storage::{Mutation, WAL_CHECKPOINT_THRESHOLD};
+
+/// Key of the commit marker of a shard: the number of the last batch spanning several shards
+/// it persisted its part of, see `CommitLog`. Reserved like the keys of the keyspaces.
+pub(crate) const COMMIT_KEY: &[u8] = b"\x00\x00commit";
+
+/// The stores a database is split into, each behind its own lock.
+///
+/// Every key belongs to exactly one shard, picked by a CRC32 hash of the key. The hash is
+/// stable across runs and platforms, so a key is found in the shard that persisted it.
+/// Operations on keys of different shards run in parallel.
+///
+/// Operations that lock several shards always lock them in index order, so they can't
+/// deadlock each other.
+///
+/// Batches spanning several shards go through a `CommitLog`, if the shards have one, so a
+/// crash keeps either every part of such a batch or none of them.
+#[derive(Debug)]
+pub(crate) struct Shards {
+    stores: Vec<RwLock<Store>>,
+    commit_log: Option<Mutex<CommitLog>>,
+}
+
+impl Shards {
+    /// Wraps the given stores, in shard order, without a commit log.
+    pub(crate) fn new(stores: Vec<Store>) -> Shards {
+        Shards {
+            stores: stores.into_iter().map(RwLock::new).collect(),
+            commit_log: None,
+        }
+    }
+
+    /// Wraps the given stores, in shard order, committing the batches spanning several of
+    /// them through `commit_log`.
+    ///
+    /// The `intents` the log held when opened are first applied again to every store whose
+    /// commit marker shows it lost its part, then the stores are flushed and the log emptied.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Shards)` - The shards, each holding its part of every batch of the log.
+    /// * `Err(Error::Corruption)` - If a commit marker or a batch doesn't fit the stores.
+    /// * `Err(Error)` - An error message if a part could not be persisted again.
+    pub(crate) fn with_commit_log(
+        mut stores: Vec<Store>,
+        mut commit_log: CommitLog,
+        intents: Vec<Intent>,
+    ) -> Result<Shards> {
+        if !intents.is_empty() {
+            for intent in intents {
+                for (index, mutations) in intent.parts {
+                    let store = stores.get_mut(index).ok_or_else(|| {
+                        Error::Corruption(format!("commit {} names shard {index}", intent.id))
+                    })?;
+                    if commit_marker(store)? < intent.id {
+                        store.apply_batch(mutations)?; // The shard lost its part, and nothing after it
+                    }
+                }
+            }
+            for store in stores.iter_mut() {
+                store.flush()?;
+                store.take_syncs(); // Flushing synced them
+            }
+            commit_log.reset()?;
+        }
+        for store in &stores {
+            commit_log.skip_to(commit_marker(store)?);
+        }
+        Ok(Shards {
+            stores: stores.into_iter().map(RwLock::new).collect(),
+            commit_log: Some(Mutex::new(commit_log)),
+        })
+    }
+
+    /// Returns the number of shards.
+    pub(crate) fn len(&self) -> usize {
+        self.stores.len()
+    }
+
+    /// Returns the index of the shard `key` belongs to.
+    pub(crate) fn index(&self, key: &[u8]) -> usize {
+        crc32fast::hash(key) as usize % self.stores.len()
+    }
+
+    /// Locks shard `index` for shared access.
+    pub(crate) fn read(&self, index: usize) -> Result<RwLockReadGuard<'_, Store>> {
+        self.stores[index].read().map_err(|_| Error::Poisoned)
+    }
+
+    /// Locks shard `index` for exclusive access.
+    pub(crate) fn write(&self, index: usize) -> Result<RwLockWriteGuard<'_, Store>> {
+        self.stores[index].write().map_err(|_| Error::Poisoned)
+    }
+
+    /// Locks every shard for exclusive access, e.g. to register a transaction in all of them
+    /// at the same point in time.
+    pub(crate) fn write_all(&self) -> Result<Vec<RwLockWriteGuard<'_, Store>>> {
+        (0..self.len()).map(|index| self.write(index)).collect()
+    }
+
+    /// Persists and applies `parts`, the mutations of each locked shard with its index, as
+    /// one atomic unit.
+    ///
+    /// With a commit log and more than one shard to write, the batch is logged first and
+    /// each part sets the commit marker of its shard, see `CommitLog`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once every part is persisted and applied. The syncs the stores deferred
+    ///   are left to the caller, see `Store::take_syncs`.
+    /// * `Err(Error)` - If the batch could not be logged, in which case nothing is written, or
+    ///   a shard could not persist its part. The shards before it keep theirs, and the others
+    ///   get theirs back from the log when the database is next opened.
+    pub(crate) fn commit(&self, parts: Vec<(&mut Store, usize, Vec<Mutation>)>) -> Result<()> {
+        let mut parts: Vec<_> = parts
+            .into_iter()
+            .filter(|(_, _, mutations)| !mutations.is_empty())
+            .collect();
+        let commit_log = match &self.commit_log {
+            Some(commit_log) if parts.len() > 1 => commit_log,
+            _ => {
+                return parts
+                    .into_iter()
+                    .try_for_each(|(store, _, mutations)| store.apply_batch(mutations))
+            }
+        };
+        {
+            let mut commit_log = commit_log.lock().map_err(|_| Error::Poisoned)?;
+            let id = commit_log.next_id();
+            for (_, _, mutations) in parts.iter_mut() {
+                mutations.push(Mutation::put(COMMIT_KEY, &id.to_le_bytes(), None));
+            }
+            let logged = parts
+                .iter()
+                .map(|(_, index, mutations)| (*index, mutations.as_slice()))
+                .collect();
+            commit_log.append(id, logged)?;
+        } // Parts of other shards can be logged meanwhile
+        for (store, _, mutations) in parts {
+            if let Err(e) = store.apply_batch(mutations) {
+                commit_log
+                    .lock()
+                    .map_err(|_| Error::Poisoned)?
+                    .mark_unfinished();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes every shard, see `Store::flush`, then empties the commit log, all of its
+    /// batches being synced.
+    pub(crate) fn flush(&self) -> Result<()> {
+        let mut stores = self.write_all()?;
+        for store in stores.iter_mut() {
+            store.flush()?;
+        }
+        match &self.commit_log {
+            Some(commit_log) => commit_log.lock().map_err(|_| Error::Poisoned)?.reset(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes every shard once the commit log has grown past `WAL_CHECKPOINT_THRESHOLD`,
+    /// so it can be emptied. Called without holding any shard.
+    pub(crate) fn trim_commit_log(&self) -> Result<()> {
+        let full = match &self.commit_log {
+            Some(commit_log) => {
+                commit_log.lock().map_err(|_| Error::Poisoned)?.size() > WAL_CHECKPOINT_THRESHOLD
+            }
+            None => false,
+        };
+        if full {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Runs `scan` on every shard in turn and merges the pairs it returns in key order.
+    pub(crate) fn merge<F>(&self, scan: F) -> Result<Vec<KV>>
+    where
+        F: Fn(usize, &Store) -> Result<Vec<KV>>,
+    {
+        let mut pairs = Vec::new();
+        for index in 0..self.len() {
+            pairs.extend(scan(index, &*self.read(index)?)?);
+        }
+        if self.len() > 1 {
+            pairs.sort_unstable_by(|a, b| a.key.cmp(&b.key)); // Each shard holds distinct keys
+        }
+        Ok(pairs)
+    }
+}
+
+/// Returns the commit marker of `store`, `0` if it never persisted a part of a batch.
+fn commit_marker(store: &Store) -> Result<u64> {
+    let Some(marker) = store.get(COMMIT_KEY)? else {
+        return Ok(0);
+    };
+    let marker = marker
+        .as_slice()
+        .try_into()
+        .map_err(|_| Error::Corruption("invalid commit marker".to_string()))?;
+    Ok(u64::from_le_bytes(marker))
+}
+
+/// Turns a range of keys into byte bounds, which are `Copy` and can be reused for each shard.
+pub(crate) fn byte_bounds<'k, R: std::ops::RangeBounds<&'k [u8]>>(
+    range: &R,
+) -> (Bound<&'k [u8]>, Bound<&'k [u8]>) {
+    (
+        range.start_bound().map(|key| *key),
+        range.end_bound().map(|key| *key),
+    )
+}
@@ -0,0 +1,112 @@
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::keyspace;
+use crate::kv_store::{now_millis, prefix_successor, KV};
+use crate::shards::{byte_bounds, Shards};
+
+/// A frozen, point-in-time view of a database.
+///
+/// A snapshot reads the data as it was when it was taken, whatever is written afterwards:
+/// it only holds a shared lock on a shard for the duration of each read, so writers are
+/// never blocked by it. Pairs are considered expired as of the moment the snapshot was taken.
+///
+/// The versions a snapshot may read are kept by the store until the snapshot is dropped.
+///
+/// Snapshots are taken with `Database::snapshot`.
+#[derive(Debug)]
+pub struct Snapshot {
+    shards: Arc<Shards>,
+    /// The number the snapshot was taken at in each shard.
+    seqs: Vec<u64>,
+    taken_at: u64,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of `shards`, locking all of them so it is the same point in time
+    /// in every shard.
+    pub(crate) fn take(shards: Arc<Shards>) -> Result<Snapshot> {
+        let seqs = shards
+            .write_all()?
+            .iter_mut()
+            .map(|store| store.take_snapshot())
+            .collect();
+        Ok(Snapshot {
+            shards,
+            seqs,
+            taken_at: now_millis(),
+        })
+    }
+
+    /// Returns the number of mutations the snapshot sees, across every shard.
+    pub fn seq(&self) -> u64 {
+        self.seqs.iter().sum()
+    }
+
+    /// Returns the value of `key` as of the snapshot.
+    ///
+    /// # Returns
+    /// * `Ok(Some(Vec<u8>))` - The value if the key existed.
+    /// * `Ok(None)` - If the key didn't exist or had expired.
+    /// * `Err(Error::Merge)` - If the operands merged into the key can't be folded.
+    /// * `Err(Error::Poisoned)` - If the store lock is poisoned.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>> {
+        let key = key.as_ref();
+        let index = self.shards.index(key);
+        let store = self.shards.read(index)?;
+        Ok(store
+            .get_at(key, self.seqs[index])?
+            .filter(|pair| !pair.is_expired(self.taken_at))
+            .map(|pair| pair.value.clone()))
+    }
+
+    /// Returns a copy of the pairs within `range` as of the snapshot, in key order.
+    pub fn scan<'k, R: RangeBounds<&'k str>>(&self, range: R) -> Result<Vec<KV>> {
+        self.scan_bounds((
+            range.start_bound().map(|key| key.as_bytes()),
+            range.end_bound().map(|key| key.as_bytes()),
+        ))
+    }
+
+    /// Returns a copy of the pairs within a range of byte string keys as of the snapshot.
+    pub fn scan_bytes<'k, R: RangeBounds<&'k [u8]>>(&self, range: R) -> Result<Vec<KV>> {
+        self.scan_bounds(byte_bounds(&range))
+    }
+
+    /// Returns a copy of the pairs whose key starts with `prefix` as of the snapshot.
+    pub fn scan_prefix<P: AsRef<[u8]>>(&self, prefix: P) -> Result<Vec<KV>> {
+        let prefix = prefix.as_ref();
+        match prefix_successor(prefix) {
+            Some(end) => {
+                self.scan_bounds((Bound::Included(prefix), Bound::Excluded(end.as_slice())))
+            }
+            None => self.scan_bounds((Bound::Included(prefix), Bound::Unbounded)),
+        }
+    }
+
+    /// The keys of the keyspaces are skipped unless the range starts among them, as with
+    /// `Database::scan_bytes`.
+    fn scan_bounds(&self, bounds: (Bound<&[u8]>, Bound<&[u8]>)) -> Result<Vec<KV>> {
+        let hide = keyspace::hides_reserved(bounds.0);
+        self.shards.merge(|index, store| {
+            Ok(store
+                .scan_at(bounds, self.seqs[index])?
+                .into_iter()
+                .filter(|pair| !pair.is_expired(self.taken_at))
+                .filter(|pair| !hide || !keyspace::is_reserved(&pair.key))
+                .cloned()
+                .collect())
+        })
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        for (index, seq) in self.seqs.iter().enumerate() {
+            if let Ok(mut store) = self.shards.write(index) {
+                store.release_snapshot(*seq);
+            }
+        }
+    }
+}
@@ -1,13 +1,42 @@
-use std::error::Error;
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::Read;
 
+use crate::error::{Error, Result};
 use crate::kv_store::KV;
+use crate::merge::MergeOperators;
+
+pub mod backend;
+pub mod bitcask;
+pub mod change_log;
+pub(crate) mod commit_log;
+pub mod durability;
+pub mod json;
+pub mod memory;
+pub mod snapshot;
+pub mod wal;
+
+pub use backend::StorageBackend;
+pub use change_log::{Change, ChangeLog, ChangeLogOptions};
+pub use durability::{Durability, SyncTicket};
+pub use json::JsonStorage;
+pub use memory::MemoryBackend;
+pub use wal::Mutation;
+use wal::Wal;
+
+/// Size in bytes past which the write-ahead log is folded into a fresh snapshot.
+pub const WAL_CHECKPOINT_THRESHOLD: u64 = 4 * 1024 * 1024;
 
 #[derive(Debug)]
 pub struct Storage {
     file_path: Option<String>,
     pub file: Option<File>,
+    wal: Option<Wal>,
+    durability: Durability,
+    /// The sync the last logged mutation waits for, see `StorageBackend::take_sync`.
+    pending: Option<SyncTicket>,
+    /// Folds the merge operands of the log when it is replayed.
+    merge_operators: MergeOperators,
 }
 
 impl Storage {
@@ -21,14 +50,43 @@ impl Storage {
     ///
     /// * A new instance of Storage.
     pub fn new(file_path: Option<&str>) -> Self {
+        Self::with_durability(file_path, Durability::default())
+    }
+
+    /// Creates a new Storage instance syncing its write-ahead log as `durability` says.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - An optional string slice that holds the file path.
+    /// * `durability` - When logged mutations are synced to disk.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance of Storage.
+    pub fn with_durability(file_path: Option<&str>, durability: Durability) -> Self {
         Storage {
             file_path: file_path.map(|path| path.to_string()), // Convert the file path to a String and store it in the struct
             file: None, // Initialize the file as None
+            wal: None, // The log is opened alongside the file in `load_file`
+            durability,
+            pending: None,
+            merge_operators: MergeOperators::default(),
         }
     }
 
+    /// Makes the storage fold the merge operands of its log with `operators` when it is
+    /// replayed, see `Options::merge_operators`.
+    pub fn with_merge_operators(mut self, operators: MergeOperators) -> Self {
+        self.merge_operators = operators;
+        self
+    }
+
     /// Loads the file from the specified or existing file path and deserializes the content.
     ///
+    /// The write-ahead log stored next to the file (`<path>.wal`) is then replayed on top of
+    /// the snapshot, folding its merge operands, so every mutation acknowledged before a
+    /// crash is recovered.
+    ///
     /// # Arguments
     ///
     /// * `file_path` - An optional string slice that holds the file path.
@@ -36,15 +94,31 @@ impl Storage {
     /// # Returns
     ///
     /// * `Ok(Vec<KV>)` - A vector of deserialized KV structs if the operation is successful.
-    /// * `Box<dyn Error>>` - An error message if the operation fails.
-    pub fn load_file(&mut self, file_path: Option<&str>) -> Result<Vec<KV>, Box<dyn Error>> {
+    /// * `Err(Error)` - An error message if the operation fails.
+    pub fn load_file(&mut self, file_path: Option<&str>) -> Result<Vec<KV>> {
+        let (pairs, mutations) = self.open_files(file_path)?;
+        let mut data = by_key(pairs);
+        for mutation in mutations {
+            apply(&mut data, mutation, &self.merge_operators)?; // Replay the log on top of the snapshot, folding merge operands
+        }
+        Ok(into_pairs(data)) // Return the deserialized data
+    }
+
+    /// Opens the snapshot file and its write-ahead log, and reads both back.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Vec<KV>, Vec<Mutation>))` - The snapshot, and the logged mutations it doesn't
+    ///   cover yet, in order.
+    /// * `Err(Error)` - An error message if the operation fails.
+    fn open_files(&mut self, file_path: Option<&str>) -> Result<(Vec<KV>, Vec<Mutation>)> {
         match file_path {
             Some(path) => {
                 self.file_path = Some(path.to_string()); // Set the file path if provided
             }
             None => {
                 if self.file_path.is_none() {
-                    return Err("No file path set.".into()); 
+                    return Err(Error::NoPath);
                 }
             }
         }
@@ -55,25 +129,68 @@ impl Storage {
                 .read(true) // Open the file for reading
                 .write(true) // Open the file for writing
                 .create(true) // Create the file if it doesn't exist
+                .truncate(false) // Keep the existing snapshot
                 .open(path)? // Open the file at the specified path
         );
+        let _ = std::fs::remove_file(snapshot::temp_path(path)); // Drop a snapshot a crash left half written
+        let (wal, mutations) = Wal::open(&format!("{path}.wal"), self.durability)?; // Open the log and read back its records
+        self.wal = Some(wal);
 
         if let Some(ref mut file) = self.file {
             let mut buffer = Vec::new(); // Create a buffer to store file contents
-            // file.seek(SeekFrom::Start(0))?; // TODO :doc
             file.read_to_end(&mut buffer)?; // Read the file content into the buffer
-            if buffer.is_empty() {
-                return Ok(Vec::new())
+            let (pairs, generation) = snapshot::decode(&buffer)?; // Any format, with or without expiry
+            let wal = self.wal.as_mut().expect("opened above");
+            if wal.generation() < generation {
+                wal.reset(generation)?; // A crash hit `save_file` before the log was emptied, the snapshot covers it
+                return Ok((pairs, Vec::new()));
             }
-            let data: Vec<KV> = bincode::deserialize(&buffer)?;
-            return Ok(data); // Return the deserialized data
+            Ok((pairs, mutations))
         } else {
-           return Err("No file open to read data.".into())
+            Err(Error::Closed) // Unreachable, the file was just opened
         }
     }
 
+    /// Appends a mutation to the write-ahead log, syncing it to disk as the durability mode
+    /// says.
+    ///
+    /// With `Durability::GroupCommit` the sync is deferred: it is handed out by `take_sync`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mutation` - The mutation to record.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the mutation can be acknowledged.
+    /// * `Err(Error)` - An error message if the log is not open or the write fails.
+    pub fn append_log(&mut self, mutation: &Mutation) -> Result<()> {
+        match self.wal {
+            Some(ref mut wal) => {
+                self.pending = wal.append(mutation)?;
+                Ok(())
+            }
+            None if self.file_path.is_none() => Err(Error::NoPath),
+            None => Err(Error::Closed), // Not loaded yet, or already closed
+        }
+    }
+
+    /// Returns `true` once the write-ahead log has grown past `WAL_CHECKPOINT_THRESHOLD`
+    /// and should be folded into a snapshot with `save_file`.
+    pub fn needs_checkpoint(&self) -> bool {
+        self.wal
+            .as_ref()
+            .is_some_and(|wal| wal.size() >= WAL_CHECKPOINT_THRESHOLD)
+    }
+
     /// Saves the provided data to the file by serializing it into binary format.
     ///
+    /// The snapshot is written with `snapshot::write_atomic`, so the file on disk always holds
+    /// either the previous complete snapshot or the new one. Since the snapshot then holds every
+    /// logged mutation, the write-ahead log is emptied. The snapshot records the generation the
+    /// emptied log starts: if a crash hits before the log is emptied, `load_file` knows the
+    /// snapshot already covers its records, and doesn't fold merge operands in twice.
+    ///
     /// # Arguments
     ///
     /// * `data` - A vector of KV structs to be serialized and saved.
@@ -81,17 +198,170 @@ impl Storage {
     /// # Returns
     ///
     /// * `Ok(())` - If the operation is successful.
-    /// * `Err(Box<dyn Error>)` - An error message if the operation fails.
-    pub fn save_file(&mut self, data: Vec<KV>) -> Result<(), Box<dyn Error>> {
-        if let Some(ref mut file) = self.file {
-            let buffer: Vec<u8> = bincode::serialize(&data)?; // Serialize the data into a binary buffer
-            file.set_len(0)?; // Clear the content of the file
-            file.seek(SeekFrom::Start(0))?; // Move the cursor to the beginning of the file
-            file.write_all(&buffer)?; // Write the binary buffer to the file
-            file.sync_all()?; // Sync all changes to the file
+    /// * `Err(Error)` - An error message if the operation fails.
+    pub fn save_file(&mut self, data: Vec<KV>) -> Result<()> {
+        if let (Some(_), Some(path)) = (&self.file, &self.file_path) {
+            let generation = self.wal.as_ref().map_or(0, Wal::generation) + 1; // The log following the snapshot
+            let buffer: Vec<u8> = snapshot::encode(&data, generation)?; // Serialize the data into a binary buffer
+            snapshot::write_atomic(path, &buffer)?; // Swap the new snapshot in with a temp file + rename
+            self.file = Some(OpenOptions::new().read(true).write(true).open(path)?); // Follow the renamed file
+            if let Some(ref mut wal) = self.wal {
+                wal.reset(generation)?; // The snapshot now covers everything the log held
+            }
             Ok(())
+        } else if self.file_path.is_none() {
+            Err(Error::NoPath) // Nowhere to save the data
         } else {
-            Err("No file open to save data.".into()) // Return an error if no file is open
+            Err(Error::Closed) // Return an error if no file is open
         }
     }
 }
+
+impl StorageBackend for Storage {
+    /// Loads the snapshot and replays the write-ahead log, see `load_file`.
+    fn load(&mut self) -> Result<Vec<KV>> {
+        self.load_file(None)
+    }
+
+    /// Loads the snapshot and replays the write-ahead log, except for the merge operands it
+    /// holds: they are folded when the store reads them, or checkpoints the log.
+    fn load_unfolded(&mut self) -> Result<(Vec<KV>, Vec<Mutation>)> {
+        let (pairs, mutations) = self.open_files(None)?;
+        let mut data = by_key(pairs);
+        let mut unfolded = HashMap::new();
+        for mutation in mutations {
+            replay_unfolded(&mut data, &mut unfolded, mutation, &self.merge_operators)?;
+        }
+        Ok((into_pairs(data), unfolded.into_values().flatten().collect()))
+    }
+
+    /// Appends the mutation to the write-ahead log, first folding the log into a snapshot
+    /// if it has grown past `WAL_CHECKPOINT_THRESHOLD`.
+    fn persist(
+        &mut self,
+        mutation: &Mutation,
+        snapshot: &dyn Fn() -> Result<Vec<KV>>,
+    ) -> Result<()> {
+        if self.needs_checkpoint() {
+            self.save_file(snapshot()?)?;
+        }
+        self.append_log(mutation)
+    }
+
+    fn take_sync(&mut self) -> Option<SyncTicket> {
+        self.pending.take()
+    }
+
+    fn flush(&mut self, data: Vec<KV>) -> Result<()> {
+        self.save_file(data)
+    }
+
+    fn close(&mut self, data: Vec<KV>) -> Result<()> {
+        self.save_file(data)?;
+        self.file = None;
+        self.wal = None;
+        self.pending = None; // Saving synced the whole dataset
+        Ok(())
+    }
+}
+
+/// Indexes the pairs of a snapshot by key, for `apply`.
+pub(crate) fn by_key(pairs: Vec<KV>) -> HashMap<Vec<u8>, KV> {
+    pairs
+        .into_iter()
+        .map(|pair| (pair.key.clone(), pair))
+        .collect()
+}
+
+/// Turns a snapshot indexed by `by_key` back into pairs, in key order.
+pub(crate) fn into_pairs(data: HashMap<Vec<u8>, KV>) -> Vec<KV> {
+    let mut pairs: Vec<KV> = data.into_values().collect();
+    pairs.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+    pairs
+}
+
+/// Applies a mutation to a snapshot of the store, indexed by key so replaying a long log
+/// stays linear.
+///
+/// Fails only if a merge operand can't be folded, e.g. `operators` has no operator by its
+/// name.
+pub(crate) fn apply(
+    data: &mut HashMap<Vec<u8>, KV>,
+    mutation: Mutation,
+    operators: &MergeOperators,
+) -> Result<()> {
+    match mutation {
+        Mutation::Put { key, value } => put(data, key, value, None),
+        Mutation::PutExpiring {
+            key,
+            value,
+            expires_at,
+        } => put(data, key, value, Some(expires_at)),
+        Mutation::Delete { key } => {
+            data.remove(&key);
+        }
+        Mutation::Expire { key, expires_at } => {
+            if let Some(pair) = data.get_mut(&key) {
+                pair.expires_at = expires_at;
+            }
+        }
+        Mutation::Batch { mutations } => {
+            for mutation in mutations {
+                apply(data, mutation, operators)?;
+            }
+        }
+        Mutation::Merge {
+            key,
+            operator,
+            operand,
+        } => {
+            let pair = data.get(&key);
+            let existing = pair.map(|pair| pair.value.as_slice());
+            let expires_at = pair.and_then(|pair| pair.expires_at);
+            let value = operators.fold(&operator, &key, existing, &operand)?;
+            put(data, key, value, expires_at);
+        }
+    }
+    Ok(())
+}
+
+/// Replays a mutation over a snapshot of the store like `apply`, without folding a merge
+/// operand: from its first merge on, the mutations of a key are pushed to `unfolded` instead,
+/// in order, until a put or a delete replaces it. See `StorageBackend::load_unfolded`.
+pub(crate) fn replay_unfolded(
+    data: &mut HashMap<Vec<u8>, KV>,
+    unfolded: &mut HashMap<Vec<u8>, Vec<Mutation>>,
+    mutation: Mutation,
+    operators: &MergeOperators,
+) -> Result<()> {
+    match mutation {
+        Mutation::Batch { mutations } => {
+            for mutation in mutations {
+                replay_unfolded(data, unfolded, mutation, operators)?;
+            }
+        }
+        Mutation::Merge { ref key, .. } => unfolded.entry(key.clone()).or_default().push(mutation),
+        Mutation::Expire { ref key, .. } if unfolded.contains_key(key) => {
+            unfolded.entry(key.clone()).or_default().push(mutation) // Changes the merged pair
+        }
+        mutation => {
+            if let Some(key) = mutation.key() {
+                unfolded.remove(key);
+            }
+            apply(data, mutation, operators)?; // Nothing to fold
+        }
+    }
+    Ok(())
+}
+
+/// Sets `key` to `value` in a snapshot of the store, replacing its expiry time.
+fn put(data: &mut HashMap<Vec<u8>, KV>, key: Vec<u8>, value: Vec<u8>, expires_at: Option<u64>) {
+    data.insert(
+        key.clone(),
+        KV {
+            key,
+            value,
+            expires_at,
+        },
+    );
+}
@@ -0,0 +1,71 @@
+use std::fmt::Debug;
+
+use crate::error::Result;
+use crate::kv_store::KV;
+use crate::storage::{Mutation, SyncTicket};
+
+/// The persistence layer a `Store` writes through.
+///
+/// A backend receives every mutation before the store applies it in memory, and is the
+/// source of the store's content when it is opened. Implementations decide how much work a
+/// mutation costs: appending to a log, rewriting a whole file, or nothing at all.
+///
+/// Backends must be `Sync` since a shared store can be read from several threads at once.
+pub trait StorageBackend: Debug + Send + Sync {
+    /// Loads every pair persisted by the backend.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<KV>)` - The persisted pairs, empty for a new database.
+    /// * `Err(Error)` - An error message if the data can't be read or decoded.
+    fn load(&mut self) -> Result<Vec<KV>>;
+
+    /// Loads every pair persisted by the backend like `load`, but leaves the merge operands
+    /// it holds unfolded, for the store to fold when they are read (see `Store::merge`): a
+    /// value that can't be folded then only fails the reads of its key, not the whole load.
+    ///
+    /// Backends that persist whole values only return what `load` returns.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Vec<KV>, Vec<Mutation>))` - The pairs, and the mutations to replay over them
+    ///   (the merges, and the expiry changes that follow them), in order.
+    /// * `Err(Error)` - An error message if the data can't be read or decoded.
+    fn load_unfolded(&mut self) -> Result<(Vec<KV>, Vec<Mutation>)> {
+        Ok((self.load()?, Vec::new()))
+    }
+
+    /// Persists a mutation the store is about to apply.
+    ///
+    /// The store only applies the mutation in memory once this returns `Ok`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mutation` - The mutation to persist.
+    /// * `snapshot` - Produces the store content *before* the mutation, for backends that
+    ///   need the whole dataset (e.g. to rewrite a file or checkpoint a log). It is only called
+    ///   when needed, since cloning the dataset is expensive, and fails with `Error::Merge` if
+    ///   a merge operand the store holds can't be folded; the keys the mutation replaces
+    ///   outright are left out, so writing them always works.
+    fn persist(
+        &mut self,
+        mutation: &Mutation,
+        snapshot: &dyn Fn() -> Result<Vec<KV>>,
+    ) -> Result<()>;
+
+    /// Takes the sync the mutations persisted so far still wait for, if the backend's
+    /// `Durability` defers it (see `Durability::GroupCommit`).
+    ///
+    /// The store's caller waits on it once it has released the store, so the writers
+    /// committing meanwhile share the sync. Backends that sync as they persist return `None`.
+    fn take_sync(&mut self) -> Option<SyncTicket> {
+        None
+    }
+
+    /// Makes the backend's on-disk state match `data` as compactly as it can, e.g. by
+    /// folding a log into a snapshot.
+    fn flush(&mut self, data: Vec<KV>) -> Result<()>;
+
+    /// Flushes `data` and releases the backend's resources. The backend must not be used after.
+    fn close(&mut self, data: Vec<KV>) -> Result<()>;
+}
@@ -0,0 +1,1305 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::error::{Error, Result};
+use crate::kv_store::{now_millis, KV};
+use crate::merge::MergeOperators;
+use crate::storage::durability::{Durability, SyncTicket, Syncer};
+use crate::storage::wal::append_record;
+use crate::storage::{Mutation, StorageBackend};
+
+/// Size in bytes of a record header: `[crc32: u32][kind: u8][key_len: u32][value_len: u32]`.
+const HEADER_SIZE: usize = 13;
+
+/// Record kinds: a value, a tombstone, a value followed by its `u64` expiry time, the
+/// marker committing the batch of records before it, or a merge operand (encoded with its
+/// operator by `encode_operand`) to fold into the value before it.
+const KIND_VALUE: u8 = 0;
+const KIND_TOMBSTONE: u8 = 1;
+const KIND_EXPIRING: u8 = 2;
+const KIND_COMMIT: u8 = 3;
+const KIND_MERGE: u8 = 4;
+
+/// Bit set in the kind of records written as part of a batch. They only count once the
+/// commit marker that follows them is read.
+const BATCH_FLAG: u8 = 0x80;
+
+/// Size in bytes of the expiry time stored between the header and the key of expiring records.
+const EXPIRY_SIZE: usize = 8;
+
+/// Size in bytes of a hint entry header: `[key_len: u32][value_len: u32][value_offset: u64]`.
+const HINT_HEADER_SIZE: usize = 16;
+
+/// Bit set in the `key_len` of a hint entry whose header is followed by a `u64` expiry time.
+const HINT_EXPIRY_FLAG: u32 = 1 << 31;
+
+/// Bit set in the `key_len` of a hint entry locating a merge operand rather than a value.
+const HINT_OPERAND_FLAG: u32 = 1 << 30;
+
+/// Name of the file a merge writes its output to before it is committed.
+const MERGE_TMP: &str = "merge.tmp";
+
+/// Tuning knobs of a `Bitcask` engine.
+#[derive(Debug, Clone)]
+pub struct BitcaskOptions {
+    /// Size in bytes past which the active segment is closed and a new one is started.
+    pub max_segment_size: u64,
+    /// Number of closed segments that triggers a background merge.
+    pub merge_trigger: usize,
+}
+
+impl Default for BitcaskOptions {
+    fn default() -> Self {
+        BitcaskOptions {
+            max_segment_size: 64 * 1024 * 1024,
+            merge_trigger: 4,
+        }
+    }
+}
+
+/// Location of the latest value of a key on disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeydirEntry {
+    file_id: u64,
+    value_offset: u64,
+    value_len: u32,
+    expires_at: Option<u64>,
+}
+
+/// Location of a merge operand on disk, not folded into the value of its key yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OperandEntry {
+    file_id: u64,
+    value_offset: u64,
+    value_len: u32,
+}
+
+/// The records a key is read from: its latest value, if any, and the merge operands
+/// written to it since, oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Chain {
+    value: Option<KeydirEntry>,
+    operands: Vec<OperandEntry>,
+}
+
+/// Where the latest record written for a key is.
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    Value(KeydirEntry),
+    Tombstone,
+    Operand(OperandEntry),
+}
+
+/// Maps each live key to the location of its latest value and of the merge operands
+/// written to it since.
+#[derive(Debug, Default)]
+struct Keydir {
+    values: HashMap<Vec<u8>, KeydirEntry>,
+    operands: HashMap<Vec<u8>, Vec<OperandEntry>>,
+}
+
+/// A key copied by a merge, with its records before and after the merge.
+type Moved = (Vec<u8>, Chain, Chain);
+
+/// What a record appended for a key holds.
+#[derive(Debug, Clone, Copy)]
+enum Change<'a> {
+    /// A value, with its expiry time.
+    Value(&'a [u8], Option<u64>),
+    Tombstone,
+    /// A merge operand, encoded with its operator by `encode_operand`.
+    Operand(&'a [u8]),
+}
+
+/// A write to append: the key and what it is set to.
+type Op<'a> = (&'a [u8], Change<'a>);
+
+/// A `Change` owning its bytes.
+#[derive(Debug, Clone)]
+enum OwnedChange {
+    Value(Vec<u8>, Option<u64>),
+    Tombstone,
+    Operand(Vec<u8>),
+}
+
+/// An `Op` owning its key and bytes.
+type OwnedOp = (Vec<u8>, OwnedChange);
+
+/// A record decoded from a segment.
+#[derive(Debug)]
+struct Record<'a> {
+    key: &'a [u8],
+    /// `None` for a tombstone or a commit marker.
+    value: Option<&'a [u8]>,
+    /// The value is an encoded merge operand.
+    operand: bool,
+    expires_at: Option<u64>,
+    /// Part of a batch, only valid once the batch's commit marker is read.
+    batched: bool,
+    /// The marker committing the batch before it.
+    commit: bool,
+    /// Offset of the next record.
+    next: usize,
+}
+
+/// The state of an open engine, shared with the background merge thread.
+#[derive(Debug)]
+struct Engine {
+    dir: PathBuf,
+    options: BitcaskOptions,
+    keydir: Keydir,
+    active: File,
+    active_id: u64,
+    active_size: u64,
+    /// Syncs the active segment.
+    syncer: Arc<Syncer>,
+    readers: HashMap<u64, File>,
+    merging: bool,
+    /// Folds the merge operands written to the engine.
+    operators: MergeOperators,
+}
+
+/// A log-structured storage engine in the style of Bitcask.
+///
+/// Every write is appended as a checksummed record to the active segment file of a
+/// directory, and an in-memory keydir maps each live key to the offset of its latest value,
+/// so a read costs a single seek. Nothing is ever rewritten in place: once the active segment
+/// passes `max_segment_size` it is closed, and once `merge_trigger` segments are closed a
+/// background merge compacts them into one segment holding only live values, along with a
+/// hint file from which the keydir can be rebuilt on startup without reading the values.
+///
+/// Values can carry an expiry time, past which `get` no longer returns them. They stay on
+/// disk until they are deleted, since the engine never drops data on its own.
+///
+/// A merge is appended as its operand, and the keydir keeps the operands written to a key
+/// since its last value: they are folded with the engine's `MergeOperators` when the key is
+/// read, and into a single value when a merge compacts their segments.
+///
+/// Several writes can be appended as one atomic batch with `write_batch`: the batch ends
+/// with a commit marker, and a batch without one is discarded when the segment is read back.
+///
+/// Writes are synced to disk as the engine's `Durability` says, see `open_with`.
+///
+/// # Example
+/// ```rust
+/// use safina_db::storage::bitcask::{Bitcask, BitcaskOptions};
+///
+/// # let dir = std::env::temp_dir().join(format!("safina-doc-bitcask-{}", std::process::id()));
+/// let db = Bitcask::open(&dir, BitcaskOptions::default()).unwrap();
+/// db.put("key", "value").unwrap();
+/// assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+/// db.delete("key").unwrap();
+/// assert_eq!(db.get("key").unwrap(), None);
+/// # drop(db);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Bitcask {
+    engine: Arc<Mutex<Engine>>,
+    merger: Mutex<Option<JoinHandle<()>>>,
+    /// The sync the last persisted mutation waits for, see `StorageBackend::take_sync`.
+    pending: Option<SyncTicket>,
+}
+
+impl Bitcask {
+    /// Opens (or creates) the engine stored in `dir`, syncing every write before it returns.
+    /// See `open_with`.
+    pub fn open<P: AsRef<Path>>(
+        dir: P,
+        options: BitcaskOptions,
+    ) -> Result<Bitcask> {
+        Self::open_with(dir, options, Durability::default())
+    }
+
+    /// Opens (or creates) the engine stored in `dir` and rebuilds its keydir.
+    ///
+    /// Segments with a hint file are indexed from the hint, the others are scanned record by
+    /// record. A merge interrupted by a crash is either completed or discarded, and a torn
+    /// record at the tail of a segment is cut off.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the segment files. It is created if needed.
+    /// * `options` - The segment size and merge settings.
+    /// * `durability` - When appended records are synced to disk.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Bitcask)` - The opened engine, writing to a fresh active segment.
+    /// * `Err(Error)` - An error message if the directory can't be read.
+    pub fn open_with<P: AsRef<Path>>(
+        dir: P,
+        options: BitcaskOptions,
+        durability: Durability,
+    ) -> Result<Bitcask> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        recover_merge(&dir)?;
+
+        let mut keydir = Keydir::default();
+        let mut readers = HashMap::new();
+        let ids = segment_ids(&dir, "data")?;
+        for &id in &ids {
+            let hint = dir.join(hint_name(id));
+            if hint.exists() {
+                load_hint(&hint, id, &mut keydir)?;
+            } else {
+                scan_segment(&dir.join(data_name(id)), id, &mut keydir)?;
+            }
+            readers.insert(id, File::open(dir.join(data_name(id)))?);
+        }
+
+        let active_id = ids.last().map_or(1, |id| id + 1);
+        let (active, reader) = create_segment(&dir, active_id)?;
+        readers.insert(active_id, reader);
+        let syncer = Syncer::new(active.try_clone()?, durability);
+
+        Ok(Bitcask {
+            engine: Arc::new(Mutex::new(Engine {
+                dir,
+                options,
+                keydir,
+                active,
+                active_id,
+                active_size: 0,
+                syncer,
+                readers,
+                merging: false,
+                operators: MergeOperators::default(),
+            })),
+            merger: Mutex::new(None),
+            pending: None,
+        })
+    }
+
+    /// Makes the engine fold merge operands with `operators`, see `Options::merge_operators`.
+    pub fn with_merge_operators(self, operators: MergeOperators) -> Self {
+        self.engine.lock().unwrap().operators = operators;
+        self
+    }
+
+    /// Retrieves the latest value of `key`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Vec<u8>))` - The value if the key exists.
+    /// * `Ok(None)` - If the key doesn't exist, was deleted or has expired.
+    /// * `Err(Error)` - An error message if the value can't be read back.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>> {
+        let now = now_millis();
+        Ok(self
+            .read(key.as_ref())?
+            .filter(|(_, expires_at)| expires_at.is_none_or(|expires_at| expires_at > now))
+            .map(|(value, _)| value))
+    }
+
+    /// Reads back the latest value of `key` and its expiry time, even if it has expired,
+    /// with the merge operands written since folded into it.
+    ///
+    /// Fails with `Error::Merge` if an operand can't be folded.
+    fn read(&self, key: &[u8]) -> Result<Option<(Vec<u8>, Option<u64>)>> {
+        let mut engine = self.engine.lock().unwrap();
+        let chain = engine.keydir.chain(key);
+        engine.fold(key, &chain)
+    }
+
+    /// Appends `key` = `value` to the active segment and points the keydir at it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the record is written, and synced if the durability mode says so.
+    /// * `Err(Error)` - An error message if the record could not be written.
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<()> {
+        self.write(&[(key.as_ref(), Change::Value(value.as_ref(), None))])
+    }
+
+    /// Like `put`, but the value expires at `expires_at`, in milliseconds since the Unix epoch.
+    pub fn put_expiring<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        value: V,
+        expires_at: u64,
+    ) -> Result<()> {
+        self.write(&[(key.as_ref(), Change::Value(value.as_ref(), Some(expires_at)))])
+    }
+
+    /// Rewrites the latest value of `key` with a new expiry time, `None` for no expiry.
+    ///
+    /// Changing the expiry of a key that doesn't exist is a no-op and writes nothing.
+    pub fn set_expiry<K: AsRef<[u8]>>(&self, key: K, expires_at: Option<u64>) -> Result<()> {
+        let key = key.as_ref();
+        match self.read(key)? {
+            Some((value, _)) => self.write(&[(key, Change::Value(&value, expires_at))]),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends a tombstone for `key` and drops it from the keydir.
+    ///
+    /// Deleting a key that doesn't exist is a no-op and writes nothing.
+    pub fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<()> {
+        let key = key.as_ref();
+        if !self.engine.lock().unwrap().keydir.contains(key) {
+            return Ok(());
+        }
+        self.write(&[(key, Change::Tombstone)])
+    }
+
+    /// Appends the given mutations as one atomic batch, synced to disk with a single write.
+    ///
+    /// Expiry changes are turned into a rewrite of the value they apply to, as of the writes
+    /// before them in the batch, and merges are appended as their operand. Nested batches
+    /// are flattened.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the whole batch is written, and synced if the durability mode says so.
+    /// * `Err(Error)` - An error message if the batch could not be written, e.g. a merge names
+    ///   no operator of the engine or an expiry change can't fold the value it rewrites. None
+    ///   of it is applied.
+    pub fn write_batch(&self, mutations: &[Mutation]) -> Result<()> {
+        if let Some(ticket) = self.append_batch(mutations)? {
+            ticket.wait()?;
+        }
+        Ok(())
+    }
+
+    /// Appends the given mutations as one atomic batch, leaving a deferred sync to the caller.
+    fn append_batch(&self, mutations: &[Mutation]) -> Result<Option<SyncTicket>> {
+        let mut ops: Vec<OwnedOp> = Vec::new();
+        self.collect_ops(mutations, &mut ops)?;
+        let ops: Vec<Op> = ops
+            .iter()
+            .map(|(key, change)| (key.as_slice(), change.borrow()))
+            .collect();
+        self.append(&ops)
+    }
+
+    /// Flattens `mutations` into the writes to append, see `write_batch`.
+    fn collect_ops(
+        &self,
+        mutations: &[Mutation],
+        ops: &mut Vec<OwnedOp>,
+    ) -> Result<()> {
+        for mutation in mutations {
+            match mutation {
+                Mutation::Put { key, value } => {
+                    ops.push((key.clone(), OwnedChange::Value(value.clone(), None)))
+                }
+                Mutation::PutExpiring {
+                    key,
+                    value,
+                    expires_at,
+                } => ops.push((key.clone(), OwnedChange::Value(value.clone(), Some(*expires_at)))),
+                Mutation::Delete { key } => ops.push((key.clone(), OwnedChange::Tombstone)),
+                Mutation::Expire { key, expires_at } => {
+                    if let Some((value, _)) = self.value_after(key, ops)? {
+                        ops.push((key.clone(), OwnedChange::Value(value, *expires_at)));
+                    }
+                }
+                Mutation::Batch { mutations } => self.collect_ops(mutations, ops)?,
+                Mutation::Merge {
+                    key,
+                    operator,
+                    operand,
+                } => {
+                    self.engine.lock().unwrap().operators.get(operator)?; // Folded when read
+                    ops.push((key.clone(), OwnedChange::Operand(encode_operand(operator, operand))));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads back the value of `key` as of the writes in `ops`, with its expiry time.
+    fn value_after(&self, key: &[u8], ops: &[OwnedOp]) -> Result<Option<(Vec<u8>, Option<u64>)>> {
+        let last = ops
+            .iter()
+            .rposition(|(k, change)| k == key && !matches!(change, OwnedChange::Operand(_)));
+        let (mut current, operands) = match last {
+            Some(i) => match &ops[i].1 {
+                OwnedChange::Value(value, expires_at) => (Some((value.clone(), *expires_at)), &ops[i + 1..]),
+                _ => (None, &ops[i + 1..]),
+            },
+            None => (self.read(key)?, ops),
+        };
+        let operators = self.engine.lock().unwrap().operators.clone();
+        for (k, change) in operands {
+            if let (true, OwnedChange::Operand(encoded)) = (k == key, change) {
+                current = Some(fold_operand(&operators, key, current, encoded)?);
+            }
+        }
+        Ok(current)
+    }
+
+    /// Returns every live key of the engine, in no particular order.
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        self.engine.lock().unwrap().keydir.keys()
+    }
+
+    /// Reads back every live pair of the engine, in the same format `Storage::load_file` returns.
+    ///
+    /// Fails with `Error::Merge` if the merge operands of a key can't be folded.
+    pub fn pairs(&self) -> Result<Vec<KV>> {
+        let mut pairs = Vec::new();
+        for key in self.keys() {
+            if let Some((value, expires_at)) = self.read(&key)? {
+                pairs.push(KV {
+                    key,
+                    value,
+                    expires_at,
+                }); // Expired pairs too, the store hides them until it deletes them
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Reads back every live pair of the engine like `pairs`, but returns the merge operands
+    /// written to a key since its value as merges to replay over it, oldest first.
+    fn unfolded(&self) -> Result<(Vec<KV>, Vec<Mutation>)> {
+        let mut engine = self.engine.lock().unwrap();
+        let values: Vec<(Vec<u8>, KeydirEntry)> = engine
+            .keydir
+            .values
+            .iter()
+            .map(|(key, entry)| (key.clone(), *entry))
+            .collect();
+        let operands: Vec<(Vec<u8>, Vec<OperandEntry>)> = engine
+            .keydir
+            .operands
+            .iter()
+            .map(|(key, operands)| (key.clone(), operands.clone()))
+            .collect();
+
+        let mut pairs = Vec::with_capacity(values.len());
+        for (key, entry) in values {
+            let value = engine.read_at(entry.file_id, entry.value_offset, entry.value_len)?;
+            pairs.push(KV {
+                key,
+                value,
+                expires_at: entry.expires_at,
+            });
+        }
+        let mut merges = Vec::new();
+        for (key, entries) in operands {
+            for entry in entries {
+                let encoded = engine.read_at(entry.file_id, entry.value_offset, entry.value_len)?;
+                let (operator, operand) = decode_operand(&encoded)?;
+                merges.push(Mutation::Merge {
+                    key: key.clone(),
+                    operator: operator.to_string(),
+                    operand: operand.to_vec(),
+                });
+            }
+        }
+        Ok((pairs, merges))
+    }
+
+    /// Syncs every record appended so far to disk, whatever the durability mode.
+    pub fn sync(&self) -> Result<()> {
+        let syncer = Arc::clone(&self.engine.lock().unwrap().syncer);
+        syncer.sync() // Without the engine lock, writers keep appending meanwhile
+    }
+
+    /// Returns the number of segment files, the active one included.
+    pub fn segment_count(&self) -> usize {
+        self.engine.lock().unwrap().readers.len()
+    }
+
+    /// Compacts every closed segment into a single one, on the calling thread.
+    ///
+    /// The merge operands in the closed segments are folded into the value of their key,
+    /// except those that fail to fold, which are copied as they are.
+    ///
+    /// Does nothing if a background merge is already running.
+    pub fn merge(&self) -> Result<()> {
+        merge(&self.engine)
+    }
+
+    /// Appends records with `append`, then waits for the sync they were deferred to.
+    fn write(&self, ops: &[Op]) -> Result<()> {
+        if let Some(ticket) = self.append(ops)? {
+            ticket.wait()?; // The engine is unlocked, other writers can join the sync
+        }
+        Ok(())
+    }
+
+    /// Writes records to the active segment, rolling it over and scheduling a background
+    /// merge when the configured thresholds are reached.
+    ///
+    /// Several writes are framed as a batch closed by a commit marker, and all of them are
+    /// written and synced at once.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(None)` - Once the records can be acknowledged.
+    /// * `Ok(Some(SyncTicket))` - With `Durability::GroupCommit` or `Periodic`, the sync they wait for.
+    /// * `Err(Error)` - An error message if the records could not be written.
+    fn append(&self, ops: &[Op]) -> Result<Option<SyncTicket>> {
+        if ops.is_empty() {
+            return Ok(None);
+        }
+        let batched = ops.len() > 1;
+        let (should_merge, ticket) = {
+            let mut engine = self.engine.lock().unwrap();
+            let mut buffer = Vec::new();
+            let mut entries = Vec::with_capacity(ops.len());
+            for &(key, change) in ops {
+                let record = encode_record(key, change, batched);
+                let offset = engine.active_size + buffer.len() as u64;
+                entries.push((key, locate(engine.active_id, offset, &record, change)));
+                buffer.extend_from_slice(&record);
+            }
+            if batched {
+                buffer.extend_from_slice(&encode_commit());
+            }
+            let end = engine.active_size;
+            append_record(&mut engine.active, end, &buffer)?; // Cut back to `end` on failure
+            engine.active_size += buffer.len() as u64;
+            let ticket = engine.syncer.record()?;
+
+            for (key, location) in entries {
+                engine.keydir.index(key, location);
+            }
+
+            if engine.active_size >= engine.options.max_segment_size {
+                engine.roll()?;
+            }
+            let should_merge = !engine.merging && engine.readers.len() > engine.options.merge_trigger;
+            (should_merge, ticket)
+        };
+
+        if should_merge {
+            let mut merger = self.merger.lock().unwrap();
+            if merger.as_ref().is_none_or(|handle| handle.is_finished()) {
+                let engine = Arc::clone(&self.engine);
+                *merger = Some(std::thread::spawn(move || {
+                    let _ = merge(&engine); // A failed merge leaves the segments untouched
+                }));
+            }
+        }
+        Ok(ticket)
+    }
+}
+
+impl StorageBackend for Bitcask {
+    fn load(&mut self) -> Result<Vec<KV>> {
+        self.pairs()
+    }
+
+    fn load_unfolded(&mut self) -> Result<(Vec<KV>, Vec<Mutation>)> {
+        self.unfolded()
+    }
+
+    /// Appends the mutation to the active segment; the rest of the dataset is never touched.
+    fn persist(
+        &mut self,
+        mutation: &Mutation,
+        _snapshot: &dyn Fn() -> Result<Vec<KV>>,
+    ) -> Result<()> {
+        let ticket = match mutation {
+            Mutation::Batch { mutations } => self.append_batch(mutations)?,
+            mutation => self.append_batch(std::slice::from_ref(mutation))?,
+        };
+        self.pending = ticket;
+        Ok(())
+    }
+
+    fn take_sync(&mut self) -> Option<SyncTicket> {
+        self.pending.take()
+    }
+
+    /// Syncs what the durability mode left unsynced, compaction is left to the merge.
+    fn flush(&mut self, _data: Vec<KV>) -> Result<()> {
+        self.sync()
+    }
+
+    /// Syncs the active segment and waits for a running background merge; the files are
+    /// closed when the engine is dropped.
+    fn close(&mut self, _data: Vec<KV>) -> Result<()> {
+        if let Some(handle) = self.merger.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+        self.pending = None;
+        self.sync()
+    }
+}
+
+impl Drop for Bitcask {
+    /// Waits for a running background merge, so no half merged files are left behind, and
+    /// syncs what the durability mode left unsynced.
+    fn drop(&mut self) {
+        if let Some(handle) = self.merger.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+        let _ = self.sync();
+    }
+}
+
+impl Engine {
+    /// Closes the active segment and starts writing to a new one.
+    fn roll(&mut self) -> Result<()> {
+        self.active.sync_all()?;
+        let (active, reader) = create_segment(&self.dir, self.active_id + 1)?;
+        self.syncer.replace_file(active.try_clone()?);
+        self.active_id += 1;
+        self.active = active;
+        self.active_size = 0;
+        self.readers.insert(self.active_id, reader);
+        Ok(())
+    }
+
+    /// Reads `len` bytes at `offset` in segment `file_id`.
+    fn read_at(&mut self, file_id: u64, offset: u64, len: u32) -> Result<Vec<u8>> {
+        let reader = self
+            .readers
+            .get_mut(&file_id)
+            .ok_or_else(|| Error::Corruption(format!("missing segment {}", file_id)))?;
+        let mut value = vec![0; len as usize];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut value)?;
+        Ok(value)
+    }
+
+    /// Reads back the value of `key` from its `chain`, with its operands folded into it.
+    fn fold(&mut self, key: &[u8], chain: &Chain) -> Result<Option<(Vec<u8>, Option<u64>)>> {
+        let mut current = match chain.value {
+            Some(entry) => Some((
+                self.read_at(entry.file_id, entry.value_offset, entry.value_len)?,
+                entry.expires_at,
+            )),
+            None => None,
+        };
+        for entry in &chain.operands {
+            let encoded = self.read_at(entry.file_id, entry.value_offset, entry.value_len)?;
+            current = Some(fold_operand(&self.operators, key, current, &encoded)?);
+        }
+        Ok(current)
+    }
+}
+
+impl Keydir {
+    /// Points `key` at the record just written for it: a value replaces its operands, an
+    /// operand is added to them and a tombstone drops the key.
+    fn index(&mut self, key: &[u8], location: Location) {
+        match location {
+            Location::Value(entry) => {
+                self.values.insert(key.to_vec(), entry);
+                self.operands.remove(key);
+            }
+            Location::Tombstone => {
+                self.values.remove(key);
+                self.operands.remove(key);
+            }
+            Location::Operand(entry) => self.operands.entry(key.to_vec()).or_default().push(entry),
+        }
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.values.contains_key(key) || self.operands.contains_key(key)
+    }
+
+    /// Returns every live key, in no particular order.
+    fn keys(&self) -> Vec<Vec<u8>> {
+        let mut keys: Vec<Vec<u8>> = self.values.keys().cloned().collect();
+        keys.extend(
+            self.operands
+                .keys()
+                .filter(|key| !self.values.contains_key(*key))
+                .cloned(),
+        );
+        keys
+    }
+
+    fn chain(&self, key: &[u8]) -> Chain {
+        Chain {
+            value: self.values.get(key).copied(),
+            operands: self.operands.get(key).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Swaps the records `old` of `key` for the `new` ones a merge copied them to. Keys
+    /// rewritten during the merge keep their newer records, and operands added meanwhile
+    /// stay after the copied ones.
+    fn replace(&mut self, key: &[u8], old: &Chain, new: &Chain) {
+        let current = self.chain(key);
+        if current.value != old.value || !current.operands.starts_with(&old.operands) {
+            return;
+        }
+        match new.value {
+            Some(entry) => {
+                self.values.insert(key.to_vec(), entry);
+            }
+            None => {
+                self.values.remove(key);
+            }
+        }
+        let mut operands = new.operands.clone();
+        operands.extend_from_slice(&current.operands[old.operands.len()..]);
+        if operands.is_empty() {
+            self.operands.remove(key);
+        } else {
+            self.operands.insert(key.to_vec(), operands);
+        }
+    }
+}
+
+impl Chain {
+    /// Returns the part of the chain written to the segments before `active_id`, the ones a
+    /// merge compacts. A value in the active segment leaves nothing to compact.
+    fn closed(self, active_id: u64) -> Chain {
+        if self.value.is_some_and(|entry| entry.file_id >= active_id) {
+            return Chain::default();
+        }
+        Chain {
+            value: self.value,
+            operands: self
+                .operands
+                .into_iter()
+                .take_while(|entry| entry.file_id < active_id)
+                .collect(),
+        }
+    }
+
+    /// Returns where the chain starts on disk.
+    fn start(&self) -> (u64, u64) {
+        match (self.value, self.operands.first()) {
+            (Some(entry), _) => (entry.file_id, entry.value_offset),
+            (None, Some(entry)) => (entry.file_id, entry.value_offset),
+            (None, None) => (0, 0),
+        }
+    }
+
+    /// Adds a record copied by a merge to the chain.
+    fn add(&mut self, location: Location) {
+        match location {
+            Location::Value(entry) => self.value = Some(entry),
+            Location::Operand(entry) => self.operands.push(entry),
+            Location::Tombstone => {}
+        }
+    }
+}
+
+impl OwnedChange {
+    fn borrow(&self) -> Change<'_> {
+        match self {
+            OwnedChange::Value(value, expires_at) => Change::Value(value, *expires_at),
+            OwnedChange::Tombstone => Change::Tombstone,
+            OwnedChange::Operand(encoded) => Change::Operand(encoded),
+        }
+    }
+}
+
+/// Compacts every closed segment into one segment holding only their live values, with the
+/// merge operands they hold folded in.
+///
+/// The live entries are picked under the lock, copied without it so writers aren't blocked,
+/// and the result is swapped in under the lock again. The merged segment takes the id of the
+/// newest segment it replaces, so it still sorts before every segment written meanwhile.
+fn merge(engine: &Mutex<Engine>) -> Result<()> {
+    let (dir, ids, live, operators) = {
+        let mut engine = engine.lock().unwrap();
+        let mut ids: Vec<u64> = engine
+            .readers
+            .keys()
+            .filter(|id| **id != engine.active_id)
+            .copied()
+            .collect();
+        if engine.merging || ids.is_empty() {
+            return Ok(());
+        }
+        ids.sort_unstable();
+        let mut live: Vec<(Vec<u8>, Chain)> = engine
+            .keydir
+            .keys()
+            .into_iter()
+            .map(|key| {
+                let chain = engine.keydir.chain(&key).closed(engine.active_id);
+                (key, chain)
+            })
+            .filter(|(_, chain)| chain.value.is_some() || !chain.operands.is_empty())
+            .collect();
+        live.sort_by_key(|(_, chain)| chain.start()); // Read sequentially
+        engine.merging = true;
+        (engine.dir.clone(), ids, live, engine.operators.clone())
+    };
+
+    let merged_id = *ids.last().unwrap();
+    let written = write_merged(&dir, merged_id, &live, &operators);
+
+    let mut engine = engine.lock().unwrap();
+    engine.merging = false;
+    let moved = written?;
+
+    // Commit point: `<id>.merge` is durable, the replaced segments can go.
+    for id in &ids {
+        engine.readers.remove(id);
+        remove_if_exists(&dir.join(data_name(*id)))?;
+        remove_if_exists(&dir.join(hint_name(*id)))?;
+    }
+    fs::rename(
+        dir.join(merge_name(merged_id)),
+        dir.join(data_name(merged_id)),
+    )?;
+    sync_dir(&dir)?;
+    engine
+        .readers
+        .insert(merged_id, File::open(dir.join(data_name(merged_id)))?);
+
+    for (key, old, new) in &moved {
+        engine.keydir.replace(key, old, new);
+    }
+    drop(engine);
+
+    write_hint(&dir, merged_id, &moved)
+}
+
+/// Copies the live values into `<merged_id>.merge`, folding the merge operands of each key
+/// into its value with `operators`. The records of a key whose operands fail to fold are
+/// copied as they are, for its reads to report the error.
+///
+/// # Returns
+///
+/// * `Ok(Vec<Moved>)` - Each copied key with its old and new records.
+/// * `Err(Error)` - An error message if a segment can't be read or the output written.
+fn write_merged(
+    dir: &Path,
+    merged_id: u64,
+    live: &[(Vec<u8>, Chain)],
+    operators: &MergeOperators,
+) -> Result<Vec<Moved>> {
+    let tmp_path = dir.join(MERGE_TMP);
+    let mut output = BufWriter::new(File::create(&tmp_path)?);
+    let mut sources: HashMap<u64, File> = HashMap::new();
+    let mut moved = Vec::with_capacity(live.len());
+    let mut offset = 0;
+
+    for (key, chain) in live {
+        let value = match chain.value {
+            Some(entry) => Some((
+                read_source(dir, &mut sources, entry.file_id, entry.value_offset, entry.value_len)?,
+                entry.expires_at,
+            )),
+            None => None,
+        };
+        let mut operands = Vec::with_capacity(chain.operands.len());
+        for entry in &chain.operands {
+            operands.push(read_source(dir, &mut sources, entry.file_id, entry.value_offset, entry.value_len)?);
+        }
+
+        let mut new = Chain::default();
+        let mut copy = |change: Change| -> Result<()> {
+            let record = encode_record(key, change, false);
+            output.write_all(&record)?;
+            new.add(locate(merged_id, offset, &record, change));
+            offset += record.len() as u64;
+            Ok(())
+        };
+        let folded = operands.iter().try_fold(value.clone(), |current, encoded| {
+            fold_operand(operators, key, current, encoded).map(Some)
+        });
+        match folded {
+            Ok(folded) => {
+                if let Some((value, expires_at)) = &folded {
+                    copy(Change::Value(value, *expires_at))?;
+                }
+            }
+            Err(_) => {
+                if let Some((value, expires_at)) = &value {
+                    copy(Change::Value(value, *expires_at))?;
+                }
+                for encoded in &operands {
+                    copy(Change::Operand(encoded))?;
+                }
+            }
+        }
+        moved.push((key.clone(), chain.clone(), new));
+    }
+
+    output
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&tmp_path, dir.join(merge_name(merged_id)))?;
+    sync_dir(dir)?;
+    Ok(moved)
+}
+
+/// Reads `len` bytes at `offset` in segment `file_id`, opening it on first use.
+fn read_source(
+    dir: &Path,
+    sources: &mut HashMap<u64, File>,
+    file_id: u64,
+    offset: u64,
+    len: u32,
+) -> Result<Vec<u8>> {
+    let source = match sources.entry(file_id) {
+        Entry::Occupied(source) => source.into_mut(),
+        Entry::Vacant(slot) => slot.insert(File::open(dir.join(data_name(file_id)))?),
+    };
+    let mut value = vec![0; len as usize];
+    source.seek(SeekFrom::Start(offset))?;
+    source.read_exact(&mut value)?;
+    Ok(value)
+}
+
+/// Writes the hint file of a merged segment, via a temp file so it is never seen half written.
+///
+/// The value of a key is hinted before its operands, which are flagged as such.
+fn write_hint(dir: &Path, id: u64, entries: &[Moved]) -> Result<()> {
+    let mut buffer = Vec::new();
+    for (key, _, chain) in entries {
+        if let Some(entry) = chain.value {
+            let flag = if entry.expires_at.is_some() { HINT_EXPIRY_FLAG } else { 0 };
+            buffer.extend_from_slice(&(key.len() as u32 | flag).to_le_bytes());
+            buffer.extend_from_slice(&entry.value_len.to_le_bytes());
+            buffer.extend_from_slice(&entry.value_offset.to_le_bytes());
+            if let Some(expires_at) = entry.expires_at {
+                buffer.extend_from_slice(&expires_at.to_le_bytes());
+            }
+            buffer.extend_from_slice(key);
+        }
+        for entry in &chain.operands {
+            buffer.extend_from_slice(&(key.len() as u32 | HINT_OPERAND_FLAG).to_le_bytes());
+            buffer.extend_from_slice(&entry.value_len.to_le_bytes());
+            buffer.extend_from_slice(&entry.value_offset.to_le_bytes());
+            buffer.extend_from_slice(key);
+        }
+    }
+    let tmp_path = dir.join(format!("{}.tmp", hint_name(id)));
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(&buffer)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, dir.join(hint_name(id)))?;
+    sync_dir(dir)
+}
+
+/// Rebuilds the keydir entries of segment `id` from its hint file.
+fn load_hint(
+    path: &Path,
+    id: u64,
+    keydir: &mut Keydir,
+) -> Result<()> {
+    let buffer = fs::read(path)?;
+    let mut offset = 0;
+    while offset < buffer.len() {
+        let header = buffer
+            .get(offset..offset + HINT_HEADER_SIZE)
+            .ok_or_else(|| Error::Corruption("truncated hint file".to_string()))?;
+        let key_len = u32::from_le_bytes(header[0..4].try_into()?);
+        let value_len = u32::from_le_bytes(header[4..8].try_into()?);
+        let value_offset = u64::from_le_bytes(header[8..16].try_into()?);
+        let mut start = offset + HINT_HEADER_SIZE;
+        let expires_at = if key_len & HINT_EXPIRY_FLAG != 0 {
+            let expiry = buffer
+                .get(start..start + EXPIRY_SIZE)
+                .ok_or_else(|| Error::Corruption("truncated hint file".to_string()))?;
+            start += EXPIRY_SIZE;
+            Some(u64::from_le_bytes(expiry.try_into()?))
+        } else {
+            None
+        };
+        let operand = key_len & HINT_OPERAND_FLAG != 0;
+        let key_len = (key_len & !(HINT_EXPIRY_FLAG | HINT_OPERAND_FLAG)) as usize;
+        let key = buffer
+            .get(start..start + key_len)
+            .ok_or_else(|| Error::Corruption("truncated hint file".to_string()))?;
+        let location = if operand {
+            Location::Operand(OperandEntry {
+                file_id: id,
+                value_offset,
+                value_len,
+            })
+        } else {
+            Location::Value(KeydirEntry {
+                file_id: id,
+                value_offset,
+                value_len,
+                expires_at,
+            })
+        };
+        keydir.index(key, location);
+        offset = start + key_len;
+    }
+    Ok(())
+}
+
+/// Rebuilds the keydir entries of segment `id` by reading every record in it.
+///
+/// Reading stops at the first incomplete or corrupted record, and the segment is cut back
+/// to the end of the last valid one. Batched records are only indexed once their commit
+/// marker is read, so a batch interrupted by a crash is cut off as a whole.
+fn scan_segment(
+    path: &Path,
+    id: u64,
+    keydir: &mut Keydir,
+) -> Result<()> {
+    let buffer = fs::read(path)?;
+    let mut offset = 0;
+    let mut valid = 0; // End of the last record that is not part of an open batch
+    let mut pending = Vec::new();
+    while let Some(record) = decode_record(&buffer, offset) {
+        offset = record.next;
+        if record.batched {
+            pending.push(record);
+            continue;
+        }
+        if record.commit {
+            for record in pending.drain(..) {
+                index_record(id, record, keydir);
+            }
+        } else {
+            pending.clear(); // An unterminated batch never counts
+            index_record(id, record, keydir);
+        }
+        valid = offset;
+    }
+
+    if valid < buffer.len() {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(valid as u64)?; // Drop the torn tail left by an interrupted append
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Points the keydir at the value or merge operand of a record read from segment `id`, or
+/// drops its key for a tombstone.
+fn index_record(id: u64, record: Record, keydir: &mut Keydir) {
+    let location = match record.value {
+        Some(value) if record.operand => Location::Operand(OperandEntry {
+            file_id: id,
+            value_offset: (record.next - value.len()) as u64,
+            value_len: value.len() as u32,
+        }),
+        Some(value) => Location::Value(KeydirEntry {
+            file_id: id,
+            value_offset: (record.next - value.len()) as u64,
+            value_len: value.len() as u32,
+            expires_at: record.expires_at,
+        }),
+        None => Location::Tombstone,
+    };
+    keydir.index(record.key, location);
+}
+
+/// Finishes or discards a merge interrupted by a crash.
+///
+/// A `<id>.merge` file is only created once the merged output is complete and synced, so
+/// when one exists the segments it replaces are removed and it takes their place.
+/// Leftover temp files are deleted.
+fn recover_merge(dir: &Path) -> Result<()> {
+    for merged_id in segment_ids(dir, "merge")? {
+        for id in segment_ids(dir, "data")? {
+            if id <= merged_id {
+                fs::remove_file(dir.join(data_name(id)))?;
+                remove_if_exists(&dir.join(hint_name(id)))?;
+            }
+        }
+        fs::rename(
+            dir.join(merge_name(merged_id)),
+            dir.join(data_name(merged_id)),
+        )?;
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "tmp") {
+            fs::remove_file(path)?;
+        }
+    }
+    sync_dir(dir)
+}
+
+/// Returns the ids of the files named `<id>.<extension>` in `dir`, in ascending order.
+fn segment_ids(dir: &Path, extension: &str) -> Result<Vec<u64>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == extension) {
+            if let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            {
+                ids.push(id);
+            }
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Creates segment `id`, returning its append handle and a separate read handle.
+fn create_segment(dir: &Path, id: u64) -> Result<(File, File)> {
+    let path = dir.join(data_name(id));
+    let writer = OpenOptions::new().create(true).append(true).open(&path)?;
+    let reader = File::open(&path)?;
+    sync_dir(dir)?;
+    Ok((writer, reader))
+}
+
+/// Serializes a record: `[crc32][kind][key_len][value_len][expires_at?][key][value]`.
+///
+/// The checksum covers everything after itself. A tombstone has an empty value, and the
+/// expiry time is only written for expiring values. Records of a batch are flagged as such.
+fn encode_record(key: &[u8], change: Change, batched: bool) -> Vec<u8> {
+    let (kind, value, expires_at) = match change {
+        Change::Value(value, None) => (KIND_VALUE, value, None),
+        Change::Value(value, Some(expires_at)) => (KIND_EXPIRING, value, Some(expires_at)),
+        Change::Tombstone => (KIND_TOMBSTONE, &[][..], None),
+        Change::Operand(encoded) => (KIND_MERGE, encoded, None),
+    };
+    let kind = if batched { kind | BATCH_FLAG } else { kind };
+    encode(kind, key, value, expires_at)
+}
+
+/// Locates the bytes `change` is encoded to, once `record` is written at `offset` in segment
+/// `file_id`.
+fn locate(file_id: u64, offset: u64, record: &[u8], change: Change) -> Location {
+    match change {
+        Change::Value(value, expires_at) => Location::Value(KeydirEntry {
+            file_id,
+            value_offset: offset + (record.len() - value.len()) as u64,
+            value_len: value.len() as u32,
+            expires_at,
+        }),
+        Change::Tombstone => Location::Tombstone,
+        Change::Operand(encoded) => Location::Operand(OperandEntry {
+            file_id,
+            value_offset: offset + (record.len() - encoded.len()) as u64,
+            value_len: encoded.len() as u32,
+        }),
+    }
+}
+
+/// Serializes a merge operand with the name of its operator: `[name_len: u32][name][operand]`.
+fn encode_operand(operator: &str, operand: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(4 + operator.len() + operand.len());
+    encoded.extend_from_slice(&(operator.len() as u32).to_le_bytes());
+    encoded.extend_from_slice(operator.as_bytes());
+    encoded.extend_from_slice(operand);
+    encoded
+}
+
+/// Splits a merge operand serialized by `encode_operand` into its operator name and operand.
+fn decode_operand(encoded: &[u8]) -> Result<(&str, &[u8])> {
+    let corrupted = || Error::Corruption("malformed merge operand".to_string());
+    let name_len = u32::from_le_bytes(encoded.get(0..4).ok_or_else(corrupted)?.try_into()?) as usize;
+    let name = encoded.get(4..4 + name_len).ok_or_else(corrupted)?;
+    let name = std::str::from_utf8(name).map_err(|_| corrupted())?;
+    Ok((name, &encoded[4 + name_len..]))
+}
+
+/// Folds a merge operand serialized by `encode_operand` into the `current` value of `key`
+/// and its expiry time, which the new value keeps.
+fn fold_operand(
+    operators: &MergeOperators,
+    key: &[u8],
+    current: Option<(Vec<u8>, Option<u64>)>,
+    encoded: &[u8],
+) -> Result<(Vec<u8>, Option<u64>)> {
+    let (operator, operand) = decode_operand(encoded)?;
+    let existing = current.as_ref().map(|(value, _)| value.as_slice());
+    let value = operators.fold(operator, key, existing, operand)?;
+    Ok((value, current.and_then(|(_, expires_at)| expires_at)))
+}
+
+/// Serializes the marker committing the batch written before it.
+fn encode_commit() -> Vec<u8> {
+    encode(KIND_COMMIT, &[], &[], None)
+}
+
+fn encode(kind: u8, key: &[u8], value: &[u8], expires_at: Option<u64>) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_SIZE + EXPIRY_SIZE + key.len() + value.len());
+    record.extend_from_slice(&[0; 4]); // Checksum placeholder
+    record.push(kind);
+    record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    if let Some(expires_at) = expires_at {
+        record.extend_from_slice(&expires_at.to_le_bytes());
+    }
+    record.extend_from_slice(key);
+    record.extend_from_slice(value);
+    let crc = crc32fast::hash(&record[4..]);
+    record[0..4].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// Decodes the record starting at `offset`.
+///
+/// # Returns
+///
+/// * `Some(Record)` - The decoded record.
+/// * `None` - If the record is incomplete, fails its checksum or has an unknown kind.
+fn decode_record(buffer: &[u8], offset: usize) -> Option<Record<'_>> {
+    let header = buffer.get(offset..offset.checked_add(HEADER_SIZE)?)?;
+    let crc = u32::from_le_bytes(header[0..4].try_into().ok()?);
+    let batched = header[4] & BATCH_FLAG != 0;
+    let kind = header[4] & !BATCH_FLAG;
+    let key_len = u32::from_le_bytes(header[5..9].try_into().ok()?) as usize;
+    let value_len = u32::from_le_bytes(header[9..13].try_into().ok()?) as usize;
+
+    let expiry_len = match kind {
+        KIND_VALUE | KIND_TOMBSTONE | KIND_MERGE => 0,
+        KIND_COMMIT if !batched => 0,
+        KIND_EXPIRING => EXPIRY_SIZE,
+        _ => return None,
+    };
+    let key_start = offset + HEADER_SIZE + expiry_len;
+    let end = key_start.checked_add(key_len)?.checked_add(value_len)?;
+    let body = buffer.get(offset + 4..end)?;
+    if crc32fast::hash(body) != crc {
+        return None;
+    }
+    let expires_at = (kind == KIND_EXPIRING)
+        .then(|| buffer[offset + HEADER_SIZE..key_start].try_into().ok())
+        .flatten()
+        .map(u64::from_le_bytes);
+    Some(Record {
+        key: &buffer[key_start..key_start + key_len],
+        value: matches!(kind, KIND_VALUE | KIND_EXPIRING | KIND_MERGE)
+            .then(|| &buffer[key_start + key_len..end]),
+        operand: kind == KIND_MERGE,
+        expires_at,
+        batched,
+        commit: kind == KIND_COMMIT,
+        next: end,
+    })
+}
+
+fn data_name(id: u64) -> String {
+    format!("{id:010}.data")
+}
+
+fn hint_name(id: u64) -> String {
+    format!("{id:010}.hint")
+}
+
+fn merge_name(id: u64) -> String {
+    format!("{id:010}.merge")
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Syncs a directory so the files created, renamed or removed in it are durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Directories can't be opened and synced on this platform, the metadata is left to the OS.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
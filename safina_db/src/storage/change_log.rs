@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Error, Result};
+use crate::kv_store::now_millis;
+use crate::storage::durability::{Durability, SyncTicket, Syncer};
+use crate::storage::snapshot;
+use crate::storage::wal::{append_record, cut_back, decode_record, encode_record, Mutation};
+
+/// Extension of the segment files.
+const SEGMENT_EXTENSION: &str = "cdc";
+
+/// Name of the file holding the saved cursors.
+const CURSORS_FILE: &str = "cursors";
+
+/// Settings of a `ChangeLog`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeLogOptions {
+    /// Size in bytes past which the active segment is closed and a new one is started.
+    pub segment_size: u64,
+    /// Retention: once the segments take more than this many bytes, the oldest are deleted.
+    /// The active segment is always kept, so the log can briefly grow past it by one segment.
+    pub max_size: u64,
+}
+
+impl Default for ChangeLogOptions {
+    fn default() -> Self {
+        ChangeLogOptions {
+            segment_size: 4 * 1024 * 1024,
+            max_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// A committed mutation, as read back from a `ChangeLog`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Position of the change in the log, one more than the change before it.
+    pub seq: u64,
+    /// When the change was committed, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The mutation, a `Mutation::Batch` for batches and transactions. Never a
+    /// `Mutation::Merge`: merges are logged as a put of the value they fold to.
+    pub mutation: Mutation,
+}
+
+/// A change as written to the log, borrowing the mutation. Encodes like a `Change`.
+#[derive(serde::Serialize)]
+struct ChangeRef<'a> {
+    seq: u64,
+    timestamp: u64,
+    mutation: &'a Mutation,
+}
+
+/// A durable, ordered feed of every committed mutation, for change data capture.
+///
+/// Each mutation the stores of a database persist is appended to the log together with its
+/// store persisting it, under the lock of the log, so the log holds a change if and only if
+/// the store does, in the order the stores persisted them. Merges are logged as the value
+/// they fold to, so consumers read values, never operands of operators they may not know.
+/// Each change is numbered with a sequence number one more than the one before. Numbers
+/// are never reused, across restarts too, so a consumer can save the number of the next
+/// change it wants (its cursor) and resume from it: `read(cursor, limit)`, process, then
+/// `save_cursor(name, last.seq + 1)`. Cursors saved in the log survive restarts with it.
+///
+/// The log is a directory of segment files, each named after the number of its first
+/// change and framed like the write-ahead log. Records are synced as the database's
+/// `Durability` says. The retention policy of the `ChangeLogOptions` deletes the oldest
+/// segments, whether or not consumers have read them: reading from a change that was
+/// deleted fails with `Error::ChangeLogTruncated`, after which the consumer has to rescan
+/// the database.
+#[derive(Debug)]
+pub struct ChangeLog {
+    dir: PathBuf,
+    options: ChangeLogOptions,
+    state: Mutex<LogState>,
+}
+
+#[derive(Debug)]
+struct LogState {
+    /// Every segment, oldest first; the last one is written to.
+    segments: Vec<Segment>,
+    active: File,
+    syncer: Arc<Syncer>,
+    /// Number of the next change appended.
+    next_seq: u64,
+    cursors: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    first_seq: u64,
+    path: PathBuf,
+    size: u64,
+}
+
+impl ChangeLog {
+    /// Opens (or creates) the change log in directory `dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the segments.
+    /// * `options` - The segment size and retention of the log.
+    /// * `durability` - When appended changes are synced to disk.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ChangeLog)` - The open log, appending after its last intact change.
+    /// * `Err(Error)` - An error message if the directory can't be read or created.
+    pub fn open<P: AsRef<Path>>(
+        dir: P,
+        options: ChangeLogOptions,
+        durability: Durability,
+    ) -> Result<ChangeLog> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+                let first_seq = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()?.parse().ok())
+                    .ok_or_else(|| {
+                        Error::Corruption(format!("bad segment name {}", path.display()))
+                    })?;
+                let size = fs::metadata(&path)?.len();
+                segments.push(Segment {
+                    first_seq,
+                    path,
+                    size,
+                });
+            }
+        }
+        segments.sort_by_key(|segment| segment.first_seq);
+        if segments.is_empty() {
+            segments.push(Segment {
+                first_seq: 1,
+                path: segment_path(&dir, 1),
+                size: 0,
+            });
+        }
+
+        let last = segments.last_mut().expect("at least one segment");
+        let mut active = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&last.path)?;
+        let mut buffer = Vec::new();
+        active.read_to_end(&mut buffer)?;
+        let mut next_seq = last.first_seq;
+        let mut offset = 0;
+        while let Some((change, next)) = decode_record::<Change>(&buffer, offset) {
+            next_seq = change.seq + 1;
+            offset = next;
+        }
+        if offset < buffer.len() {
+            active.set_len(offset as u64)?; // Drop the torn tail left by an interrupted append
+            active.sync_all()?;
+        }
+        active.seek(SeekFrom::Start(offset as u64))?;
+        last.size = offset as u64;
+
+        let cursors = match fs::read(dir.join(CURSORS_FILE)) {
+            Ok(buffer) => bincode::deserialize(&buffer)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let syncer = Syncer::new(active.try_clone()?, durability);
+        Ok(ChangeLog {
+            dir,
+            options,
+            state: Mutex::new(LogState {
+                segments,
+                active,
+                syncer,
+                next_seq,
+                cursors,
+            }),
+        })
+    }
+
+    /// Appends a committed mutation, syncing it to disk as the log's `Durability` says.
+    ///
+    /// The change is written first, then `persist` hands the mutation to the store's backend
+    /// under the lock of the log. If it fails, the change is cut back out of the log: either
+    /// both hold the mutation, or neither does.
+    ///
+    /// # Arguments
+    ///
+    /// * `mutation` - The change to log.
+    /// * `persist` - Persists the mutation to the backend of the store.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(None)` - Once the change can be acknowledged.
+    /// * `Ok(Some(SyncTicket))` - With `Durability::GroupCommit` or `Periodic`, the sync the
+    ///   change waits for, see `Syncer::record`.
+    /// * `Err(Error)` - An error message if the change could not be written or persisted.
+    pub(crate) fn append<F: FnOnce() -> Result<()>>(
+        &self,
+        mutation: &Mutation,
+        persist: F,
+    ) -> Result<Option<SyncTicket>> {
+        let mut state = self.state.lock().unwrap();
+        let record = encode_record(&ChangeRef {
+            seq: state.next_seq,
+            timestamp: now_millis(),
+            mutation,
+        })?;
+        let end = state.segments.last().expect("at least one segment").size;
+        append_record(&mut state.active, end, &record)?;
+        if let Err(e) = persist() {
+            cut_back(&mut state.active, end)?;
+            return Err(e);
+        }
+        state.next_seq += 1;
+        let segment = state.segments.last_mut().expect("at least one segment");
+        segment.size += record.len() as u64;
+        if segment.size >= self.options.segment_size {
+            state.syncer.sync()?;
+            self.roll(&mut state)?;
+            return Ok(None); // Rolling synced the change
+        }
+        state.syncer.record()
+    }
+
+    /// Returns up to `limit` changes, in order, starting with change number `from`.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The number of the first change to return, typically a saved cursor.
+    /// * `limit` - The maximum number of changes to return.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Change>)` - The changes, empty if `from` is past the last change.
+    /// * `Err(Error::ChangeLogTruncated)` - If change `from` was deleted by the retention.
+    /// * `Err(Error)` - An error message if a segment can't be read.
+    pub fn read(&self, from: u64, limit: usize) -> Result<Vec<Change>> {
+        let segments = {
+            let state = self.state.lock().unwrap();
+            let first_seq = state.segments[0].first_seq;
+            if from < first_seq {
+                return Err(Error::ChangeLogTruncated(first_seq));
+            }
+            let start = state
+                .segments
+                .partition_point(|segment| segment.first_seq <= from)
+                .saturating_sub(1);
+            state.segments[start..].to_vec()
+        }; // Segments are read without the lock, appends carry on meanwhile
+
+        let mut changes = Vec::new();
+        for segment in segments {
+            let buffer = match fs::read(&segment.path) {
+                Ok(buffer) => buffer,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(Error::ChangeLogTruncated(self.first_seq())); // Deleted meanwhile
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let mut offset = 0;
+            while let Some((change, next)) = decode_record::<Change>(&buffer, offset) {
+                if changes.len() == limit {
+                    return Ok(changes);
+                }
+                if change.seq >= from {
+                    changes.push(change);
+                }
+                offset = next; // A change being appended is left for the next read
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Returns the number of the oldest change the log still holds.
+    pub fn first_seq(&self) -> u64 {
+        self.state.lock().unwrap().segments[0].first_seq
+    }
+
+    /// Returns the number the next appended change will get.
+    pub fn next_seq(&self) -> u64 {
+        self.state.lock().unwrap().next_seq
+    }
+
+    /// Saves cursor `name`, the number of the next change its consumer wants, so it can
+    /// resume from it after a restart.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the cursor is durable.
+    /// * `Err(Error)` - An error message if the cursors could not be written.
+    pub fn save_cursor(&self, name: &str, seq: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.cursors.insert(name.to_string(), seq);
+        let buffer = bincode::serialize(&state.cursors)?;
+        let path = self.dir.join(CURSORS_FILE);
+        snapshot::write_atomic(&path.to_string_lossy(), &buffer)
+    }
+
+    /// Returns the saved cursor `name`, or `None` if it was never saved.
+    pub fn cursor(&self, name: &str) -> Option<u64> {
+        self.state.lock().unwrap().cursors.get(name).copied()
+    }
+
+    /// Syncs every change appended so far to disk.
+    pub fn sync(&self) -> Result<()> {
+        let syncer = Arc::clone(&self.state.lock().unwrap().syncer);
+        syncer.sync()
+    }
+
+    /// Closes the active segment, starts a new one and applies the retention.
+    fn roll(&self, state: &mut LogState) -> Result<()> {
+        let path = segment_path(&self.dir, state.next_seq);
+        let active = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        state.syncer.replace_file(active.try_clone()?);
+        state.active = active;
+        state.segments.push(Segment {
+            first_seq: state.next_seq,
+            path,
+            size: 0,
+        });
+
+        let mut total: u64 = state.segments.iter().map(|segment| segment.size).sum();
+        while total > self.options.max_size && state.segments.len() > 1 {
+            let oldest = state.segments.remove(0);
+            fs::remove_file(&oldest.path)?;
+            total -= oldest.size;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path of the segment whose first change is `first_seq`.
+fn segment_path(dir: &Path, first_seq: u64) -> PathBuf {
+    dir.join(format!("{first_seq:020}.{SEGMENT_EXTENSION}"))
+}
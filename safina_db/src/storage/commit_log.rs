@@ -0,0 +1,143 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::error::Result;
+use crate::storage::durability::Durability;
+use crate::storage::wal::{append_record, decode_record, encode_record, Mutation};
+
+/// A batch spanning several shards, as read back from a `CommitLog`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Intent {
+    /// Number of the commit, one more than the commit before it.
+    pub(crate) id: u64,
+    /// The mutations of each shard the batch touches, by shard index.
+    pub(crate) parts: Vec<(usize, Vec<Mutation>)>,
+}
+
+/// An intent as written to the log, borrowing the mutations. Encodes like an `Intent`.
+#[derive(serde::Serialize)]
+struct IntentRef<'a> {
+    id: u64,
+    parts: Vec<(usize, &'a [Mutation])>,
+}
+
+/// The log making the batches that span several shards atomic.
+///
+/// Each shard persists its part of a batch on its own, so a crash in the middle of a batch
+/// could keep the part of some shards and lose the others. Before any shard persists its
+/// part, the whole batch is appended to this log and synced, under a new commit number;
+/// each part also sets the shard's commit marker to that number, in the same atomic step.
+/// When the database is opened again, every batch of the log whose number is past the
+/// marker of one of its shards is applied to that shard again: a shard persists in order,
+/// so it lost that part and everything it persisted after it.
+///
+/// The log is emptied once every shard has synced its parts, on `reset`.
+#[derive(Debug)]
+pub(crate) struct CommitLog {
+    file: File,
+    size: u64,
+    durability: Durability,
+    /// Number of the next commit appended.
+    next_id: u64,
+    /// A shard failed to persist its part of a batch, which only the next opening
+    /// completes: the log must keep it until then.
+    unfinished: bool,
+}
+
+impl CommitLog {
+    /// Opens (or creates) the log at `path` and reads back every intact batch, dropping a
+    /// torn one at the tail.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((CommitLog, Vec<Intent>))` - The open log and the batches it holds, in order.
+    /// * `Err(Error)` - An error message if the file can't be opened or read.
+    pub(crate) fn open(path: &str, durability: Durability) -> Result<(CommitLog, Vec<Intent>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        let mut intents: Vec<Intent> = Vec::new();
+        let mut offset = 0;
+        while let Some((intent, next)) = decode_record(&buffer, offset) {
+            intents.push(intent);
+            offset = next;
+        }
+        if offset < buffer.len() {
+            file.set_len(offset as u64)?; // Torn while appended, before any shard persisted its part
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let next_id = intents.last().map_or(1, |intent| intent.id + 1);
+        Ok((
+            CommitLog {
+                file,
+                size: offset as u64,
+                durability,
+                next_id,
+                unfinished: false,
+            },
+            intents,
+        ))
+    }
+
+    /// Appends a batch, and syncs it unless the durability is `Durability::None`, so it is
+    /// on disk before any of its parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the batch, see `next_id`.
+    /// * `parts` - The mutations of each shard, by shard index, commit markers included.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the batch is logged.
+    /// * `Err(Error)` - An error message if it could not be written, the log is then unchanged.
+    pub(crate) fn append(&mut self, id: u64, parts: Vec<(usize, &[Mutation])>) -> Result<()> {
+        let record = encode_record(&IntentRef { id, parts })?;
+        append_record(&mut self.file, self.size, &record)?;
+        self.size += record.len() as u64;
+        self.next_id = id + 1;
+        if self.durability != Durability::None {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number the next batch gets.
+    pub(crate) fn next_id(&self) -> u64 {
+        self.next_id
+    }
+
+    /// Makes the next batch get a number past `id`, e.g. past the commit markers of the
+    /// shards once the log is emptied.
+    pub(crate) fn skip_to(&mut self, id: u64) {
+        self.next_id = self.next_id.max(id + 1);
+    }
+
+    /// Records that a shard failed to persist its part of a batch, so `reset` keeps the log.
+    pub(crate) fn mark_unfinished(&mut self) {
+        self.unfinished = true;
+    }
+
+    /// Returns the size of the log in bytes.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Empties the log, once every shard has synced its parts, unless one of them failed.
+    pub(crate) fn reset(&mut self) -> Result<()> {
+        if self.unfinished {
+            return Ok(());
+        }
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_all()?;
+        self.size = 0;
+        Ok(())
+    }
+}
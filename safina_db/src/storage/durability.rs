@@ -0,0 +1,253 @@
+use std::fs::File;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+
+/// When the records a backend appends to its log are synced to disk.
+///
+/// Syncing is what makes a write survive a power loss or an OS crash, and it is by far the
+/// most expensive part of a write. The modes trade how much an acknowledged write can lose
+/// for how many writes go through per second. Whatever the mode, `flush` and `close` sync
+/// everything written so far.
+///
+/// Only the log based backends (`Storage` and `Bitcask`) honour the mode: the JSON backend
+/// rewrites and syncs its whole file on every mutation, and the memory backend has no file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Every commit is synced before it is acknowledged. A crash loses nothing acknowledged.
+    #[default]
+    Always,
+    /// Commits share syncs: the first commit waiting for a sync gives the others the window
+    /// to join it, then one sync covers all of them. A crash still loses nothing
+    /// acknowledged, but a lone writer pays the window on every commit.
+    GroupCommit(Duration),
+    /// Commits are acknowledged as soon as they are written, and a background thread syncs
+    /// the log at every interval. A crash loses at most the last interval of commits.
+    Periodic(Duration),
+    /// Commits are never synced, the OS writes them back whenever it sees fit. A crash of
+    /// the process loses nothing, a crash of the machine can lose anything since the last
+    /// `flush`.
+    None,
+}
+
+/// Syncs the file a backend appends its records to, as its `Durability` says.
+///
+/// Appended records are numbered: a sync covers every record appended before it started,
+/// so a commit is durable once the sync count passes its number.
+#[derive(Debug)]
+pub(crate) struct Syncer {
+    durability: Durability,
+    state: Mutex<SyncState>,
+    /// Notified whenever a sync finishes.
+    synced: Condvar,
+    /// Dropped with the syncer, stopping the `Periodic` thread.
+    _stop: Option<mpsc::Sender<()>>,
+}
+
+/// The progress of a `Syncer`.
+#[derive(Debug)]
+struct SyncState {
+    /// The file being appended to, shared with a running sync.
+    file: Arc<File>,
+    /// Number of the last record appended.
+    written: u64,
+    /// Number of the last record known to be on disk.
+    synced: u64,
+    /// A sync is running, the others wait for it rather than start their own.
+    syncing: bool,
+}
+
+/// A commit waiting for a sync, see `Durability::GroupCommit`.
+///
+/// A store hands it back to its caller, so the caller can wait once it has released the
+/// store and other writers have been able to commit meanwhile.
+#[derive(Debug)]
+pub struct SyncTicket {
+    syncer: Arc<Syncer>,
+    position: u64,
+}
+
+/// The point a commit was appended at, reached once a sync covers it.
+///
+/// Unlike a `SyncTicket`, waiting for a watermark never starts a sync: it lets a reader hold
+/// back what it hands out until the writer's sync, or the `Periodic` thread, made it durable.
+#[derive(Debug, Clone)]
+pub(crate) struct Watermark {
+    syncer: Arc<Syncer>,
+    position: u64,
+}
+
+impl Syncer {
+    /// Starts syncing `file`, spawning the background thread of the `Periodic` mode.
+    pub(crate) fn new(file: File, durability: Durability) -> Arc<Syncer> {
+        Arc::new_cyclic(|syncer: &Weak<Syncer>| {
+            let stop = match durability {
+                Durability::Periodic(interval) => Some(spawn_periodic(syncer.clone(), interval)),
+                _ => None,
+            };
+            Syncer {
+                durability,
+                state: Mutex::new(SyncState {
+                    file: Arc::new(file),
+                    written: 0,
+                    synced: 0,
+                    syncing: false,
+                }),
+                synced: Condvar::new(),
+                _stop: stop,
+            }
+        })
+    }
+
+    /// Records that a commit was appended to the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(SyncTicket))` - With `GroupCommit`, the sync the commit has to wait for.
+    ///   With `Periodic`, a ticket that is acknowledged right away, but whose `watermark`
+    ///   tells when the background thread synced the commit.
+    /// * `Ok(None)` - If the commit can be acknowledged: synced with `Always`, never synced
+    ///   with `None`.
+    /// * `Err(Error)` - An error message if the file could not be synced.
+    pub(crate) fn record(self: &Arc<Self>) -> Result<Option<SyncTicket>> {
+        let position = {
+            let mut state = self.state.lock().unwrap();
+            state.written += 1;
+            state.written
+        };
+        match self.durability {
+            Durability::Always => self.sync_to(position, Duration::ZERO).map(|_| None),
+            Durability::GroupCommit(_) | Durability::Periodic(_) => Ok(Some(SyncTicket {
+                syncer: Arc::clone(self),
+                position,
+            })),
+            Durability::None => Ok(None),
+        }
+    }
+
+    /// Syncs every record appended so far.
+    pub(crate) fn sync(&self) -> Result<()> {
+        let position = self.state.lock().unwrap().written;
+        self.sync_to(position, Duration::ZERO)
+    }
+
+    /// Records that every record appended so far is on disk, e.g. once a checkpoint saved
+    /// them elsewhere and truncated the file.
+    pub(crate) fn mark_synced(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.synced = state.written;
+        self.synced.notify_all();
+    }
+
+    /// Switches to a new file once the previous one is synced, e.g. when a segment rolls over.
+    pub(crate) fn replace_file(&self, file: File) {
+        let mut state = self.state.lock().unwrap();
+        state.file = Arc::new(file);
+        state.synced = state.written; // The caller synced the previous file
+        self.synced.notify_all();
+    }
+
+    /// Waits until record `position` is on disk, syncing the file if no sync already running
+    /// covers it. A syncing thread first waits for `window`, so more commits join its sync.
+    fn sync_to(&self, position: u64, window: Duration) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.synced >= position {
+                return Ok(());
+            }
+            if !state.syncing {
+                break;
+            }
+            state = self.synced.wait(state).unwrap(); // Followers wait for the running sync
+        }
+        state.syncing = true;
+        drop(state);
+
+        if !window.is_zero() {
+            thread::sleep(window);
+        }
+        let (file, target) = {
+            let state = self.state.lock().unwrap();
+            (Arc::clone(&state.file), state.written)
+        };
+        let result = file.sync_data();
+
+        let mut state = self.state.lock().unwrap();
+        state.syncing = false;
+        if result.is_ok() {
+            state.synced = state.synced.max(target);
+        }
+        self.synced.notify_all(); // On failure a follower retries the sync for itself
+        Ok(result?)
+    }
+}
+
+impl SyncTicket {
+    /// Waits until the commit is on disk.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Once the commit is durable and can be acknowledged.
+    /// * `Err(Error)` - An error message if the sync failed; the commit may or may not
+    ///   survive a crash.
+    pub fn wait(self) -> Result<()> {
+        match self.syncer.durability {
+            Durability::GroupCommit(window) => self.syncer.sync_to(self.position, window),
+            _ => Ok(()), // Left to the `Periodic` thread
+        }
+    }
+
+    /// Returns the watermark of the commit, see `Watermark`.
+    pub(crate) fn watermark(&self) -> Watermark {
+        Watermark {
+            syncer: Arc::clone(&self.syncer),
+            position: self.position,
+        }
+    }
+}
+
+impl Watermark {
+    /// Waits until a sync covers the watermark, without syncing.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - When to stop waiting, `None` to wait for as long as it takes.
+    ///
+    /// # Returns
+    ///
+    /// `true` once the commit is on disk, `false` if the deadline passed first.
+    pub(crate) fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut state = self.syncer.state.lock().unwrap();
+        while state.synced < self.position {
+            state = match deadline {
+                None => self.syncer.synced.wait(state).unwrap(),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return false;
+                    }
+                    self.syncer.synced.wait_timeout(state, left).unwrap().0
+                }
+            };
+        }
+        true
+    }
+}
+
+/// Starts the thread syncing the file of `syncer` every `interval`, until the returned
+/// sender is dropped.
+fn spawn_periodic(syncer: Weak<Syncer>, interval: Duration) -> mpsc::Sender<()> {
+    let (stop, stopped) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+            let Some(syncer) = syncer.upgrade() else {
+                return;
+            };
+            let _ = syncer.sync(); // A failed sync is retried on the next round
+        }
+    });
+    stop
+}
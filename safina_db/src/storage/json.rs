@@ -0,0 +1,139 @@
+use std::fs;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use crate::error::{Error, Result};
+use crate::kv_store::KV;
+use crate::merge::MergeOperators;
+use crate::storage::{apply, by_key, into_pairs, snapshot, Mutation, StorageBackend};
+
+/// A backend keeping the whole dataset in a human readable JSON file.
+///
+/// The file holds an array of `{"key": .., "value": ..}` objects, with an `"expires_at"`
+/// field for pairs that expire. Keys and values that are valid UTF-8 are stored as plain
+/// strings, anything else as a `{"base64": ..}` object. There is no log: every mutation
+/// rewrites the whole file atomically (see `snapshot::write_atomic`), which makes this
+/// backend convenient to inspect and edit by hand but slow for large datasets.
+#[derive(Debug)]
+pub struct JsonStorage {
+    file_path: String,
+    /// Folds the merge operands, since the file only holds whole values.
+    merge_operators: MergeOperators,
+}
+
+impl JsonStorage {
+    /// Creates a new `JsonStorage` for the file at `file_path`. Nothing is read until `load`.
+    pub fn new(file_path: &str) -> Self {
+        JsonStorage {
+            file_path: file_path.to_string(),
+            merge_operators: MergeOperators::default(),
+        }
+    }
+
+    /// Makes the storage fold merge operands with `operators`, see `Options::merge_operators`.
+    pub fn with_merge_operators(mut self, operators: MergeOperators) -> Self {
+        self.merge_operators = operators;
+        self
+    }
+
+    /// Atomically replaces the file with the JSON encoding of `data`.
+    fn save(&self, data: &[KV]) -> Result<()> {
+        let pairs: Vec<JsonPair> = data.iter().map(JsonPair::from).collect();
+        let buffer = serde_json::to_vec_pretty(&pairs)?;
+        snapshot::write_atomic(&self.file_path, &buffer)
+    }
+}
+
+impl StorageBackend for JsonStorage {
+    fn load(&mut self) -> Result<Vec<KV>> {
+        match fs::read(&self.file_path) {
+            Ok(buffer) if buffer.is_empty() => Ok(Vec::new()),
+            Ok(buffer) => {
+                let pairs: Vec<JsonPair> = serde_json::from_slice(&buffer)?;
+                pairs.into_iter().map(KV::try_from).collect()
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn persist(
+        &mut self,
+        mutation: &Mutation,
+        snapshot: &dyn Fn() -> Result<Vec<KV>>,
+    ) -> Result<()> {
+        let mut data = by_key(snapshot()?);
+        apply(&mut data, mutation.clone(), &self.merge_operators)?;
+        self.save(&into_pairs(data))
+    }
+
+    fn flush(&mut self, data: Vec<KV>) -> Result<()> {
+        self.save(&data)
+    }
+
+    fn close(&mut self, data: Vec<KV>) -> Result<()> {
+        self.flush(data)
+    }
+}
+
+/// A pair as it is written in the JSON file.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct JsonPair {
+    key: JsonBytes,
+    value: JsonBytes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+/// A byte string in the JSON file: plain text when possible, base64 otherwise.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum JsonBytes {
+    Text(String),
+    Binary { base64: String },
+}
+
+impl From<&KV> for JsonPair {
+    fn from(pair: &KV) -> Self {
+        JsonPair {
+            key: JsonBytes::from(pair.key.as_slice()),
+            value: JsonBytes::from(pair.value.as_slice()),
+            expires_at: pair.expires_at,
+        }
+    }
+}
+
+impl TryFrom<JsonPair> for KV {
+    type Error = Error;
+
+    fn try_from(pair: JsonPair) -> Result<Self> {
+        Ok(KV {
+            key: pair.key.into_bytes()?,
+            value: pair.value.into_bytes()?,
+            expires_at: pair.expires_at,
+        })
+    }
+}
+
+impl From<&[u8]> for JsonBytes {
+    fn from(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => JsonBytes::Text(text.to_string()),
+            Err(_) => JsonBytes::Binary {
+                base64: BASE64.encode(bytes),
+            },
+        }
+    }
+}
+
+impl JsonBytes {
+    fn into_bytes(self) -> Result<Vec<u8>> {
+        match self {
+            JsonBytes::Text(text) => Ok(text.into_bytes()),
+            JsonBytes::Binary { base64 } => BASE64
+                .decode(base64)
+                .map_err(|e| Error::Corruption(format!("invalid base64: {e}"))),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::error::Result;
+use crate::kv_store::KV;
+use crate::storage::{Mutation, StorageBackend};
+
+/// A backend that persists nothing: the store lives in memory only and starts empty.
+///
+/// Useful for tests and for caches that don't need to survive a restart.
+#[derive(Debug, Default)]
+pub struct MemoryBackend;
+
+impl MemoryBackend {
+    /// Creates a new `MemoryBackend`.
+    pub fn new() -> Self {
+        MemoryBackend
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn load(&mut self) -> Result<Vec<KV>> {
+        Ok(Vec::new())
+    }
+
+    fn persist(
+        &mut self,
+        _mutation: &Mutation,
+        _snapshot: &dyn Fn() -> Result<Vec<KV>>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self, _data: Vec<KV>) -> Result<()> {
+        Ok(())
+    }
+
+    fn close(&mut self, _data: Vec<KV>) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use crate::error::{Error, Result};
+use crate::kv_store::KV;
+
+/// Bytes opening a snapshot in the current format, followed by the `u64` generation of the
+/// write-ahead log it is followed by, then the bincode encoding of a `Vec<KV>`.
+const MAGIC: &[u8; 8] = b"SAFINA\x00\x03";
+
+/// Bytes opening a snapshot written before the generations, followed by the bincode encoding
+/// of a `Vec<KV>`. Snapshots written before pairs could expire have no header at all: they
+/// start with the `u64` length of the vector, which can't plausibly match either header.
+const MAGIC_V2: &[u8; 8] = b"SAFINA\x00\x02";
+
+/// A pair as written by versions without expiry support.
+#[derive(serde::Deserialize)]
+struct LegacyKV {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+/// Serializes the content of the store into the current snapshot format, along with the
+/// generation of the write-ahead log that follows it.
+pub fn encode(data: &[KV], generation: u64) -> Result<Vec<u8>> {
+    let mut buffer = MAGIC.to_vec();
+    buffer.extend_from_slice(&generation.to_le_bytes());
+    bincode::serialize_into(&mut buffer, data)?;
+    Ok(buffer)
+}
+
+/// Deserializes a snapshot, in the current format or an older one.
+///
+/// # Returns
+///
+/// * `Ok((Vec<KV>, u64))` - The pairs, and the generation of the write-ahead log following
+///   the snapshot: logs of older generations are already folded into it. Snapshots written
+///   before the generations have generation `0`.
+/// * `Err(Error)` - An error message if the snapshot can't be decoded.
+pub fn decode(buffer: &[u8]) -> Result<(Vec<KV>, u64)> {
+    if buffer.is_empty() {
+        return Ok((Vec::new(), 0));
+    }
+    if let Some(rest) = buffer.strip_prefix(MAGIC) {
+        let (generation, payload) = rest
+            .split_at_checked(8)
+            .ok_or_else(|| Error::Corruption("truncated snapshot header".to_string()))?;
+        return Ok((
+            bincode::deserialize(payload)?,
+            u64::from_le_bytes(generation.try_into()?),
+        ));
+    }
+    match buffer.strip_prefix(MAGIC_V2) {
+        Some(payload) => Ok((bincode::deserialize(payload)?, 0)),
+        None => {
+            let pairs: Vec<LegacyKV> = bincode::deserialize(buffer)?;
+            let pairs = pairs
+                .into_iter()
+                .map(|pair| KV::new(pair.key, pair.value))
+                .collect();
+            Ok((pairs, 0))
+        }
+    }
+}
+
+/// Returns the path of the temporary file a snapshot of `path` is staged in.
+pub fn temp_path(path: &str) -> String {
+    format!("{path}.tmp")
+}
+
+/// Atomically replaces the content of the file at `path` with `buffer`.
+///
+/// The buffer is written to a sibling temporary file which is synced, then renamed over the
+/// target, and finally the parent directory is synced so the rename itself is durable.
+/// Since a rename within a directory is atomic, a reader (or a restart after a crash) always
+/// finds either the old complete snapshot or the new one, never a partially written file.
+///
+/// # Arguments
+///
+/// * `path` - The path of the snapshot file to replace.
+/// * `buffer` - The complete new content of the snapshot.
+///
+/// # Returns
+///
+/// * `Ok(())` - Once the new snapshot is durable under `path`.
+/// * `Err(Error)` - An error message if any step fails; the old snapshot is left intact.
+pub fn write_atomic(path: &str, buffer: &[u8]) -> Result<()> {
+    let tmp_path = temp_path(path);
+    let mut tmp = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true) // Discard whatever an interrupted snapshot left behind
+        .open(&tmp_path)?;
+    if let Err(e) = tmp.write_all(buffer).and_then(|_| tmp.sync_all()) {
+        let _ = fs::remove_file(&tmp_path); // Don't leave a partial snapshot lying around
+        return Err(e.into());
+    }
+    drop(tmp);
+
+    fs::rename(&tmp_path, path)?; // Atomically swap the new snapshot in
+    sync_parent_dir(path)
+}
+
+/// Syncs the directory containing `path`, making a rename into it durable.
+#[cfg(unix)]
+fn sync_parent_dir(path: &str) -> Result<()> {
+    use std::fs::File;
+    use std::path::Path;
+
+    let parent = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+/// Directories can't be opened and synced on this platform, the rename is left to the OS.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &str) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::storage::durability::{Durability, SyncTicket, Syncer};
+
+/// Size in bytes of a record header: a `u32` payload length followed by a `u32` CRC32 checksum.
+const HEADER_SIZE: usize = 8;
+
+/// Bytes opening a log that records its generation, followed by the generation as a `u64`.
+/// Logs written before generations have no header: they start with a record, whose length
+/// can't plausibly match these bytes, and are generation `0`.
+const MAGIC: &[u8; 8] = b"SAFWAL\x00\x01";
+
+/// Size in bytes of the header opening a log with a generation.
+const FILE_HEADER_SIZE: usize = MAGIC.len() + 8;
+
+/// A single mutation applied to the store, as recorded in the write-ahead log.
+///
+/// New variants are only ever added at the end, so logs written by older versions still decode.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub enum Mutation {
+    /// Sets `key` to `value`, whether or not the key already exists. The pair never expires.
+    Put { key: Vec<u8>, value: Vec<u8> },
+    /// Removes `key` from the store.
+    Delete { key: Vec<u8> },
+    /// Sets `key` to `value`, expiring at `expires_at` (milliseconds since the Unix epoch).
+    PutExpiring {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expires_at: u64,
+    },
+    /// Changes the expiry time of an existing `key`; `None` makes it never expire.
+    Expire {
+        key: Vec<u8>,
+        expires_at: Option<u64>,
+    },
+    /// Applies several mutations in order, as one atomic unit.
+    Batch { mutations: Vec<Mutation> },
+    /// Folds `operand` into the value of `key` with the merge operator named `operator`,
+    /// keeping its expiry time. See `merge::MergeOperator`.
+    Merge {
+        key: Vec<u8>,
+        operator: String,
+        operand: Vec<u8>,
+    },
+}
+
+impl Mutation {
+    /// Builds the mutation setting `key` to `value` with the given expiry time.
+    pub fn put(key: &[u8], value: &[u8], expires_at: Option<u64>) -> Mutation {
+        match expires_at {
+            Some(expires_at) => Mutation::PutExpiring {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                expires_at,
+            },
+            None => Mutation::Put {
+                key: key.to_vec(),
+                value: value.to_vec(),
+            },
+        }
+    }
+
+    /// Returns the key the mutation changes, or `None` for a batch.
+    pub fn key(&self) -> Option<&[u8]> {
+        match self {
+            Mutation::Put { key, .. }
+            | Mutation::Delete { key }
+            | Mutation::PutExpiring { key, .. }
+            | Mutation::Expire { key, .. }
+            | Mutation::Merge { key, .. } => Some(key),
+            Mutation::Batch { .. } => None,
+        }
+    }
+}
+
+/// An append-only write-ahead log.
+///
+/// Every record is framed as `[len: u32 LE][crc32: u32 LE][payload]`, where the payload is the
+/// bincode encoding of a `Mutation`. Each time the log is emptied by `reset`, it starts a new
+/// generation, written in a header before the first record: a snapshot records the
+/// generation of the log following it, so the records it already covers are never replayed
+/// twice (see `Storage::save_file`). A record is only considered written once it has been
+/// synced to disk, when the log's `Durability` says. A crash can at worst leave a torn record
+/// at the tail of the log, which is detected by its length or checksum and discarded on the
+/// next open.
+#[derive(Debug)]
+pub struct Wal {
+    file: File,
+    size: u64,
+    generation: u64,
+    syncer: Arc<Syncer>,
+}
+
+impl Wal {
+    /// Opens (or creates) the log at the given path and reads back every intact record.
+    ///
+    /// Replay stops at the first truncated or corrupted record; the file is then cut back to
+    /// the end of the last valid record so new appends never follow garbage.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the log file.
+    /// * `durability` - When appended records are synced to disk.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Wal, Vec<Mutation>))` - The opened log and the mutations it contains, in order.
+    /// * `Err(Error)` - An error message if the file can't be opened or read.
+    pub fn open(path: &str, durability: Durability) -> Result<(Wal, Vec<Mutation>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false) // Keep the existing records, they still have to be replayed
+            .open(path)?;
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+
+        let (generation, mut offset) = match buffer.strip_prefix(MAGIC) {
+            Some(rest) if rest.len() >= 8 => {
+                (u64::from_le_bytes(rest[..8].try_into()?), FILE_HEADER_SIZE)
+            }
+            _ => (0, 0), // A log without header, or one whose header was torn while reset
+        };
+        let mut mutations = Vec::new();
+        while let Some((mutation, next)) = decode_record(&buffer, offset) {
+            mutations.push(mutation);
+            offset = next;
+        }
+
+        if offset < buffer.len() {
+            file.set_len(offset as u64)?; // Drop the torn tail left by an interrupted append
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let syncer = Syncer::new(file.try_clone()?, durability);
+
+        Ok((
+            Wal {
+                file,
+                size: offset as u64,
+                generation,
+                syncer,
+            },
+            mutations,
+        ))
+    }
+
+    /// Appends a mutation to the log, syncing it to disk as the log's `Durability` says.
+    ///
+    /// # Arguments
+    ///
+    /// * `mutation` - The mutation to record.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(None)` - Once the record can be acknowledged.
+    /// * `Ok(Some(SyncTicket))` - With `Durability::GroupCommit` or `Periodic`, the sync the
+    ///   record waits for, see `Syncer::record`.
+    /// * `Err(Error)` - An error message if the record could not be written.
+    pub fn append(&mut self, mutation: &Mutation) -> Result<Option<SyncTicket>> {
+        let record = encode_record(mutation)?;
+        append_record(&mut self.file, self.size, &record)?;
+        self.size += record.len() as u64;
+        self.syncer.record()
+    }
+
+    /// Syncs every record appended so far to disk.
+    pub fn sync(&self) -> Result<()> {
+        self.syncer.sync()
+    }
+
+    /// Empties the log, typically after its content has been folded into a snapshot, and
+    /// starts generation `generation`.
+    pub fn reset(&mut self, generation: u64) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&generation.to_le_bytes());
+        append_record(&mut self.file, 0, &header)?;
+        self.file.sync_all()?;
+        self.syncer.mark_synced(); // The records are in the snapshot
+        self.size = header.len() as u64;
+        self.generation = generation;
+        Ok(())
+    }
+
+    /// Returns the generation of the log, `0` until it is first reset.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the current size of the log in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A file records are appended to, which can be cut back after a failed append.
+pub trait LogFile: Write + Seek {
+    /// Truncates the file to `len` bytes.
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl LogFile for File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+/// Writes `record` at `end`, the current end of `file`.
+///
+/// A write failing partway leaves torn bytes behind, and a valid record appended after them
+/// would be dropped with them the next time the file is read back. So on failure the file is
+/// cut back to `end`, and its cursor moved back there, before the error is returned.
+///
+/// # Returns
+///
+/// * `Ok(())` - Once the whole record is written.
+/// * `Err(Error)` - The write error, the file then ends at `end` again.
+pub fn append_record<F: LogFile>(file: &mut F, end: u64, record: &[u8]) -> Result<()> {
+    if let Err(e) = file.write_all(record) {
+        cut_back(file, end)?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Truncates `file` to `end` and moves its cursor there, dropping what was appended after.
+pub fn cut_back<F: LogFile>(file: &mut F, end: u64) -> Result<()> {
+    file.set_len(end)?;
+    file.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Serializes a mutation, or any other entry, into a framed, checksummed record.
+pub(crate) fn encode_record<T: serde::Serialize>(entry: &T) -> Result<Vec<u8>> {
+    let payload = bincode::serialize(entry)?;
+    let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    record.extend_from_slice(&payload);
+    Ok(record)
+}
+
+/// Decodes the record starting at `offset`.
+///
+/// # Returns
+///
+/// * `Some((T, usize))` - The decoded entry and the offset of the next record.
+/// * `None` - If the record is incomplete, fails its checksum or can't be deserialized.
+pub(crate) fn decode_record<T: serde::de::DeserializeOwned>(
+    buffer: &[u8],
+    offset: usize,
+) -> Option<(T, usize)> {
+    let header = buffer.get(offset..offset + HEADER_SIZE)?;
+    let len = u32::from_le_bytes(header[0..4].try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(header[4..8].try_into().ok()?);
+
+    let start = offset + HEADER_SIZE;
+    let payload = buffer.get(start..start.checked_add(len)?)?;
+    if crc32fast::hash(payload) != crc {
+        return None;
+    }
+    let entry = bincode::deserialize(payload).ok()?;
+    Some((entry, start + len))
+}
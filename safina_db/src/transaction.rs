@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::batch::WriteBatch;
+use crate::error::{Error, Result};
+use crate::kv_store::{expiry_after, now_millis, Store};
+use crate::shards::Shards;
+use crate::storage::{Mutation, SyncTicket};
+
+/// A write buffered by a transaction until it commits.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Write {
+    /// Sets a key that didn't exist, with the expiry time of the new pair.
+    Insert(Vec<u8>, Option<u64>),
+    /// Sets a key that existed, keeping its expiry time.
+    Update(Vec<u8>),
+    /// Removes a key.
+    Delete,
+}
+
+/// What a transaction commits to one shard.
+#[derive(Debug, Default)]
+struct ShardCommit {
+    /// Keys that must not have changed since the transaction began.
+    checked: HashSet<Vec<u8>>,
+    writes: BTreeMap<Vec<u8>, Write>,
+}
+
+/// How much a transaction is isolated from the transactions and writes running alongside it.
+///
+/// Every level is optimistic: nothing is locked while the transaction runs, conflicts are
+/// detected when it commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IsolationLevel {
+    /// Reads see the latest committed data, so reading a key twice may give two different
+    /// values. The commit never conflicts: the last transaction to commit a key wins.
+    ReadCommitted,
+    /// Reads see the data as it was when the transaction began. The commit fails if a key
+    /// the transaction writes was changed since then, but keys it only read are not checked,
+    /// so two transactions may each write what the other read (write skew).
+    /// Also known as repeatable read.
+    Snapshot,
+    /// Reads see the data as it was when the transaction began, and the commit fails if any
+    /// key the transaction read or wrote was changed since then, so committed transactions
+    /// behave as if they ran one after the other.
+    #[default]
+    Serializable,
+}
+
+impl IsolationLevel {
+    /// Returns `true` if transactions at this level read from a snapshot.
+    fn reads_snapshot(self) -> bool {
+        self != IsolationLevel::ReadCommitted
+    }
+}
+
+/// A set of reads and writes applied to a database as one atomic unit.
+///
+/// Writes are buffered in the transaction, where its own reads see them, and nothing
+/// reaches the store until `commit`. What the transaction's reads see, and which changes made
+/// by others after it began make the commit fail with `Error::Conflict`, depend on its
+/// `IsolationLevel`. A successful commit persists every write as a single batch, so after a
+/// crash either all of them are recovered or none is, whatever shards they span, see
+/// `Database::write_batch`. Dropping a transaction without committing it rolls it back.
+///
+/// Transactions are started with `Database::begin` or `Database::begin_with`, or run with
+/// `Database::transaction` or `Database::transaction_with`.
+#[derive(Debug)]
+pub struct Transaction {
+    shards: Arc<Shards>,
+    isolation: IsolationLevel,
+    /// The number the transaction started at in each shard.
+    starts: Vec<u64>,
+    /// When the transaction began, in milliseconds since the Unix epoch. Snapshot reads
+    /// consider pairs expired as of this time.
+    began_at: u64,
+    reads: HashSet<Vec<u8>>,
+    writes: BTreeMap<Vec<u8>, Write>,
+    /// The shards the transaction is still registered in, see `end`.
+    registered: Vec<bool>,
+}
+
+impl Transaction {
+    /// Starts a transaction on `shards` at the given isolation level.
+    ///
+    /// Every shard is locked while the transaction registers, so its snapshot is the same
+    /// point in time in all of them.
+    pub(crate) fn begin(shards: Arc<Shards>, isolation: IsolationLevel) -> Result<Transaction> {
+        let starts: Vec<u64> = shards
+            .write_all()?
+            .iter_mut()
+            .map(|store| {
+                if isolation.reads_snapshot() {
+                    store.take_snapshot(); // Same number as the transaction, taken under the same lock
+                }
+                store.begin_transaction()
+            })
+            .collect();
+        Ok(Transaction {
+            shards,
+            isolation,
+            began_at: now_millis(),
+            reads: HashSet::new(),
+            writes: BTreeMap::new(),
+            registered: vec![true; starts.len()],
+            starts,
+        })
+    }
+
+    /// Returns the isolation level of the transaction.
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    /// Returns the value of `key` as seen by the transaction, its own writes included.
+    ///
+    /// # Returns
+    /// * `Ok(Some(Vec<u8>))` - The value if the key exists.
+    /// * `Ok(None)` - If the key doesn't exist, has expired or was deleted by the transaction.
+    /// * `Err(Error::Merge)` - If the operands merged into the key can't be folded.
+    /// * `Err(Error::Poisoned)` - If the store lock is poisoned.
+    pub fn get<K: AsRef<[u8]>>(&mut self, key: K) -> Result<Option<Vec<u8>>> {
+        let key = key.as_ref();
+        match self.writes.get(key) {
+            Some(Write::Insert(value, _) | Write::Update(value)) => return Ok(Some(value.clone())),
+            Some(Write::Delete) => return Ok(None),
+            None => {}
+        }
+        if self.isolation == IsolationLevel::Serializable {
+            self.reads.insert(key.to_vec()); // Only serializable commits check what was read
+        }
+        let index = self.shards.index(key);
+        let store = self.shards.read(index)?;
+        if !self.isolation.reads_snapshot() {
+            return store.get(key);
+        }
+        Ok(store
+            .get_at(key, self.starts[index])?
+            .filter(|pair| !pair.is_expired(self.began_at))
+            .map(|pair| pair.value.clone()))
+    }
+
+    /// Buffers the insertion of a new key-value pair.
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the insertion is buffered.
+    /// * `Err(Error::KeyExists)` - If the key already exists.
+    pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        self.insert_expiring(key.as_ref(), value.as_ref(), None)
+    }
+
+    /// Buffers the insertion of a new key-value pair that expires once `ttl` has elapsed,
+    /// counted from now rather than from the commit.
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the insertion is buffered.
+    /// * `Err(Error::KeyExists)` - If the key already exists.
+    pub fn insert_with_ttl<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<()> {
+        self.insert_expiring(key.as_ref(), value.as_ref(), Some(expiry_after(ttl)))
+    }
+
+    fn insert_expiring(&mut self, key: &[u8], value: &[u8], expires_at: Option<u64>) -> Result<()> {
+        if self.get(key)?.is_some() {
+            return Err(Error::KeyExists);
+        }
+        self.writes
+            .insert(key.to_vec(), Write::Insert(value.to_vec(), expires_at));
+        Ok(())
+    }
+
+    /// Buffers the update of an existing key, which keeps its expiry time.
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the update is buffered.
+    /// * `Err(Error::KeyNotFound)` - If the key doesn't exist.
+    pub fn update<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        let key = key.as_ref();
+        if self.get(key)?.is_none() {
+            return Err(Error::KeyNotFound);
+        }
+        let value = value.as_ref().to_vec();
+        let write = match self.writes.get(key) {
+            Some(Write::Insert(_, expires_at)) => Write::Insert(value, *expires_at), // Still a new pair once committed
+            _ => Write::Update(value),
+        };
+        self.writes.insert(key.to_vec(), write);
+        Ok(())
+    }
+
+    /// Buffers the deletion of a key. Deleting a key that doesn't exist is not an error.
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<()> {
+        self.writes.insert(key.as_ref().to_vec(), Write::Delete);
+        Ok(())
+    }
+
+    /// Buffers every write of `batch`, in order. Puts don't check whether their key exists,
+    /// see `WriteBatch`.
+    pub fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        for mutation in batch.mutations {
+            let (key, write) = match mutation {
+                Mutation::Put { key, value } => (key, Write::Insert(value, None)), // Replaces the expiry too
+                Mutation::PutExpiring {
+                    key,
+                    value,
+                    expires_at,
+                } => (key, Write::Insert(value, Some(expires_at))),
+                Mutation::Delete { key } => (key, Write::Delete),
+                _ => unreachable!("a batch only holds puts and deletes"),
+            };
+            self.writes.insert(key, write);
+        }
+        Ok(())
+    }
+
+    /// Checks for conflicts and applies every buffered write as one atomic batch.
+    ///
+    /// # Returns
+    /// * `Ok(())` - If every write is persisted and applied.
+    /// * `Err(Error::Conflict)` - If a key the isolation level checks was changed since the
+    ///   transaction began, in which case nothing is written.
+    /// * `Err(Error)` - If the batch could not be persisted, see `Database::write_batch`.
+    pub fn commit(mut self) -> Result<()> {
+        let locks = Arc::clone(&self.shards); // Guards borrow it while `end` changes `self`
+        let mut shards: BTreeMap<usize, ShardCommit> = BTreeMap::new(); // Only the shards touched
+        for (key, write) in std::mem::take(&mut self.writes) {
+            let shard = shards.entry(self.shards.index(&key)).or_default();
+            if self.isolation != IsolationLevel::ReadCommitted {
+                shard.checked.insert(key.clone());
+            }
+            shard.writes.insert(key, write);
+        }
+        for key in std::mem::take(&mut self.reads) {
+            let shard = shards.entry(self.shards.index(&key)).or_default();
+            shard.checked.insert(key); // Only serializable transactions record their reads
+        }
+
+        let mut stores = Vec::with_capacity(shards.len());
+        for &index in shards.keys() {
+            stores.push((index, locks.write(index)?)); // In index order, see `Shards`
+        }
+        let mut result = shards
+            .values()
+            .zip(&stores)
+            .try_for_each(|(shard, (index, store))| {
+                store.check_conflicts(self.starts[*index], &shard.checked)
+            });
+        if result.is_ok() {
+            result = locks.commit(
+                shards
+                    .into_values()
+                    .zip(stores.iter_mut())
+                    .map(|(shard, (index, store))| {
+                        let mutations = store.commit_mutations(shard.writes);
+                        (&mut **store, *index, mutations)
+                    })
+                    .collect(),
+            );
+        }
+        let mut syncs = Vec::new();
+        for (index, store) in stores.iter_mut() {
+            self.end(store, *index);
+            syncs.extend(store.take_syncs());
+        }
+        let touched: HashSet<usize> = stores.iter().map(|(index, _)| *index).collect();
+        drop(stores);
+        for index in (0..locks.len()).filter(|index| !touched.contains(index)) {
+            self.end(&mut *locks.write(index)?, index); // Dropping ends the shards left on error
+        }
+        result?;
+        syncs.into_iter().try_for_each(SyncTicket::wait)?; // Shards unlocked
+        locks.trim_commit_log()
+    }
+
+    /// Discards every buffered write.
+    pub fn rollback(self) {
+        drop(self); // Dropping unregisters the transaction
+    }
+
+    /// Unregisters the transaction from shard `index`, and its snapshot if it has one.
+    /// Does nothing if it already was, so every path out of `commit` can end it.
+    fn end(&mut self, store: &mut Store, index: usize) {
+        if !std::mem::replace(&mut self.registered[index], false) {
+            return;
+        }
+        let start = self.starts[index];
+        store.end_transaction(start);
+        if self.isolation.reads_snapshot() {
+            store.release_snapshot(start);
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        let shards = Arc::clone(&self.shards);
+        for index in 0..shards.len() {
+            if !self.registered[index] {
+                continue;
+            }
+            if let Ok(mut store) = shards.write(index) {
+                self.end(&mut store, index);
+            }
+        }
+    }
+}
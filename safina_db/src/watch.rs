@@ -0,0 +1,145 @@
+use std::cell::RefCell;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use crate::keyspace;
+use crate::kv_store::Value;
+use crate::storage::durability::Watermark;
+
+/// What happened to the key of an `Event`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// The key was set, by an insert, an update, a merge or a batch.
+    Put,
+    /// The key was deleted.
+    Delete,
+    /// The key was deleted once its expiry time had passed, e.g. by the sweeper.
+    Expire,
+}
+
+/// A change to a watched key, see `Store::watch`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub key: Vec<u8>,
+    /// The new value for `EventKind::Put`, `None` otherwise.
+    pub value: Option<Value>,
+    /// Number of the mutation that made the change, which is also the new version of the
+    /// key. Numbers grow with every change of the store holding the key.
+    pub seq: u64,
+}
+
+/// A subscription to the changes of the keys starting with a prefix, returned by
+/// `Store::watch` and `Database::watch`.
+///
+/// Events are queued as the store applies each change, once it has been persisted, so they
+/// arrive in the order the changes were made to each key. An event is only handed out once
+/// the change is on disk: with `Durability::GroupCommit` or `Periodic`, it waits for the sync
+/// covering the change, so a watcher never sees a change a crash could still lose. The queue
+/// is unbounded: a watcher that isn't read holds every event since it was created. Dropping
+/// the watcher unsubscribes it on the next change.
+///
+/// The watcher is also an iterator, which blocks until the next event and ends once every
+/// store it watches has been dropped.
+#[derive(Debug)]
+pub struct Watcher {
+    receiver: Receiver<Queued>,
+    /// The next event, taken off the queue before its change was on disk.
+    head: RefCell<Option<Queued>>,
+}
+
+/// An event, with the syncs its change waits for.
+type Queued = (Event, Vec<Watermark>);
+
+/// The sending half of a watcher, kept by a store.
+#[derive(Debug, Clone)]
+pub(crate) struct Subscriber {
+    prefix: Vec<u8>,
+    /// Skip the keys reserved for keyspaces and the catalog, see `Database::watch`.
+    hide_reserved: bool,
+    sender: Sender<Queued>,
+}
+
+impl Watcher {
+    /// Creates a watcher for the keys starting with `prefix`, with the subscriber stores
+    /// notify it through. With `hide_reserved`, the reserved keys are skipped.
+    pub(crate) fn new(prefix: &[u8], hide_reserved: bool) -> (Watcher, Subscriber) {
+        let (sender, receiver) = mpsc::channel();
+        let subscriber = Subscriber {
+            prefix: prefix.to_vec(),
+            hide_reserved,
+            sender,
+        };
+        let watcher = Watcher {
+            receiver,
+            head: RefCell::new(None),
+        };
+        (watcher, subscriber)
+    }
+
+    /// Waits for the next event.
+    ///
+    /// # Returns
+    /// * `Some(Event)` - The next event.
+    /// * `None` - If every store the watcher watches has been dropped.
+    pub fn recv(&self) -> Option<Event> {
+        self.next_before(None)
+    }
+
+    /// Returns the next event if one is queued and its change is on disk, without waiting.
+    pub fn try_recv(&self) -> Option<Event> {
+        self.next_before(Some(Instant::now()))
+    }
+
+    /// Waits up to `timeout` for the next event.
+    ///
+    /// # Returns
+    /// * `Some(Event)` - The next event.
+    /// * `None` - If no event came in time, or every store the watcher watches has been dropped.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Event> {
+        self.next_before(Instant::now().checked_add(timeout))
+    }
+
+    /// Waits up to `deadline` for the next event and for its change to be on disk, keeping
+    /// the event at the head of the queue if the deadline passes first.
+    fn next_before(&self, deadline: Option<Instant>) -> Option<Event> {
+        let mut head = self.head.borrow_mut();
+        if head.is_none() {
+            *head = Some(match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    self.receiver.recv_timeout(timeout).ok()?
+                }
+                None => self.receiver.recv().ok()?,
+            });
+        }
+        let (_, durable_at) = head.as_ref()?;
+        if !durable_at.iter().all(|watermark| watermark.wait(deadline)) {
+            return None;
+        }
+        head.take().map(|(event, _)| event)
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.recv()
+    }
+}
+
+impl Subscriber {
+    /// Returns `true` if the subscriber watches `key`.
+    pub(crate) fn watches(&self, key: &[u8]) -> bool {
+        key.starts_with(&self.prefix) && !(self.hide_reserved && keyspace::is_reserved(key))
+    }
+
+    /// Sends `event` to the watcher, which hands it out once the syncs `durable_at` are done.
+    ///
+    /// # Returns
+    /// `false` if the watcher has been dropped, so the subscriber can be removed.
+    pub(crate) fn notify(&self, event: Event, durable_at: Vec<Watermark>) -> bool {
+        self.sender.send((event, durable_at)).is_ok()
+    }
+}
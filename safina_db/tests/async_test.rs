@@ -0,0 +1,103 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-async-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::test_db_name;
+    use safina_db::{AsyncDatabase, Backend, Error, Options};
+
+    async fn memory_db() -> AsyncDatabase {
+        let options = Options {
+            backend: Backend::Memory,
+            ..Options::default()
+        };
+        AsyncDatabase::open("memory", options).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_crud() {
+        let db = memory_db().await;
+        db.insert("key1", "value1").await.unwrap();
+        db.insert("key2", "value2").await.unwrap();
+        assert!(matches!(
+            db.insert("key1", "again").await,
+            Err(Error::KeyExists)
+        ));
+        db.update("key1", "value1-updated").await.unwrap();
+        db.delete("key2").await.unwrap();
+
+        assert_eq!(
+            db.get("key1").await.unwrap(),
+            Some(b"value1-updated".to_vec())
+        );
+        assert_eq!(db.get("key2").await.unwrap(), None);
+        assert_eq!(db.scan(..).await.unwrap().len(), 1);
+        assert_eq!(db.scan_prefix("key").await.unwrap().len(), 1);
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_tasks_share_the_database() {
+        let db = memory_db().await;
+        let tasks: Vec<_> = (0..8)
+            .map(|t| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    for i in 0..50 {
+                        db.insert(format!("t{t}-key{i}"), "value").await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(db.scan(..).await.unwrap().len(), 400);
+        assert_eq!(db.database().scan_prefix("t3-").unwrap().len(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let db = memory_db().await;
+        db.insert("alice", "100").await.unwrap();
+        db.transaction(|tx| {
+            tx.update("alice", "70")?;
+            tx.insert("bob", "30")
+        })
+        .await
+        .unwrap();
+        assert_eq!(db.get("bob").await.unwrap(), Some(b"30".to_vec()));
+
+        let result: Result<(), Error> = db
+            .transaction(|tx| {
+                tx.delete("alice")?;
+                Err(Error::KeyNotFound)
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(db.get("alice").await.unwrap(), Some(b"70".to_vec())); // Rolled back
+    }
+
+    #[tokio::test]
+    async fn test_reopen_persisted_database() {
+        let db_name = test_db_name("reopen");
+        let db = AsyncDatabase::open(&db_name, Options::default())
+            .await
+            .unwrap();
+        db.insert("key1", "value1").await.unwrap();
+        db.close().await.unwrap();
+
+        let db = AsyncDatabase::open(&db_name, Options::default())
+            .await
+            .unwrap();
+        assert_eq!(db.get("key1").await.unwrap(), Some(b"value1".to_vec()));
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-backend-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::bitcask::{Bitcask, BitcaskOptions};
+    use safina_db::storage::{JsonStorage, MemoryBackend, StorageBackend};
+    use safina_db::{Storage, Store};
+
+    /// Runs the same mutations through a store on `backend`, then closes it.
+    fn write_through(backend: Box<dyn StorageBackend>) {
+        let mut store = Store::open(backend).unwrap();
+        store.insert("key1", "value1").unwrap();
+        store.insert("key2", "value2").unwrap();
+        store.insert("key3", "value3").unwrap();
+        store.update("key1", "value1-updated").unwrap();
+        store.delete("key2").unwrap();
+        store.close().unwrap();
+    }
+
+    /// Checks a store reopened after `write_through` holds the expected data.
+    fn assert_reloaded(backend: Box<dyn StorageBackend>) {
+        let store = Store::open(backend).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get("key1").unwrap().unwrap(), b"value1-updated");
+        assert!(store.get("key2").unwrap().is_none());
+        assert_eq!(store.get("key3").unwrap().unwrap(), b"value3");
+    }
+
+    #[test]
+    fn test_bincode_backend_round_trip() {
+        let db_name = test_db_name("bincode");
+        write_through(Box::new(Storage::new(Some(&db_name))));
+        assert_reloaded(Box::new(Storage::new(Some(&db_name))));
+    }
+
+    #[test]
+    fn test_json_backend_round_trip() {
+        let db_name = test_db_name("json");
+        write_through(Box::new(JsonStorage::new(&db_name)));
+        assert_reloaded(Box::new(JsonStorage::new(&db_name)));
+
+        let content = std::fs::read_to_string(&db_name).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+        assert!(content.contains("\"key\": \"key3\""));
+    }
+
+    #[test]
+    fn test_json_backend_persists_every_mutation() {
+        let db_name = test_db_name("json-crash");
+        let mut store = Store::open(Box::new(JsonStorage::new(&db_name))).unwrap();
+        store.insert("key1", "value1").unwrap();
+        drop(store); // No close: every mutation already rewrote the file
+
+        let store = Store::open(Box::new(JsonStorage::new(&db_name))).unwrap();
+        assert_eq!(store.get("key1").unwrap().unwrap(), b"value1");
+    }
+
+    #[test]
+    fn test_bitcask_backend_round_trip() {
+        let dir = test_db_name("bitcask");
+        let open = || Box::new(Bitcask::open(&dir, BitcaskOptions::default()).unwrap());
+        write_through(open());
+        assert_reloaded(open());
+    }
+
+    #[test]
+    fn test_memory_backend_starts_empty() {
+        write_through(Box::new(MemoryBackend::new()));
+        let store = Store::open(Box::new(MemoryBackend::new())).unwrap();
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_new_store_is_in_memory() {
+        let mut store = Store::new();
+        store.insert("key1", "value1").unwrap();
+        store.update("key1", "value1-updated").unwrap();
+        assert_eq!(store.get("key1").unwrap().unwrap(), b"value1-updated");
+        store.close().unwrap();
+    }
+}
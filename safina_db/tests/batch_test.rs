@@ -0,0 +1,137 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-batch-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::bitcask::BitcaskOptions;
+    use safina_db::{Backend, Database, Options, Store, WriteBatch};
+    use std::time::Duration;
+
+    fn options(backend: Backend, shard_count: usize) -> Options {
+        Options {
+            backend,
+            shard_count,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_store_write_batch() {
+        let mut store = Store::new();
+        store.insert("key1", "value1").unwrap();
+        store
+            .insert_with_ttl("key2", "value2", Duration::from_secs(60))
+            .unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch
+            .put("key2", "updated")
+            .put("key3", "value3")
+            .delete("key1")
+            .delete("missing")
+            .put("key4", "first")
+            .put("key4", "last");
+        assert_eq!(batch.len(), 6);
+        let seq = store.seq();
+        store.write_batch(batch).unwrap();
+
+        assert_eq!(store.get("key1").unwrap(), None);
+        assert_eq!(store.get("key2").unwrap(), Some(b"updated".to_vec()));
+        assert_eq!(store.ttl("key2").unwrap(), None); // A put drops the expiry time
+        assert_eq!(store.get("key3").unwrap(), Some(b"value3".to_vec()));
+        assert_eq!(store.get("key4").unwrap(), Some(b"last".to_vec()));
+        assert_eq!(store.seq(), seq + 6);
+
+        let mut batch = WriteBatch::new();
+        store.write_batch(batch.clone()).unwrap(); // An empty batch writes nothing
+        assert_eq!(store.seq(), seq + 6);
+        batch.put("key5", "value5");
+        batch.clear();
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_batch_is_persisted() {
+        let bitcask = || Backend::Bitcask(BitcaskOptions::default());
+        for (name, backend) in [
+            ("bincode", Backend::Bincode),
+            ("json", Backend::Json),
+            ("bitcask", bitcask()),
+        ] {
+            let db_name = test_db_name(name);
+            let db = Database::open(&db_name, options(backend.clone(), 1)).unwrap();
+            db.insert("old", "value").unwrap();
+            let mut batch = WriteBatch::new();
+            for i in 0..100 {
+                batch.put(format!("key{i:03}"), format!("value{i}"));
+            }
+            batch.delete("old");
+            db.write_batch(batch).unwrap();
+            db.close().unwrap();
+
+            let db = Database::open(&db_name, options(backend, 1)).unwrap();
+            let pairs = db.scan(..).unwrap();
+            assert_eq!(pairs.len(), 100, "{name}");
+            assert_eq!(pairs[42].value, b"value42");
+            assert_eq!(db.get("old").unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_batch_across_shards() {
+        let db_name = test_db_name("shards");
+        let db = Database::open(&db_name, options(Backend::Bincode, 4)).unwrap();
+        let mut batch = WriteBatch::new();
+        for i in 0..100 {
+            batch.put(format!("key{i}"), "value");
+        }
+        db.write_batch(batch).unwrap();
+        assert!((0..4).all(|index| !db.with_shard(index, Store::is_empty).unwrap()));
+
+        let mut batch = WriteBatch::new();
+        for i in 0..50 {
+            batch.delete(format!("key{i}"));
+        }
+        db.write_batch(batch).unwrap();
+        db.close().unwrap();
+
+        let db = Database::open(&db_name, options(Backend::Bincode, 4)).unwrap();
+        assert_eq!(db.scan(..).unwrap().len(), 50);
+        assert_eq!(db.get("key10").unwrap(), None);
+        assert_eq!(db.get_string("key60").unwrap(), Some("value".to_string()));
+    }
+
+    #[test]
+    fn test_batch_in_transaction() {
+        let db = Database::open("memory", options(Backend::Memory, 2)).unwrap();
+        db.insert("key1", "value1").unwrap();
+        db.insert_with_ttl("key2", "value2", Duration::from_secs(60))
+            .unwrap();
+
+        let mut tx = db.begin().unwrap();
+        let mut batch = WriteBatch::new();
+        batch
+            .put("key2", "updated")
+            .put("key3", "value3")
+            .delete("key1");
+        tx.write_batch(batch).unwrap();
+        assert_eq!(tx.get("key1").unwrap(), None);
+        assert_eq!(tx.get("key3").unwrap(), Some(b"value3".to_vec()));
+        assert_eq!(db.get("key3").unwrap(), None); // Buffered until the commit
+        tx.commit().unwrap();
+
+        assert_eq!(db.get("key1").unwrap(), None);
+        assert_eq!(db.get_string("key2").unwrap(), Some("updated".to_string()));
+        assert_eq!(db.ttl("key2").unwrap(), None);
+        assert_eq!(db.get_string("key3").unwrap(), Some("value3".to_string()));
+    }
+}
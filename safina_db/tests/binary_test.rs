@@ -0,0 +1,78 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-binary-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::bitcask::{Bitcask, BitcaskOptions};
+    use safina_db::storage::{JsonStorage, StorageBackend};
+    use safina_db::{Backend, Database, Options, Storage, Store};
+
+    /// A value that is not valid UTF-8, with a NUL byte in it.
+    const BLOB: &[u8] = &[0x89, b'P', b'N', b'G', 0x00, 0xff, 0xfe];
+
+    /// Writes a binary key and value, plus a text pair, through a store on `backend`.
+    fn write_binary(backend: Box<dyn StorageBackend>) {
+        let mut store = Store::open(backend).unwrap();
+        store.insert([0x00, 0xc3], BLOB).unwrap();
+        store.insert("text", "value").unwrap();
+        store.close().unwrap();
+    }
+
+    /// Checks a store reopened after `write_binary` gives the exact bytes back.
+    fn assert_binary_reloaded(backend: Box<dyn StorageBackend>) {
+        let store = Store::open(backend).unwrap();
+        assert_eq!(store.get([0x00, 0xc3]).unwrap().unwrap(), BLOB);
+        assert_eq!(store.get("text").unwrap().unwrap(), b"value");
+    }
+
+    #[test]
+    fn test_bincode_binary_round_trip() {
+        let db_name = test_db_name("bincode");
+        write_binary(Box::new(Storage::new(Some(&db_name))));
+        assert_binary_reloaded(Box::new(Storage::new(Some(&db_name))));
+    }
+
+    #[test]
+    fn test_json_binary_round_trip() {
+        let db_name = test_db_name("json");
+        write_binary(Box::new(JsonStorage::new(&db_name)));
+        let content = std::fs::read_to_string(&db_name).unwrap();
+        assert!(content.contains("\"base64\": \"iVBORwD//g==\""));
+        assert!(content.contains("\"key\": \"text\""));
+        assert_binary_reloaded(Box::new(JsonStorage::new(&db_name)));
+    }
+
+    #[test]
+    fn test_bitcask_binary_round_trip() {
+        let db_name = test_db_name("bitcask");
+        let options = BitcaskOptions::default();
+        write_binary(Box::new(Bitcask::open(&db_name, options.clone()).unwrap()));
+        assert_binary_reloaded(Box::new(Bitcask::open(&db_name, options).unwrap()));
+    }
+
+    #[test]
+    fn test_database_string_wrappers() {
+        let options = Options {
+            backend: Backend::Memory,
+            ..Options::default()
+        };
+        let db = Database::open(&test_db_name("wrappers"), options).unwrap();
+        db.insert("key", BLOB).unwrap();
+        assert_eq!(db.get("key").unwrap(), Some(BLOB.to_vec()));
+        assert_eq!(
+            db.get_string("key").unwrap(),
+            Some("\u{fffd}PNG\0\u{fffd}\u{fffd}".to_string())
+        );
+        db.update("key", "text").unwrap();
+        assert_eq!(db.get_string("key").unwrap(), Some("text".to_string()));
+    }
+}
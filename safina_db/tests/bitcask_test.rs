@@ -0,0 +1,247 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique engine directory name for a single test.
+#[cfg(test)]
+pub fn test_db_dir(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-bitcask-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_dir;
+    use safina_db::merge;
+    use safina_db::storage::bitcask::{Bitcask, BitcaskOptions};
+    use safina_db::storage::wal::{self, LogFile};
+    use safina_db::storage::Mutation;
+    use safina_db::Error;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Seek, SeekFrom, Write};
+
+    /// Options rolling a segment every few records and never merging in the background.
+    fn small_segments() -> BitcaskOptions {
+        BitcaskOptions {
+            max_segment_size: 128,
+            merge_trigger: usize::MAX,
+        }
+    }
+
+    /// A segment file whose writes fail once `budget` bytes have been written, like a full disk.
+    struct FailingFile {
+        file: File,
+        budget: usize,
+    }
+
+    impl Write for FailingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.budget == 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            let written = self.file.write(&buf[..buf.len().min(self.budget)])?;
+            self.budget -= written;
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+    }
+
+    impl Seek for FailingFile {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.file.seek(pos)
+        }
+    }
+
+    impl LogFile for FailingFile {
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.file.set_len(len)
+        }
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let db = Bitcask::open(test_db_dir("crud"), BitcaskOptions::default()).unwrap();
+        db.put("key1", "value1").unwrap();
+        db.put("key2", "value2").unwrap();
+        db.put("key1", "value1-updated").unwrap();
+        db.delete("key2").unwrap();
+        db.delete("key-doesnt-exists").unwrap();
+
+        assert_eq!(db.get("key1").unwrap(), Some(b"value1-updated".to_vec()));
+        assert_eq!(db.get("key2").unwrap(), None);
+        assert_eq!(db.keys(), vec![b"key1".to_vec()]);
+    }
+
+    #[test]
+    fn test_reopen_rebuilds_keydir() {
+        let dir = test_db_dir("reopen");
+        let db = Bitcask::open(&dir, BitcaskOptions::default()).unwrap();
+        db.put("key1", "value1").unwrap();
+        db.put("key2", "value2").unwrap();
+        db.delete("key1").unwrap();
+        db.put("key3", "").unwrap();
+        drop(db);
+
+        let db = Bitcask::open(&dir, BitcaskOptions::default()).unwrap();
+        assert_eq!(db.get("key1").unwrap(), None);
+        assert_eq!(db.get("key2").unwrap(), Some(b"value2".to_vec()));
+        assert_eq!(db.get("key3").unwrap(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_segments_roll_and_merge() {
+        let dir = test_db_dir("merge");
+        let db = Bitcask::open(&dir, small_segments()).unwrap();
+        for round in 0..10 {
+            for i in 0..5 {
+                db.put(format!("key-{i}"), format!("value-{i}-{round}"))
+                    .unwrap();
+            }
+        }
+        db.delete("key-4").unwrap();
+        assert!(db.segment_count() > 2);
+
+        db.merge().unwrap();
+        assert_eq!(db.segment_count(), 2); // The merged segment and the active one
+        for i in 0..4 {
+            assert_eq!(
+                db.get(format!("key-{i}")).unwrap(),
+                Some(format!("value-{i}-9").into_bytes())
+            );
+        }
+        assert_eq!(db.get("key-4").unwrap(), None);
+
+        let hints = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension().unwrap() == "hint")
+            .count();
+        assert_eq!(hints, 1);
+
+        db.put("key-0", "after-merge").unwrap();
+        drop(db);
+        let db = Bitcask::open(&dir, small_segments()).unwrap();
+        assert_eq!(db.get("key-0").unwrap(), Some(b"after-merge".to_vec()));
+        assert_eq!(db.get("key-3").unwrap(), Some(b"value-3-9".to_vec()));
+        assert_eq!(db.get("key-4").unwrap(), None);
+        assert_eq!(db.pairs().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_operands_are_folded_on_read_and_merge() {
+        let dir = test_db_dir("operands");
+        let incr = |key: &str| Mutation::Merge {
+            key: key.as_bytes().to_vec(),
+            operator: merge::COUNTER.to_string(),
+            operand: b"1".to_vec(),
+        };
+        let db = Bitcask::open(&dir, small_segments()).unwrap();
+        db.put("counter", "10").unwrap();
+        db.put("text", "abc").unwrap();
+        for _ in 0..20 {
+            db.write_batch(&[incr("counter")]).unwrap();
+        }
+        db.write_batch(&[incr("text"), incr("fresh")]).unwrap();
+        assert_eq!(db.get("counter").unwrap(), Some(b"30".to_vec()));
+        assert_eq!(db.get("fresh").unwrap(), Some(b"1".to_vec())); // A missing key counts as 0
+        assert!(matches!(db.get("text"), Err(Error::Merge(_)))); // Written, then failed on read
+        assert!(db.write_batch(&[Mutation::Merge {
+            key: b"counter".to_vec(),
+            operator: "missing".to_string(),
+            operand: Vec::new(),
+        }])
+        .is_err());
+
+        db.merge().unwrap(); // Folds the operands of the closed segments, copies the others
+        db.write_batch(&[incr("counter")]).unwrap();
+        drop(db);
+        let db = Bitcask::open(&dir, small_segments()).unwrap();
+        assert_eq!(db.get("counter").unwrap(), Some(b"31".to_vec()));
+        assert_eq!(db.get("fresh").unwrap(), Some(b"1".to_vec()));
+        assert!(matches!(db.get("text"), Err(Error::Merge(_))));
+
+        db.put("text", "5").unwrap(); // A value replaces the operands before it
+        db.write_batch(&[incr("text")]).unwrap();
+        assert_eq!(db.get("text").unwrap(), Some(b"6".to_vec()));
+        assert_eq!(db.pairs().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_background_merge() {
+        let dir = test_db_dir("background");
+        let options = BitcaskOptions {
+            max_segment_size: 128,
+            merge_trigger: 3,
+        };
+        let db = Bitcask::open(&dir, options.clone()).unwrap();
+        for round in 0..50 {
+            db.put("counter", round.to_string()).unwrap();
+        }
+        drop(db); // Waits for the running merge, if any
+
+        let db = Bitcask::open(&dir, options).unwrap();
+        assert_eq!(db.get("counter").unwrap(), Some(b"49".to_vec()));
+        let merged = std::fs::read_dir(&dir)
+            .unwrap()
+            .any(|entry| entry.unwrap().path().extension().unwrap() == "hint");
+        assert!(merged);
+    }
+
+    #[test]
+    fn test_torn_record_is_discarded() {
+        let dir = test_db_dir("torn");
+        let db = Bitcask::open(&dir, BitcaskOptions::default()).unwrap();
+        db.put("key1", "value1").unwrap();
+        drop(db);
+
+        // Simulate a crash in the middle of an append to the segment.
+        let mut segment = OpenOptions::new()
+            .append(true)
+            .open(format!("{dir}/0000000001.data"))
+            .unwrap();
+        segment.write_all(&[1, 2, 3, 4, 0, 9, 0]).unwrap();
+        drop(segment);
+
+        let db = Bitcask::open(&dir, BitcaskOptions::default()).unwrap();
+        assert_eq!(db.get("key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(
+            std::fs::metadata(format!("{dir}/0000000001.data"))
+                .unwrap()
+                .len(),
+            13 + 4 + 6
+        );
+    }
+
+    #[test]
+    fn test_failed_append_keeps_offsets() {
+        let dir = test_db_dir("failed");
+        let db = Bitcask::open(&dir, BitcaskOptions::default()).unwrap();
+        db.put("key1", "value1").unwrap();
+
+        // Fail a write to the active segment partway, as a full disk would.
+        let mut segments: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "data"))
+            .collect();
+        segments.sort();
+        let path = segments.last().unwrap();
+        let end = std::fs::metadata(path).unwrap().len();
+        let mut file = FailingFile {
+            file: OpenOptions::new().append(true).open(path).unwrap(),
+            budget: 7,
+        };
+        assert!(wal::append_record(&mut file, end, &[0; 32]).is_err());
+        assert_eq!(std::fs::metadata(path).unwrap().len(), end); // Cut back
+        drop(file);
+
+        db.put("key2", "value2").unwrap(); // Its offset assumes the segment ends at `end`
+        assert_eq!(db.get("key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get("key2").unwrap(), Some(b"value2".to_vec()));
+        drop(db);
+        let db = Bitcask::open(&dir, BitcaskOptions::default()).unwrap();
+        assert_eq!(db.get("key2").unwrap(), Some(b"value2".to_vec()));
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-cas-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::{Backend, Database, Error, Options, Store};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_compare_and_swap() {
+        let mut store = Store::new();
+        let none = None::<&str>;
+
+        // Inserts only if missing, like a lock.
+        assert!(store
+            .compare_and_swap("lock", none, Some("owner-1"))
+            .unwrap());
+        assert!(!store
+            .compare_and_swap("lock", none, Some("owner-2"))
+            .unwrap());
+        assert!(!store
+            .compare_and_swap("lock", Some("owner-2"), none)
+            .unwrap());
+        assert_eq!(store.get("lock").unwrap(), Some(b"owner-1".to_vec()));
+        assert!(store
+            .compare_and_swap("lock", Some("owner-1"), none)
+            .unwrap());
+        assert_eq!(store.get("lock").unwrap(), None);
+        assert!(store.compare_and_swap("lock", none, none).unwrap()); // Missing as expected
+
+        // A swap keeps the expiry time, an expired pair counts as missing.
+        store
+            .insert_with_ttl("session", "a", Duration::from_secs(60))
+            .unwrap();
+        assert!(store
+            .compare_and_swap("session", Some("a"), Some("b"))
+            .unwrap());
+        assert!(store.ttl("session").unwrap().is_some());
+        store
+            .insert_with_ttl("expired", "old", Duration::from_millis(1))
+            .unwrap();
+        thread::sleep(Duration::from_millis(5));
+        assert!(!store
+            .compare_and_swap("expired", Some("old"), Some("new"))
+            .unwrap());
+        assert!(store
+            .compare_and_swap("expired", none, Some("new"))
+            .unwrap());
+        assert_eq!(store.ttl("expired").unwrap(), None);
+    }
+
+    #[test]
+    fn test_update_if_version() {
+        let mut store = Store::new();
+        store.insert("key1", "value1").unwrap();
+        store.insert("key2", "value2").unwrap();
+        let (value, version) = store.get_with_version("key1").unwrap().unwrap();
+        assert_eq!(value, b"value1");
+
+        store.update("key2", "changed").unwrap();
+        assert_eq!(store.version("key1"), Some(version)); // Other keys don't matter
+
+        let new_version = store
+            .update_if_version("key1", version, "value2")
+            .unwrap()
+            .unwrap();
+        assert!(new_version > version);
+        assert_eq!(store.version("key1"), Some(new_version));
+        assert_eq!(
+            store.update_if_version("key1", version, "stale").unwrap(),
+            None
+        );
+        assert_eq!(store.get("key1").unwrap(), Some(b"value2".to_vec()));
+        assert!(matches!(
+            store.update_if_version("missing", version, "value"),
+            Err(Error::KeyNotFound)
+        ));
+
+        // A key deleted and inserted again gets a new version.
+        store.delete("key1").unwrap();
+        assert_eq!(store.version("key1"), None);
+        store.insert("key1", "value2").unwrap();
+        assert!(store.version("key1").unwrap() > new_version);
+    }
+
+    #[test]
+    fn test_versions_grow_across_reopens() {
+        let db_name = test_db_name("reopen");
+        let db = Database::open(&db_name, Options::default()).unwrap();
+        db.insert("key1", "value1").unwrap();
+        let version = db.version("key1").unwrap().unwrap();
+        db.close().unwrap();
+
+        let db = Database::open(&db_name, Options::default()).unwrap();
+        assert!(db.version("key1").unwrap().unwrap() > version);
+        assert_eq!(
+            db.update_if_version("key1", version, "stale").unwrap(),
+            None
+        );
+        let (value, version) = db.get_with_version("key1").unwrap().unwrap();
+        assert_eq!(value, b"value1");
+        assert!(db
+            .update_if_version("key1", version, "value2")
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_concurrent_read_modify_write() {
+        let options = Options {
+            backend: Backend::Memory,
+            shard_count: 4,
+            ..Options::default()
+        };
+        let db = Arc::new(Database::open("memory", options).unwrap());
+        db.insert("counter", "0").unwrap();
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        loop {
+                            let (value, version) = db.get_with_version("counter").unwrap().unwrap();
+                            let count: u64 = String::from_utf8(value).unwrap().parse().unwrap();
+                            let next = (count + 1).to_string();
+                            if db
+                                .update_if_version("counter", version, next)
+                                .unwrap()
+                                .is_some()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(db.get_string("counter").unwrap(), Some("400".to_string()));
+    }
+}
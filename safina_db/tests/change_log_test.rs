@@ -0,0 +1,211 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-cdc-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::{Change, ChangeLogOptions, Mutation};
+    use safina_db::{Backend, Database, Error, Options, WriteBatch};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn options(backend: Backend, log_options: ChangeLogOptions) -> Options {
+        Options {
+            backend,
+            sweep_interval: None,
+            shard_count: 4,
+            change_log: Some(log_options),
+            ..Options::default()
+        }
+    }
+
+    fn keys(changes: &[Change]) -> Vec<Option<&[u8]>> {
+        changes.iter().map(|change| change.mutation.key()).collect()
+    }
+
+    #[test]
+    fn test_changes_resume_from_a_cursor() {
+        let db_name = test_db_name("resume");
+        let db = Database::open(&db_name, options(Backend::Bincode, Default::default())).unwrap();
+        db.insert("key1", "value1").unwrap();
+        db.update("key1", "value2").unwrap();
+        db.incr("counter").unwrap();
+        db.delete("key1").unwrap();
+        db.delete("missing").unwrap(); // Nothing committed, nothing logged
+
+        let log = db.change_log().unwrap();
+        let changes = log.read(1, 100).unwrap();
+        let seqs: Vec<u64> = changes.iter().map(|change| change.seq).collect();
+        assert_eq!(seqs, [1, 2, 3, 4]);
+        assert_eq!(
+            changes[1].mutation,
+            Mutation::Put {
+                key: b"key1".to_vec(),
+                value: b"value2".to_vec()
+            }
+        );
+        assert_eq!(changes[3].mutation.key(), Some(&b"key1"[..]));
+        assert_eq!(log.read(2, 1).unwrap()[0].seq, 2);
+        assert!(log.read(5, 100).unwrap().is_empty());
+        log.save_cursor("search", 3).unwrap();
+        db.close().unwrap();
+
+        let db = Database::open(&db_name, options(Backend::Bincode, Default::default())).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put("key2", "value").put("key3", "value");
+        db.write_batch(batch).unwrap();
+        let log = db.change_log().unwrap();
+        let cursor = log.cursor("search").unwrap();
+        assert_eq!(cursor, 3);
+        assert_eq!(log.cursor("warehouse"), None);
+        let changes = log.read(cursor, 100).unwrap();
+        assert_eq!(changes[0].seq, 3);
+        assert!(changes.len() >= 3); // The batch is split per shard
+        assert!(changes
+            .windows(2)
+            .all(|pair| pair[1].seq == pair[0].seq + 1));
+        assert_eq!(log.next_seq(), changes.last().unwrap().seq + 1);
+    }
+
+    #[test]
+    fn test_retention_bounds_the_log() {
+        let db_name = test_db_name("retention");
+        let log_options = ChangeLogOptions {
+            segment_size: 512,
+            max_size: 2048,
+        };
+        let db = Database::open(&db_name, options(Backend::Memory, log_options)).unwrap();
+        for i in 0..200 {
+            db.insert(format!("key{i}"), "value").unwrap();
+        }
+
+        let log = db.change_log().unwrap();
+        let first = log.first_seq();
+        assert!(first > 1);
+        assert!(matches!(log.read(1, 10), Err(Error::ChangeLogTruncated(seq)) if seq == first));
+        let changes = log.read(first, usize::MAX).unwrap();
+        assert_eq!(changes.last().unwrap().seq, 200);
+        let size: u64 = fs::read_dir(format!("{db_name}.cdc"))
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum();
+        assert!(size <= 2048 + 512, "{size}");
+    }
+
+    #[test]
+    fn test_torn_tail_is_dropped() {
+        let db_name = test_db_name("torn");
+        let db = Database::open(&db_name, options(Backend::Bincode, Default::default())).unwrap();
+        db.insert("key1", "value1").unwrap();
+        db.insert("key2", "value2").unwrap();
+        db.close().unwrap();
+
+        let segment = fs::read_dir(format!("{db_name}.cdc"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "cdc"))
+            .unwrap();
+        let mut file = OpenOptions::new().append(true).open(segment).unwrap();
+        file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap(); // An interrupted append
+
+        let db = Database::open(&db_name, options(Backend::Bincode, Default::default())).unwrap();
+        db.insert("key3", "value3").unwrap();
+        let changes = db.change_log().unwrap().read(1, 100).unwrap();
+        assert_eq!(
+            keys(&changes),
+            [Some(&b"key1"[..]), Some(&b"key2"[..]), Some(&b"key3"[..])]
+        );
+        assert_eq!(changes[2].seq, 3);
+    }
+
+    #[test]
+    fn test_concurrent_writers_and_transactions() {
+        let db_name = test_db_name("concurrent");
+        let db = Arc::new(
+            Database::open(&db_name, options(Backend::Memory, Default::default())).unwrap(),
+        );
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        db.insert(format!("t{t}-key{i}"), "value").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        db.transaction(|tx| tx.update("t0-key0", "updated"))
+            .unwrap();
+
+        let changes = db.change_log().unwrap().read(1, usize::MAX).unwrap();
+        assert_eq!(changes.len(), 401);
+        assert!(changes
+            .iter()
+            .enumerate()
+            .all(|(i, change)| change.seq == i as u64 + 1));
+        match &changes[400].mutation {
+            Mutation::Batch { mutations } => assert_eq!(mutations[0].key(), Some(&b"t0-key0"[..])),
+            mutation => panic!("expected the committed batch, got {mutation:?}"),
+        }
+    }
+
+    #[test]
+    fn test_merges_are_logged_as_values() {
+        let db_name = test_db_name("merge");
+        let db = Database::open(&db_name, options(Backend::Bincode, Default::default())).unwrap();
+        db.incr("counter").unwrap();
+        db.incr_by("counter", 2).unwrap();
+        db.merge("list", "append", "a,").unwrap();
+        db.merge("list", "append", "b,").unwrap();
+
+        let changes = db.change_log().unwrap().read(1, 100).unwrap();
+        let put = |key: &str, value: &str| Mutation::Put {
+            key: key.as_bytes().to_vec(),
+            value: value.as_bytes().to_vec(),
+        };
+        let mutations: Vec<Mutation> = changes.into_iter().map(|change| change.mutation).collect();
+        assert_eq!(
+            mutations,
+            [
+                put("counter", "1"),
+                put("counter", "3"),
+                put("list", "a,"),
+                put("list", "a,b,")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_persist_is_not_logged() {
+        let db_name = test_db_name("failed");
+        let options = Options {
+            shard_count: 1,
+            ..options(Backend::Json, Default::default())
+        };
+        let db = Database::open(&db_name, options).unwrap();
+        db.insert("key1", "value1").unwrap();
+        let tmp_path = format!("{db_name}.tmp");
+        fs::create_dir(&tmp_path).unwrap(); // The JSON file can't be rewritten anymore
+        assert!(db.insert("key2", "value2").is_err());
+        fs::remove_dir(&tmp_path).unwrap();
+        db.insert("key3", "value3").unwrap();
+
+        let changes = db.change_log().unwrap().read(1, 100).unwrap();
+        assert_eq!(keys(&changes), [Some(&b"key1"[..]), Some(&b"key3"[..])]);
+        assert_eq!(changes[1].seq, 2);
+        assert_eq!(db.get("key2").unwrap(), None);
+    }
+}
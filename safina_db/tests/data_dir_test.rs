@@ -0,0 +1,183 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique data directory name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-data-dir-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::{DataDir, Database, Error, Options};
+    use std::path::Path;
+
+    fn options() -> Options {
+        Options {
+            sweep_interval: None,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_databases_are_isolated() {
+        let root = test_db_name("isolated");
+        let dir = DataDir::open(&root, options()).unwrap();
+        let sales = dir.create_database("sales").unwrap();
+        let hr = dir.create_database("hr").unwrap();
+        sales.insert("key", "sales").unwrap();
+        hr.insert("key", "hr").unwrap();
+        assert_eq!(dir.database("default").unwrap().get("key").unwrap(), None);
+        assert_eq!(sales.get_string("key").unwrap(), Some("sales".to_string()));
+        assert_eq!(hr.get_string("key").unwrap(), Some("hr".to_string()));
+        assert!(Path::new(&root).join("sales").join("db").exists());
+        assert!(matches!(
+            dir.create_database("sales"),
+            Err(Error::DatabaseExists(_))
+        ));
+        assert!(matches!(
+            dir.create_database("../escape"),
+            Err(Error::InvalidDatabaseName(_))
+        ));
+        drop((sales, hr));
+        dir.close().unwrap();
+
+        let dir = DataDir::open(&root, options()).unwrap();
+        assert_eq!(dir.databases().unwrap(), ["default", "hr", "sales"]);
+        let sales = dir.database("sales").unwrap();
+        assert_eq!(sales.get_string("key").unwrap(), Some("sales".to_string()));
+    }
+
+    #[test]
+    fn test_databases_open_lazily() {
+        let root = test_db_name("lazy");
+        let dir = DataDir::open(&root, options()).unwrap();
+        dir.create_database("sales")
+            .unwrap()
+            .insert("key", "value")
+            .unwrap();
+        dir.close().unwrap();
+
+        let dir = DataDir::open(&root, options()).unwrap();
+        assert!(!dir.is_open("sales").unwrap());
+        assert!(!dir.is_open("default").unwrap());
+        let first = dir.database("sales").unwrap();
+        assert!(dir.is_open("sales").unwrap());
+        let second = dir.database("sales").unwrap();
+        second.update("key", "updated").unwrap();
+        assert_eq!(
+            first.get_string("key").unwrap(),
+            Some("updated".to_string())
+        ); // One shared handle
+        assert!(matches!(
+            dir.database("missing"),
+            Err(Error::DatabaseNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_drop_database() {
+        let root = test_db_name("drop");
+        let dir = DataDir::open(&root, options()).unwrap();
+        let sales = dir.create_database("sales").unwrap();
+        sales.insert("key", "value").unwrap();
+        assert!(matches!(
+            dir.drop_database("sales"),
+            Err(Error::DatabaseInUse(_))
+        ));
+        drop(sales);
+
+        dir.drop_database("sales").unwrap();
+        assert!(!Path::new(&root).join("sales").exists());
+        assert_eq!(dir.databases().unwrap(), ["default"]);
+        assert!(matches!(
+            dir.drop_database("sales"),
+            Err(Error::DatabaseNotFound(_))
+        ));
+        assert!(matches!(
+            dir.drop_database("default"),
+            Err(Error::InvalidDatabaseName(_))
+        ));
+        let sales = dir.create_database("sales").unwrap();
+        assert_eq!(sales.get("key").unwrap(), None); // Dropped with its files
+    }
+
+    #[test]
+    fn test_legacy_database_is_imported() {
+        let legacy = test_db_name("legacy");
+        std::fs::create_dir(&legacy).unwrap();
+        let legacy_path = Path::new(&legacy).join("db");
+        let db = Database::open(&legacy_path.to_string_lossy(), options()).unwrap();
+        db.insert("key", "legacy").unwrap();
+        drop(db); // Not closed, the writes are still in the log
+
+        let root = test_db_name("import");
+        let dir = DataDir::open(&root, options()).unwrap();
+        assert!(dir.import_legacy(&legacy_path).unwrap());
+        assert!(!legacy_path.exists());
+        assert!(!dir.import_legacy(&legacy_path).unwrap()); // Nothing left to import
+        let default = dir.database("default").unwrap();
+        assert_eq!(
+            default.get_string("key").unwrap(),
+            Some("legacy".to_string())
+        );
+
+        let db = Database::open(&legacy_path.to_string_lossy(), options()).unwrap();
+        db.insert("key", "another").unwrap();
+        db.close().unwrap();
+        assert!(matches!(
+            dir.import_legacy(&legacy_path),
+            Err(Error::DatabaseInUse(_))
+        ));
+        drop(default);
+        dir.close().unwrap();
+        let dir = DataDir::open(&root, options()).unwrap();
+        assert!(matches!(
+            dir.import_legacy(&legacy_path),
+            Err(Error::DatabaseExists(_))
+        ));
+        assert!(legacy_path.exists()); // Left in place
+    }
+
+    #[test]
+    fn test_legacy_import_moves_only_database_files() {
+        let legacy = test_db_name("legacy-files");
+        std::fs::create_dir(&legacy).unwrap();
+        let legacy_path = Path::new(&legacy).join("db");
+        let db = Database::open(&legacy_path.to_string_lossy(), options()).unwrap();
+        db.insert("key", "legacy").unwrap();
+        db.close().unwrap();
+        std::fs::write(Path::new(&legacy).join("db.backup"), b"kept").unwrap();
+        std::fs::write(Path::new(&legacy).join("db.shard-x"), b"kept").unwrap();
+
+        let root = test_db_name("import-files");
+        let dir = DataDir::open(&root, options()).unwrap();
+        assert!(dir.import_legacy(&legacy_path).unwrap());
+        assert!(!legacy_path.exists());
+        assert!(Path::new(&legacy).join("db.backup").exists()); // Not a file of the database
+        assert!(Path::new(&legacy).join("db.shard-x").exists());
+        assert!(!Path::new(&root).join("default").join("db.backup").exists());
+    }
+
+    #[test]
+    fn test_legacy_import_refuses_what_is_not_a_snapshot() {
+        let legacy = test_db_name("legacy-invalid");
+        std::fs::create_dir(&legacy).unwrap();
+        let legacy_path = Path::new(&legacy).join("db");
+        std::fs::write(&legacy_path, b"not a snapshot").unwrap();
+        std::fs::write(Path::new(&legacy).join("db.wal"), b"").unwrap();
+
+        let root = test_db_name("import-invalid");
+        let dir = DataDir::open(&root, options()).unwrap();
+        assert!(matches!(
+            dir.import_legacy(&legacy_path),
+            Err(Error::Corruption(_))
+        ));
+        assert!(legacy_path.exists()); // Nothing moved
+        assert!(Path::new(&legacy).join("db.wal").exists());
+        assert_eq!(dir.database("default").unwrap().get("key").unwrap(), None);
+    }
+}
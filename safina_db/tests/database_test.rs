@@ -0,0 +1,120 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-database-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::{Backend, Database, Options};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_database_is_send_sync() {
+        assert_send_sync::<Database>();
+    }
+
+    #[test]
+    fn test_independent_databases() {
+        let first = Database::open(&test_db_name("first"), Options::default()).unwrap();
+        let second = Database::open(&test_db_name("second"), Options::default()).unwrap();
+        first.insert("key1", "first").unwrap();
+        second.insert("key1", "second").unwrap();
+        second.insert("key2", "second").unwrap();
+
+        assert_eq!(first.get("key1").unwrap(), Some(b"first".to_vec()));
+        assert_eq!(second.get("key1").unwrap(), Some(b"second".to_vec()));
+        assert_eq!(first.get("key2").unwrap(), None);
+    }
+
+    #[test]
+    fn test_drop_closes_and_reopen_loads() {
+        let db_name = test_db_name("reopen");
+        let db = Database::open(&db_name, Options::default()).unwrap();
+        db.insert("key1", "value1").unwrap();
+        db.insert("key2", "value2").unwrap();
+        db.delete("key2").unwrap();
+        drop(db);
+
+        // Closing folded the log into the snapshot, only the header of its generation is left.
+        assert_eq!(
+            std::fs::metadata(format!("{db_name}.wal")).unwrap().len(),
+            16
+        );
+
+        let db = Database::open(&db_name, Options::default()).unwrap();
+        assert_eq!(db.get("key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get("key2").unwrap(), None);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let options = Options {
+            backend: Backend::Memory,
+            ..Options::default()
+        };
+        let db = Arc::new(Database::open("memory", options).unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        db.insert(format!("t{t}-key{i}"), "value").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(db.scan(..).unwrap().len(), 400);
+        assert_eq!(db.scan_prefix("t2-").unwrap().len(), 100);
+    }
+
+    #[test]
+    fn test_json_backend_option() {
+        let db_name = test_db_name("json");
+        let options = Options {
+            backend: Backend::Json,
+            ..Options::default()
+        };
+        let db = Database::open(&db_name, options.clone()).unwrap();
+        db.insert("key1", "value1").unwrap();
+        db.update("key1", "value1-updated").unwrap();
+        drop(db);
+
+        let db = Database::open(&db_name, options).unwrap();
+        assert_eq!(db.get("key1").unwrap(), Some(b"value1-updated".to_vec()));
+    }
+
+    #[test]
+    fn test_readers_share_the_store() {
+        let db = Arc::new(Database::open(&test_db_name("readers"), Options::default()).unwrap());
+        db.insert("key1", "value1").unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let reader = Arc::clone(&db);
+        let read = db
+            .with_shard(0, |store| {
+                // The shard is held while another thread reads
+                let reader =
+                    thread::spawn(move || sender.send(reader.get("key1").unwrap()).unwrap());
+                let read = receiver.recv_timeout(Duration::from_secs(5));
+                assert_eq!(store.get("key1").unwrap(), Some(b"value1".to_vec()));
+                reader.join().unwrap();
+                read
+            })
+            .unwrap();
+        assert_eq!(read, Ok(Some(b"value1".to_vec())));
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-durability-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::bitcask::{Bitcask, BitcaskOptions};
+    use safina_db::storage::Durability;
+    use safina_db::{Backend, Database, Options, Storage, Store};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn options(backend: Backend, durability: Durability) -> Options {
+        Options {
+            backend,
+            sweep_interval: None,
+            durability,
+            ..Options::default()
+        }
+    }
+
+    fn modes() -> [Durability; 4] {
+        [
+            Durability::Always,
+            Durability::GroupCommit(Duration::from_millis(1)),
+            Durability::Periodic(Duration::from_millis(10)),
+            Durability::None,
+        ]
+    }
+
+    #[test]
+    fn test_every_mode_reopens() {
+        for (i, durability) in modes().into_iter().enumerate() {
+            let bitcask = || Backend::Bitcask(BitcaskOptions::default());
+            for (name, backend) in [("bincode", Backend::Bincode), ("bitcask", bitcask())] {
+                let db_name = test_db_name(&format!("{name}-{i}"));
+                let db = Database::open(&db_name, options(backend.clone(), durability)).unwrap();
+                for j in 0..20 {
+                    db.insert(format!("key{j}"), "value").unwrap();
+                }
+                db.delete("key3").unwrap();
+                db.transaction(|tx| tx.update("key4", "updated")).unwrap();
+                db.close().unwrap();
+
+                let db = Database::open(&db_name, options(backend, durability)).unwrap();
+                assert_eq!(db.scan(..).unwrap().len(), 19, "{durability:?}");
+                assert_eq!(db.get_string("key4").unwrap(), Some("updated".to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_group_commit_concurrent_writers() {
+        let db_name = test_db_name("group");
+        let durability = Durability::GroupCommit(Duration::from_millis(2));
+        let db = Arc::new(Database::open(&db_name, options(Backend::Bincode, durability)).unwrap());
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    for i in 0..25 {
+                        db.insert(format!("t{t}-key{i}"), "value").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        Arc::into_inner(db).unwrap().close().unwrap();
+
+        let db = Database::open(&db_name, options(Backend::Bincode, durability)).unwrap();
+        assert_eq!(db.scan(..).unwrap().len(), 200);
+    }
+
+    #[test]
+    fn test_unsynced_writes_survive_a_process_crash() {
+        // Without a sync, the writes still reach the OS: only a machine crash loses them.
+        for (i, durability) in [
+            Durability::Periodic(Duration::from_secs(60)),
+            Durability::None,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let db_name = test_db_name(&format!("crash-{i}"));
+            let db = Database::open(&db_name, options(Backend::Bincode, durability)).unwrap();
+            db.insert("key1", "value1").unwrap();
+            db.update("key1", "value2").unwrap();
+            std::mem::forget(db); // Neither flushed nor closed
+
+            let db = Database::open(&db_name, options(Backend::Bincode, durability)).unwrap();
+            assert_eq!(db.get_string("key1").unwrap(), Some("value2".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_standalone_backends_with_group_commit() {
+        let durability = Durability::GroupCommit(Duration::ZERO);
+        let db_name = test_db_name("store");
+        let mut store = Store::open(Box::new(Storage::with_durability(
+            Some(&db_name),
+            durability,
+        )))
+        .unwrap();
+        store.insert("key1", "value1").unwrap();
+        store.close().unwrap();
+        let store = Store::open(Box::new(Storage::new(Some(&db_name)))).unwrap();
+        assert_eq!(store.get("key1").unwrap(), Some(b"value1".to_vec()));
+
+        let dir = test_db_name("bitcask");
+        let db = Bitcask::open_with(&dir, BitcaskOptions::default(), durability).unwrap();
+        db.put("key1", "value1").unwrap();
+        db.sync().unwrap();
+        drop(db);
+        let db = Bitcask::open(&dir, BitcaskOptions::default()).unwrap();
+        assert_eq!(db.get("key1").unwrap(), Some(b"value1".to_vec()));
+    }
+
+    #[test]
+    fn test_watchers_only_see_synced_changes() {
+        let durability = Durability::Periodic(Duration::from_secs(60));
+        let db_name = test_db_name("watch");
+        let db = Database::open(&db_name, options(Backend::Bincode, durability)).unwrap();
+        let watcher = db.watch("key").unwrap();
+        db.insert("key1", "value1").unwrap(); // Acknowledged, not synced yet
+        assert!(watcher.try_recv().is_none());
+        assert!(watcher.recv_timeout(Duration::from_millis(20)).is_none());
+
+        db.flush().unwrap();
+        assert_eq!(watcher.try_recv().unwrap().value, Some(b"value1".to_vec()));
+
+        let db = Arc::new(db);
+        let writer = Arc::clone(&db);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            writer.flush().unwrap();
+        });
+        db.insert("key2", "value2").unwrap();
+        assert_eq!(watcher.recv().unwrap().key, b"key2"); // Once the flush synced it
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use safina_db::kv_store::KV;
+    use safina_db::storage::{Mutation, StorageBackend};
+    use safina_db::{Error, Result, Storage, Store};
+    use std::error::Error as _;
+    use std::io;
+
+    /// A backend whose disk is full: it loads one pair but fails every write.
+    #[derive(Debug)]
+    struct FullDisk;
+
+    impl StorageBackend for FullDisk {
+        fn load(&mut self) -> Result<Vec<KV>> {
+            Ok(vec![KV::new("key1", "value1")])
+        }
+
+        fn persist(
+            &mut self,
+            _mutation: &Mutation,
+            _snapshot: &dyn Fn() -> Result<Vec<KV>>,
+        ) -> Result<()> {
+            Err(io::Error::new(io::ErrorKind::StorageFull, "disk full").into())
+        }
+
+        fn flush(&mut self, _data: Vec<KV>) -> Result<()> {
+            Ok(())
+        }
+
+        fn close(&mut self, _data: Vec<KV>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_persistence_errors_are_returned() {
+        let mut store = Store::open(Box::new(FullDisk)).unwrap();
+        assert!(matches!(store.insert("key2", "value2"), Err(Error::Io(_))));
+        assert!(matches!(
+            store.update("key1", "value1-updated"),
+            Err(Error::Io(_))
+        ));
+        assert!(matches!(store.delete("key1"), Err(Error::Io(_))));
+
+        // Nothing that failed to persist was applied.
+        assert!(store.get("key2").unwrap().is_none());
+        assert_eq!(store.get("key1").unwrap().unwrap(), b"value1");
+    }
+
+    #[test]
+    fn test_key_errors_take_precedence() {
+        let mut store = Store::open(Box::new(FullDisk)).unwrap();
+        assert!(matches!(
+            store.insert("key1", "value"),
+            Err(Error::KeyExists)
+        ));
+        assert!(matches!(
+            store.update("key2", "value"),
+            Err(Error::KeyNotFound)
+        ));
+        assert!(store.delete("key2").is_ok());
+    }
+
+    #[test]
+    fn test_error_display_and_source() {
+        assert_eq!(Error::KeyNotFound.to_string(), "Key not found");
+        assert_eq!(Error::KeyExists.to_string(), "Key already exists");
+
+        let error: Error = io::Error::new(io::ErrorKind::NotFound, "gone").into();
+        assert!(error.source().is_some());
+        assert!(Error::Corruption("bad checksum".to_string())
+            .source()
+            .is_none());
+    }
+
+    #[test]
+    fn test_missing_path_is_not_closed() {
+        let mut storage = Storage::new(None);
+        assert!(matches!(storage.load_file(None), Err(Error::NoPath)));
+        assert!(matches!(storage.save_file(Vec::new()), Err(Error::NoPath)));
+        assert!(matches!(
+            Storage::new(Some("db-test-error-unloaded")).save_file(Vec::new()),
+            Err(Error::Closed)
+        ));
+    }
+
+    #[test]
+    fn test_corrupted_snapshot_is_reported() {
+        let db_name = format!("db-test-error-corrupt-{}", std::process::id());
+        std::fs::write(&db_name, [0xff; 3]).unwrap();
+        let result = safina_db::Storage::new(None).load_file(Some(&db_name));
+        assert!(matches!(
+            result,
+            Err(Error::Serialization(_)) | Err(Error::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-index-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::index::Extractor;
+    use safina_db::keyspace::Compression;
+    use safina_db::kv_store::KV;
+    use safina_db::{Backend, Database, Error, KeyspaceOptions, Options, Store, WriteBatch};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn options(backend: Backend) -> Options {
+        Options {
+            backend,
+            sweep_interval: None,
+            shard_count: 4,
+            ..Options::default()
+        }
+    }
+
+    fn keys(pairs: Vec<KV>) -> Vec<String> {
+        pairs
+            .iter()
+            .map(|pair| pair.key_str().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_store_keeps_index_in_step() {
+        let mut store = Store::new();
+        store
+            .insert("user:1", r#"{"city": "Paris", "tags": ["admin", "dev"]}"#)
+            .unwrap();
+        store.insert("user:2", r#"{"city": "Rabat"}"#).unwrap();
+        store.insert("raw", "not json").unwrap();
+        store
+            .create_index("city", Extractor::json_path("$.city"))
+            .unwrap();
+        store
+            .create_index("tag", Extractor::json_path("tags"))
+            .unwrap();
+        assert_eq!(keys(store.find("city", "Paris").unwrap()), ["user:1"]);
+        assert_eq!(keys(store.find("tag", "dev").unwrap()), ["user:1"]);
+
+        store
+            .update("user:1", r#"{"city": "Rabat", "tags": ["dev"]}"#)
+            .unwrap();
+        assert!(store.find("city", "Paris").unwrap().is_empty());
+        assert_eq!(
+            keys(store.find("city", "Rabat").unwrap()),
+            ["user:1", "user:2"]
+        );
+        assert!(store.find("tag", "admin").unwrap().is_empty());
+
+        store.delete("user:2").unwrap();
+        assert_eq!(keys(store.find("city", "Rabat").unwrap()), ["user:1"]);
+
+        store
+            .insert_with_ttl("user:3", r#"{"city": "Rabat"}"#, Duration::from_millis(20))
+            .unwrap();
+        assert_eq!(store.find("city", "Rabat").unwrap().len(), 2);
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(keys(store.find("city", "Rabat").unwrap()), ["user:1"]); // Expired
+
+        store.drop_index("tag").unwrap();
+        assert_eq!(store.index_names(), ["city"]);
+        assert!(matches!(
+            store.find("tag", "dev"),
+            Err(Error::IndexNotFound(_))
+        ));
+        assert!(matches!(
+            store.create_index("city", Extractor::json_path("city")),
+            Err(Error::IndexExists(_))
+        ));
+        assert!(matches!(
+            store.create_index("bad", Extractor::json_path("a..b")),
+            Err(Error::InvalidIndex(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_indexes_are_rebuilt_on_open() {
+        let db_name = test_db_name("rebuilt");
+        let db = Database::open(&db_name, options(Backend::Bincode)).unwrap();
+        db.insert("user:1", r#"{"email": "alice@example.com", "age": 30}"#)
+            .unwrap();
+        db.create_index("email", Extractor::json_path("$.email"))
+            .unwrap();
+        db.create_index("age", Extractor::json_path("age")).unwrap();
+        db.create_index(
+            "initial",
+            Extractor::closure(|key, _| vec![key[5..6].to_vec()]),
+        )
+        .unwrap();
+        db.insert("user:2", r#"{"email": "bob@example.com", "age": 30}"#)
+            .unwrap();
+        assert_eq!(keys(db.find("age", "30").unwrap()), ["user:1", "user:2"]);
+        assert_eq!(keys(db.find("initial", "2").unwrap()), ["user:2"]);
+        assert_eq!(db.indexes().unwrap(), ["age", "email", "initial"]);
+        db.drop_index("age").unwrap();
+        db.close().unwrap();
+
+        let db = Database::open(&db_name, options(Backend::Bincode)).unwrap();
+        assert_eq!(db.indexes().unwrap(), ["email", "initial"]);
+        assert_eq!(
+            keys(db.find("email", "bob@example.com").unwrap()),
+            ["user:2"]
+        );
+        assert!(matches!(
+            db.find("initial", "2"),
+            Err(Error::IndexUnregistered(_))
+        )); // Closures aren't persisted
+        db.create_index(
+            "initial",
+            Extractor::closure(|key, _| vec![key[5..6].to_vec()]),
+        )
+        .unwrap();
+        assert_eq!(keys(db.find("initial", "2").unwrap()), ["user:2"]);
+        assert!(db
+            .scan(..)
+            .unwrap()
+            .iter()
+            .all(|pair| pair.key_str().starts_with("user:")));
+        assert!(matches!(
+            db.create_index("email", Extractor::json_path("mail")),
+            Err(Error::IndexExists(_))
+        ));
+        assert!(matches!(
+            db.create_index("bad", Extractor::json_path("a[x]")),
+            Err(Error::InvalidIndex(_))
+        ));
+        assert!(matches!(db.drop_index("age"), Err(Error::IndexNotFound(_))));
+        db.close().unwrap();
+
+        let db = Database::open(&db_name, options(Backend::Bincode)).unwrap();
+        db.drop_index("initial").unwrap(); // Dropped without being created again
+        assert_eq!(db.indexes().unwrap(), ["email"]);
+    }
+
+    #[test]
+    fn test_transactions_and_batches_maintain_indexes() {
+        let db = Database::open("memory", options(Backend::Memory)).unwrap();
+        db.create_index("status", Extractor::json_path("status"))
+            .unwrap();
+        db.insert("order:1", r#"{"status": "open"}"#).unwrap();
+
+        let failed: Result<(), Error> = db.transaction(|tx| {
+            tx.update("order:1", r#"{"status": "shipped"}"#)?;
+            Err(Error::KeyNotFound)
+        });
+        assert!(failed.is_err());
+        assert_eq!(keys(db.find("status", "open").unwrap()), ["order:1"]); // Rolled back
+
+        db.transaction(|tx| {
+            tx.update("order:1", r#"{"status": "shipped"}"#)?;
+            tx.insert("order:2", r#"{"status": "open"}"#)
+        })
+        .unwrap();
+        assert_eq!(keys(db.find("status", "open").unwrap()), ["order:2"]);
+        assert_eq!(keys(db.find("status", "shipped").unwrap()), ["order:1"]);
+
+        let mut batch = WriteBatch::new();
+        batch
+            .delete("order:1")
+            .put("order:3", r#"{"status": "shipped"}"#);
+        db.write_batch(batch).unwrap();
+        assert_eq!(keys(db.find("status", "shipped").unwrap()), ["order:3"]);
+    }
+
+    #[test]
+    fn test_keyspace_find() {
+        let db = Database::open("memory", options(Backend::Memory)).unwrap();
+        let users = db.create_keyspace("users", Default::default()).unwrap();
+        db.create_index("city", Extractor::json_path("city"))
+            .unwrap();
+        users.insert("1", r#"{"city": "Paris"}"#).unwrap();
+        db.insert("office", r#"{"city": "Paris"}"#).unwrap();
+
+        let found = users.find("city", "Paris").unwrap();
+        assert_eq!(keys(found.clone()), ["1"]); // Without the prefix of the keyspace
+        assert_eq!(found[0].value, br#"{"city": "Paris"}"#);
+        assert_eq!(keys(db.find("city", "Paris").unwrap()), ["office"]);
+        assert!(matches!(
+            users.find("missing", "Paris"),
+            Err(Error::IndexNotFound(_))
+        ));
+
+        let archive = db
+            .create_keyspace(
+                "archive",
+                KeyspaceOptions {
+                    compression: Compression::Lz4,
+                    ..KeyspaceOptions::default()
+                },
+            )
+            .unwrap();
+        archive.insert("2", r#"{"city": "Paris"}"#).unwrap();
+        let found = archive.find("city", "Paris").unwrap(); // Indexed decompressed
+        assert_eq!(keys(found.clone()), ["2"]);
+        assert_eq!(found[0].value, br#"{"city": "Paris"}"#);
+        assert!(matches!(
+            db.create_index("", Extractor::json_path("city")),
+            Err(Error::InvalidIndex(_))
+        ));
+    }
+
+    #[test]
+    fn test_updates_remove_what_a_key_was_indexed_by() {
+        let tagged = Arc::new(AtomicBool::new(true));
+        let extractor = {
+            let tagged = Arc::clone(&tagged);
+            Extractor::closure(move |_, _| match tagged.load(Ordering::SeqCst) {
+                true => vec![b"tagged".to_vec()],
+                false => Vec::new(),
+            })
+        };
+        let mut store = Store::new();
+        store.insert("key1", "value1").unwrap();
+        store.create_index("tag", extractor).unwrap();
+        assert_eq!(keys(store.find("tag", "tagged").unwrap()), ["key1"]);
+
+        tagged.store(false, Ordering::SeqCst); // The old value no longer extracts the tag
+        store.update("key1", "value2").unwrap();
+        assert!(store.find("tag", "tagged").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use safina_db::{Backend, Database, Error, IsolationLevel, Options, Store};
+
+    fn memory_db() -> Database {
+        let options = Options {
+            backend: Backend::Memory,
+            ..Options::default()
+        };
+        Database::open("memory", options).unwrap()
+    }
+
+    /// Both doctors are on call; each transaction takes one off call if the other still is.
+    fn doctors_on_call(isolation: IsolationLevel) -> (Database, Result<(), Error>) {
+        let db = memory_db();
+        db.insert("alice", "on").unwrap();
+        db.insert("bob", "on").unwrap();
+
+        let mut first = db.begin_with(isolation).unwrap();
+        let mut second = db.begin_with(isolation).unwrap();
+        assert_eq!(first.get("bob").unwrap(), Some(b"on".to_vec()));
+        first.update("alice", "off").unwrap();
+        assert_eq!(second.get("alice").unwrap(), Some(b"on".to_vec()));
+        second.update("bob", "off").unwrap();
+
+        first.commit().unwrap();
+        let result = second.commit();
+        (db, result)
+    }
+
+    #[test]
+    fn test_default_level_is_serializable() {
+        let db = memory_db();
+        assert_eq!(
+            db.begin().unwrap().isolation(),
+            IsolationLevel::Serializable
+        );
+    }
+
+    #[test]
+    fn test_read_committed_sees_new_commits() {
+        let db = memory_db();
+        db.insert("key", "1").unwrap();
+        let mut tx = db.begin_with(IsolationLevel::ReadCommitted).unwrap();
+        assert_eq!(tx.get("key").unwrap(), Some(b"1".to_vec()));
+        db.update("key", "2").unwrap();
+        db.insert("new", "3").unwrap();
+        assert_eq!(tx.get("key").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(tx.get("new").unwrap(), Some(b"3".to_vec()));
+
+        // The last committer wins, nothing is checked.
+        tx.update("key", "from-tx").unwrap();
+        db.update("key", "from-db").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(db.get_string("key").unwrap(), Some("from-tx".to_string()));
+    }
+
+    #[test]
+    fn test_snapshot_reads_are_repeatable() {
+        let db = memory_db();
+        db.insert("key", "1").unwrap();
+        db.insert("gone", "x").unwrap();
+        let mut tx = db.begin_with(IsolationLevel::Snapshot).unwrap();
+        db.update("key", "2").unwrap();
+        db.delete("gone").unwrap();
+        db.insert("new", "3").unwrap();
+
+        assert_eq!(tx.get("key").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(tx.get("gone").unwrap(), Some(b"x".to_vec()));
+        assert_eq!(tx.get("new").unwrap(), None);
+        tx.insert("other", "4").unwrap();
+        tx.commit().unwrap(); // Only read keys changed, no conflict
+        assert_eq!(db.get_string("other").unwrap(), Some("4".to_string()));
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 0); // The transaction's snapshot was released
+    }
+
+    #[test]
+    fn test_snapshot_write_write_conflict() {
+        let db = memory_db();
+        db.insert("key", "1").unwrap();
+        let mut tx = db.begin_with(IsolationLevel::Snapshot).unwrap();
+        tx.update("key", "from-tx").unwrap();
+        db.update("key", "from-db").unwrap();
+        assert!(matches!(tx.commit(), Err(Error::Conflict)));
+        assert_eq!(db.get_string("key").unwrap(), Some("from-db".to_string()));
+    }
+
+    #[test]
+    fn test_snapshot_allows_write_skew() {
+        let (db, result) = doctors_on_call(IsolationLevel::Snapshot);
+        result.unwrap();
+        assert_eq!(db.get_string("alice").unwrap(), Some("off".to_string()));
+        assert_eq!(db.get_string("bob").unwrap(), Some("off".to_string()));
+    }
+
+    #[test]
+    fn test_serializable_prevents_write_skew() {
+        let (db, result) = doctors_on_call(IsolationLevel::Serializable);
+        assert!(matches!(result, Err(Error::Conflict)));
+        assert_eq!(db.get_string("alice").unwrap(), Some("off".to_string()));
+        assert_eq!(db.get_string("bob").unwrap(), Some("on".to_string()));
+    }
+
+    #[test]
+    fn test_transaction_with_level() {
+        let db = memory_db();
+        db.insert("key", "1").unwrap();
+        let seen = db
+            .transaction_with(IsolationLevel::Snapshot, |tx| {
+                assert_eq!(tx.isolation(), IsolationLevel::Snapshot);
+                db.update("key", "2")?;
+                tx.get("key")
+            })
+            .unwrap();
+        assert_eq!(seen, Some(b"1".to_vec()));
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_failed_commit_releases_its_snapshot() {
+        let options = Options {
+            backend: Backend::Memory,
+            shard_count: 2,
+            ..Options::default()
+        };
+        let db = Database::open("memory", options).unwrap();
+        let key_in = |shard| {
+            (0..)
+                .map(|n| format!("key{n}"))
+                .find(|key| db.shard_of(key) == shard)
+                .unwrap()
+        };
+        let (first, second) = (key_in(0), key_in(1));
+        db.insert(&first, "1").unwrap();
+
+        let mut tx = db.begin_with(IsolationLevel::Snapshot).unwrap();
+        tx.insert(&second, "from-tx").unwrap();
+        db.update(&first, "2").unwrap(); // Kept for the snapshot of the transaction
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 1);
+        let poisoned = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    db.with_shard_mut(1, |_| -> Result<(), Error> { panic!("poison the shard") })
+                })
+                .join()
+        });
+        assert!(poisoned.is_err());
+
+        assert!(matches!(tx.commit(), Err(Error::Poisoned)));
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 0); // Unregistered despite the error
+    }
+}
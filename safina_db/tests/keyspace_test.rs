@@ -0,0 +1,199 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-keyspace-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::keyspace::Compression;
+    use safina_db::{Backend, Database, Error, KeyspaceOptions, Options, WriteBatch};
+    use std::fs::{self, OpenOptions};
+    use std::time::Duration;
+
+    fn options(backend: Backend) -> Options {
+        Options {
+            backend,
+            sweep_interval: None,
+            shard_count: 4,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_create_list_and_drop() {
+        let db = Database::open("memory", options(Backend::Memory)).unwrap();
+        let users = db.create_keyspace("users", Default::default()).unwrap();
+        db.create_keyspace("sessions", Default::default()).unwrap();
+        assert!(matches!(
+            db.create_keyspace("users", Default::default()),
+            Err(Error::KeyspaceExists(_))
+        ));
+        assert!(matches!(
+            db.create_keyspace("", Default::default()),
+            Err(Error::InvalidKeyspaceName(_))
+        ));
+        assert_eq!(db.keyspaces().unwrap(), ["default", "sessions", "users"]);
+
+        users.insert("1", "alice").unwrap();
+        users.insert("2", "bob").unwrap();
+        db.keyspace("sessions")
+            .unwrap()
+            .insert("1", "token")
+            .unwrap();
+        db.insert("1", "default").unwrap();
+        assert_eq!(users.get("1").unwrap(), Some(b"alice".to_vec()));
+        assert_eq!(db.get_string("1").unwrap(), Some("default".to_string()));
+        let keys: Vec<String> = users
+            .scan(..)
+            .unwrap()
+            .iter()
+            .map(|pair| pair.key_str().into_owned())
+            .collect();
+        assert_eq!(keys, ["1", "2"]);
+
+        db.drop_keyspace("users").unwrap();
+        assert_eq!(db.keyspaces().unwrap(), ["default", "sessions"]);
+        assert!(matches!(
+            db.keyspace("users"),
+            Err(Error::KeyspaceNotFound(_))
+        ));
+        assert!(matches!(
+            users.insert("3", "carol"),
+            Err(Error::KeyspaceNotFound(_))
+        ));
+        let sessions = db.keyspace("sessions").unwrap();
+        assert_eq!(db.scan_prefix(sessions.key("")).unwrap().len(), 1);
+        let users = db.create_keyspace("users", Default::default()).unwrap();
+        assert_eq!(users.get("1").unwrap(), None); // Dropped with the keyspace
+        assert!(matches!(
+            db.drop_keyspace("default"),
+            Err(Error::InvalidKeyspaceName(_))
+        ));
+    }
+
+    #[test]
+    fn test_drop_survives_a_crash_whole() {
+        let db_name = test_db_name("drop-crash");
+        let db = Database::open(&db_name, options(Backend::Bincode)).unwrap();
+        let users = db.create_keyspace("users", Default::default()).unwrap();
+        for i in 0..20 {
+            users.insert(i.to_string(), "user").unwrap();
+        }
+        let wal = format!("{db_name}.shard-{}.wal", db.shard_of(users.key("1")));
+        let len = fs::metadata(&wal).unwrap().len();
+        db.drop_keyspace("users").unwrap();
+        std::mem::forget(db); // Neither flushed nor closed
+
+        // The crash lost the deletions of one shard: the commit log applies them again.
+        let file = OpenOptions::new().write(true).open(&wal).unwrap();
+        file.set_len(len).unwrap();
+        let db = Database::open(&db_name, options(Backend::Bincode)).unwrap();
+        assert_eq!(db.keyspaces().unwrap(), ["default"]);
+        let users = db.create_keyspace("users", Default::default()).unwrap();
+        assert_eq!(users.scan(..).unwrap().len(), 0);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_options_persist_across_restarts() {
+        let db_name = test_db_name("options");
+        let db = Database::open(&db_name, options(Backend::Bincode)).unwrap();
+        let cache = db
+            .create_keyspace(
+                "cache",
+                KeyspaceOptions {
+                    default_ttl: Some(Duration::from_secs(60)),
+                    compression: Compression::Lz4,
+                },
+            )
+            .unwrap();
+        let page = "<html>".repeat(100);
+        cache.insert("home", &page).unwrap();
+        cache
+            .insert_with_ttl("about", "<p>", Duration::from_secs(600))
+            .unwrap();
+        drop(cache);
+        db.close().unwrap();
+
+        let db = Database::open(&db_name, options(Backend::Bincode)).unwrap();
+        let cache = db.keyspace("cache").unwrap();
+        assert_eq!(cache.options().compression, Compression::Lz4);
+        assert_eq!(cache.get("home").unwrap(), Some(page.as_bytes().to_vec()));
+        let stored = db.scan_prefix(cache.key("home")).unwrap();
+        assert!(stored[0].value.len() < page.len()); // Compressed on disk
+        assert!(cache.ttl("home").unwrap().unwrap() <= Duration::from_secs(60));
+        assert!(cache.ttl("about").unwrap().unwrap() > Duration::from_secs(60));
+        assert!(matches!(cache.incr_by("hits", 1), Err(Error::Merge(_))));
+    }
+
+    #[test]
+    fn test_batch_across_keyspaces() {
+        let db = Database::open("memory", options(Backend::Memory)).unwrap();
+        let users = db.create_keyspace("users", Default::default()).unwrap();
+        let emails = db
+            .create_keyspace(
+                "emails",
+                KeyspaceOptions {
+                    default_ttl: Some(Duration::from_secs(60)),
+                    ..KeyspaceOptions::default()
+                },
+            )
+            .unwrap();
+        users.insert("old", "value").unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch
+            .put_in(&users, "alice", "{\"email\": \"alice@example.com\"}")
+            .put_in(&emails, "alice@example.com", "alice")
+            .delete_in(&users, "old")
+            .put("count", "1");
+        db.write_batch(batch).unwrap();
+        assert_eq!(users.get("old").unwrap(), None);
+        assert_eq!(
+            emails.get("alice@example.com").unwrap(),
+            Some(b"alice".to_vec())
+        );
+        assert!(emails.ttl("alice@example.com").unwrap().is_some());
+        assert_eq!(db.get_string("count").unwrap(), Some("1".to_string()));
+
+        // The same batch inside a transaction keeps the default TTL too.
+        db.transaction(|tx| {
+            let mut batch = WriteBatch::new();
+            batch.put_in(&emails, "bob@example.com", "bob");
+            tx.write_batch(batch)
+        })
+        .unwrap();
+        assert!(emails.ttl("bob@example.com").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_default_scans_hide_keyspaces() {
+        let db = Database::open("memory", options(Backend::Memory)).unwrap();
+        let users = db.create_keyspace("users", Default::default()).unwrap();
+        users.insert("1", "alice").unwrap();
+        db.insert("a", "value").unwrap();
+
+        let keys = |pairs: Vec<safina_db::kv_store::KV>| -> Vec<String> {
+            pairs
+                .iter()
+                .map(|pair| pair.key_str().into_owned())
+                .collect()
+        };
+        assert_eq!(keys(db.scan(..).unwrap()), ["a"]);
+        assert_eq!(keys(db.scan_prefix("").unwrap()), ["a"]);
+        assert_eq!(
+            keys(db.keyspace("default").unwrap().scan(..).unwrap()),
+            ["a"]
+        );
+        assert_eq!(keys(db.snapshot().unwrap().scan(..).unwrap()), ["a"]);
+        let snapshot = db.snapshot().unwrap();
+        assert_eq!(snapshot.scan_prefix(users.key("")).unwrap().len(), 1);
+    }
+}
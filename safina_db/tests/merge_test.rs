@@ -0,0 +1,253 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-merge-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::merge::{self, MergeOperator, MergeOperators};
+    use safina_db::storage::bitcask::BitcaskOptions;
+    use safina_db::storage::Mutation;
+    use safina_db::{Backend, Database, Error, Options, Storage, Store};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Keeps the lowest of the value and the operand, compared as bytes.
+    #[derive(Debug)]
+    struct Min;
+
+    impl MergeOperator for Min {
+        fn merge(
+            &self,
+            _key: &[u8],
+            existing: Option<&[u8]>,
+            operand: &[u8],
+        ) -> safina_db::Result<Vec<u8>> {
+            Ok(existing
+                .map_or(operand, |value| value.min(operand))
+                .to_vec())
+        }
+    }
+
+    fn options(backend: Backend) -> Options {
+        Options {
+            backend,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut store = Store::new();
+        assert_eq!(store.incr("hits").unwrap(), 1); // A missing key counts as 0
+        assert_eq!(store.incr_by("hits", 41).unwrap(), 42);
+        assert_eq!(store.decr("hits").unwrap(), 41);
+        assert_eq!(store.incr_by("hits", -50).unwrap(), -9);
+        assert_eq!(store.get("hits").unwrap(), Some(b"-9".to_vec()));
+
+        store.insert("name", "safina").unwrap();
+        assert!(matches!(store.incr("name"), Err(Error::Merge(_))));
+        assert_eq!(store.get("name").unwrap(), Some(b"safina".to_vec())); // Nothing was written
+        store.insert("max", i64::MAX.to_string()).unwrap();
+        assert!(matches!(store.incr("max"), Err(Error::Merge(_))));
+
+        // A counter keeps its expiry time, an expired one starts over.
+        store
+            .insert_with_ttl("session", "5", Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(store.incr("session").unwrap(), 6);
+        assert!(store.ttl("session").unwrap().is_some());
+        store
+            .insert_with_ttl("expired", "5", Duration::from_millis(1))
+            .unwrap();
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(store.incr("expired").unwrap(), 1);
+        assert_eq!(store.ttl("expired").unwrap(), None);
+    }
+
+    #[test]
+    fn test_merge_operators() {
+        let mut store = Store::new();
+        store.merge("list", "append", "a,").unwrap();
+        store.merge("list", "append", "b,").unwrap();
+        assert_eq!(store.get("list").unwrap(), Some(b"a,b,".to_vec()));
+        store.merge("best", "max", "7").unwrap();
+        store.merge("best", "max", "3").unwrap();
+        assert_eq!(store.get("best").unwrap(), Some(b"7".to_vec()));
+        store.merge("best", "max", "12").unwrap();
+        assert_eq!(store.get("best").unwrap(), Some(b"12".to_vec()));
+
+        assert!(matches!(
+            store.merge("key", "missing", "operand"),
+            Err(Error::Merge(_))
+        ));
+        assert_eq!(store.get("key").unwrap(), None);
+
+        // An operand that can't be folded fails the reads of its key, until it is replaced.
+        store.insert("name", "safina").unwrap();
+        store.merge("name", "max", "1").unwrap();
+        assert!(matches!(store.get("name"), Err(Error::Merge(_))));
+        assert!(matches!(store.scan(..), Err(Error::Merge(_))));
+        assert_eq!(store.scan("a".."c").unwrap().count(), 1); // Other keys still read
+        store.update("name", "fixed").unwrap();
+        assert_eq!(store.get("name").unwrap(), Some(b"fixed".to_vec()));
+
+        // A batch with a merge that can't be folded is rejected before it is persisted.
+        let merge = |key: &str, operator: &str| Mutation::Merge {
+            key: key.as_bytes().to_vec(),
+            operator: operator.to_string(),
+            operand: b"1".to_vec(),
+        };
+        let seq = store.seq();
+        assert!(store
+            .apply_batch(vec![merge("key", "counter"), merge("key", "missing")])
+            .is_err());
+        assert_eq!(store.seq(), seq);
+        store
+            .apply_batch(vec![merge("key", "counter"), merge("key", "counter")])
+            .unwrap();
+        assert_eq!(store.get("key").unwrap(), Some(b"2".to_vec()));
+    }
+
+    #[test]
+    fn test_custom_operators() {
+        let mut store = Store::new();
+        assert!(matches!(
+            store.merge("lowest", "min", "b"),
+            Err(Error::Merge(_))
+        ));
+        store.set_merge_operators(MergeOperators::new().with("min", Min));
+        store.merge("lowest", "min", "b").unwrap();
+        store.merge("lowest", "min", "a").unwrap();
+        assert_eq!(store.get("lowest").unwrap(), Some(b"a".to_vec()));
+        assert_eq!(store.incr("hits").unwrap(), 1); // The built-in operators are still there
+
+        // A counter operator that doesn't write integers is reported, not trusted.
+        store.set_merge_operators(MergeOperators::new().with(merge::COUNTER, merge::Append));
+        store.insert("text", "a").unwrap();
+        assert!(matches!(store.incr("text"), Err(Error::Merge(_))));
+    }
+
+    #[test]
+    fn test_operands_are_folded_on_reopen() {
+        let options = |backend| Options {
+            merge_operators: MergeOperators::new().with("test-min", Min),
+            ..options(backend)
+        };
+        let bitcask = || Backend::Bitcask(BitcaskOptions::default());
+        for (name, backend) in [
+            ("bincode", Backend::Bincode),
+            ("json", Backend::Json),
+            ("bitcask", bitcask()),
+        ] {
+            let db_name = test_db_name(name);
+            let db = Database::open(&db_name, options(backend.clone())).unwrap();
+            for _ in 0..10 {
+                db.incr("counter").unwrap();
+            }
+            db.merge("lowest", "test-min", "m").unwrap();
+            db.merge("lowest", "test-min", "c").unwrap();
+            db.merge("lowest", "test-min", "x").unwrap();
+            db.insert_with_ttl("session", "1", Duration::from_secs(60))
+                .unwrap();
+            db.incr_by("session", 9).unwrap();
+            std::mem::forget(db); // Leaves the operands in the log
+
+            let db = Database::open(&db_name, options(backend.clone())).unwrap();
+            assert_eq!(
+                db.get_string("counter").unwrap(),
+                Some("10".to_string()),
+                "{name}"
+            );
+            assert_eq!(
+                db.get_string("lowest").unwrap(),
+                Some("c".to_string()),
+                "{name}"
+            );
+            assert_eq!(
+                db.get_string("session").unwrap(),
+                Some("10".to_string()),
+                "{name}"
+            );
+            assert!(db.ttl("session").unwrap().is_some());
+            db.decr("counter").unwrap();
+            db.close().unwrap(); // Folds the operands into the snapshot
+
+            let db = Database::open(&db_name, options(backend)).unwrap();
+            assert_eq!(
+                db.get_string("counter").unwrap(),
+                Some("9".to_string()),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_crash_does_not_fold_operands_twice() {
+        let db_name = test_db_name("checkpoint");
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        let incr = Mutation::Merge {
+            key: b"counter".to_vec(),
+            operator: merge::COUNTER.to_string(),
+            operand: b"1".to_vec(),
+        };
+        storage.append_log(&incr).unwrap();
+        storage.append_log(&incr).unwrap();
+
+        // Simulate a crash after the new snapshot is renamed in, before the log is emptied.
+        let wal_path = format!("{db_name}.wal");
+        let log = std::fs::read(&wal_path).unwrap();
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        storage.save_file(data).unwrap();
+        std::fs::write(&wal_path, log).unwrap();
+        drop(storage);
+
+        let mut storage = Storage::new(None);
+        let data = storage.load_file(Some(&db_name)).unwrap();
+        assert_eq!(data[0].value, b"2"); // Not 4
+        storage.append_log(&incr).unwrap(); // Logged in the generation following the snapshot
+        drop(storage);
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data[0].value, b"3");
+    }
+
+    #[test]
+    fn test_concurrent_increments() {
+        let db_name = test_db_name("concurrent");
+        let options = Options {
+            shard_count: 4,
+            ..options(Backend::Bincode)
+        };
+        let db = Arc::new(Database::open(&db_name, options.clone()).unwrap());
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        db.incr("total").unwrap();
+                        db.incr_by(format!("counter{}", i % 5), t).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(db.get_string("total").unwrap(), Some("400".to_string()));
+        Arc::into_inner(db).unwrap().close().unwrap();
+
+        let db = Database::open(&db_name, options).unwrap();
+        assert_eq!(db.get_string("total").unwrap(), Some("400".to_string()));
+        assert_eq!(db.get_string("counter3").unwrap(), Some("280".to_string()));
+        // 10 * (0 + ... + 7)
+    }
+}
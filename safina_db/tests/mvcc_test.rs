@@ -0,0 +1,135 @@
+#[cfg(test)]
+mod tests {
+    use safina_db::{Backend, Database, Options, Store};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn memory_db() -> Database {
+        let options = Options {
+            backend: Backend::Memory,
+            sweep_interval: None,
+            ..Options::default()
+        };
+        Database::open("memory", options).unwrap()
+    }
+
+    fn keys(pairs: &[safina_db::kv_store::KV]) -> Vec<String> {
+        pairs
+            .iter()
+            .map(|pair| pair.key_str().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_snapshot_is_frozen() {
+        let db = memory_db();
+        db.insert("a", "1").unwrap();
+        db.insert("b", "2").unwrap();
+        let snapshot = db.snapshot().unwrap();
+
+        db.update("a", "1-updated").unwrap();
+        db.delete("b").unwrap();
+        db.insert("c", "3").unwrap();
+
+        assert_eq!(snapshot.get("a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(snapshot.get("b").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(snapshot.get("c").unwrap(), None);
+        assert_eq!(keys(&snapshot.scan(..).unwrap()), vec!["a", "b"]);
+        assert_eq!(keys(&db.scan(..).unwrap()), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn test_snapshot_sees_key_recreated_later_as_it_was() {
+        let db = memory_db();
+        db.insert("a", "1").unwrap();
+        let snapshot = db.snapshot().unwrap();
+        db.delete("a").unwrap();
+        db.insert("a", "2").unwrap();
+        db.update("a", "3").unwrap();
+
+        assert_eq!(snapshot.get("a").unwrap(), Some(b"1".to_vec()));
+        let later = db.snapshot().unwrap();
+        assert!(later.seq() > snapshot.seq());
+        assert_eq!(later.get("a").unwrap(), Some(b"3".to_vec()));
+    }
+
+    #[test]
+    fn test_snapshot_sees_whole_transactions() {
+        let db = memory_db();
+        db.insert("alice", "100").unwrap();
+        let before = db.snapshot().unwrap();
+        db.transaction(|tx| {
+            tx.update("alice", "70")?;
+            tx.insert("bob", "30")
+        })
+        .unwrap();
+        let after = db.snapshot().unwrap();
+
+        assert_eq!(keys(&before.scan_prefix("").unwrap()), vec!["alice"]);
+        assert_eq!(before.get("alice").unwrap(), Some(b"100".to_vec()));
+        assert_eq!(keys(&after.scan_prefix("").unwrap()), vec!["alice", "bob"]);
+        assert_eq!(after.get("alice").unwrap(), Some(b"70".to_vec()));
+    }
+
+    #[test]
+    fn test_versions_are_collected_when_snapshots_end() {
+        let db = memory_db();
+        db.insert("a", "1").unwrap();
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 0);
+
+        let first = db.snapshot().unwrap();
+        db.update("a", "2").unwrap();
+        let second = db.snapshot().unwrap();
+        db.update("a", "3").unwrap();
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 2);
+
+        drop(first); // Only the second snapshot still needs a version
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 1);
+        assert_eq!(second.get("a").unwrap(), Some(b"2".to_vec()));
+
+        drop(second);
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 0);
+        db.update("a", "4").unwrap(); // No snapshot open, nothing is kept
+        assert_eq!(db.with_shard(0, Store::versions).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_snapshot_expiry_is_frozen() {
+        let db = memory_db();
+        db.insert_with_ttl("short", "v", Duration::from_millis(100))
+            .unwrap();
+        let snapshot = db.snapshot().unwrap();
+        thread::sleep(Duration::from_millis(150));
+
+        assert_eq!(db.get("short").unwrap(), None);
+        assert_eq!(snapshot.get("short").unwrap(), Some(b"v".to_vec()));
+    }
+
+    #[test]
+    fn test_snapshot_does_not_block_writers() {
+        let db = Arc::new(memory_db());
+        for i in 0..100 {
+            db.insert(format!("key{i:03}"), "0").unwrap();
+        }
+        let snapshot = db.snapshot().unwrap();
+
+        let writer = {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                for i in 0..100 {
+                    db.update(format!("key{i:03}"), "1").unwrap();
+                }
+            })
+        };
+        for _ in 0..10 {
+            let pairs = snapshot.scan(..).unwrap();
+            assert_eq!(pairs.len(), 100);
+            assert!(pairs.iter().all(|pair| pair.value == b"0"));
+        }
+        writer.join().unwrap();
+
+        assert_eq!(db.get_string("key099").unwrap(), Some("1".to_string()));
+        assert_eq!(snapshot.get("key099").unwrap(), Some(b"0".to_vec()));
+    }
+}
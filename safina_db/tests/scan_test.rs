@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests {
+    use safina_db::kv_store::KV;
+    use safina_db::Store;
+
+    /// Builds a store holding the given keys, each mapped to `"value-<key>"`.
+    fn store_with(keys: &[&str]) -> Store {
+        let mut store = Store::new();
+        store.load(
+            keys.iter()
+                .map(|key| KV::new(*key, format!("value-{key}")))
+                .collect(),
+        );
+        store
+    }
+
+    fn keys<'a>(pairs: impl Iterator<Item = &'a KV>) -> Vec<&'a str> {
+        pairs
+            .map(|pair| std::str::from_utf8(&pair.key).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_full_range_is_ordered() {
+        let store = store_with(&["b", "a", "c", "ab"]);
+        assert_eq!(keys(store.scan(..).unwrap()), vec!["a", "ab", "b", "c"]);
+        assert_eq!(keys(store.scan(..).unwrap().rev()), vec!["c", "b", "ab", "a"]);
+    }
+
+    #[test]
+    fn test_scan_bounded_ranges() {
+        let store = store_with(&["a", "b", "c", "d"]);
+        assert_eq!(keys(store.scan("b".."d").unwrap()), vec!["b", "c"]);
+        assert_eq!(keys(store.scan("b"..="d").unwrap()), vec!["b", "c", "d"]);
+        assert_eq!(keys(store.scan("c"..).unwrap()), vec!["c", "d"]);
+        assert_eq!(keys(store.scan(.."b").unwrap()), vec!["a"]);
+        assert_eq!(keys(store.scan("b".."d").unwrap().rev()), vec!["c", "b"]);
+    }
+
+    #[test]
+    fn test_scan_inverted_range_is_empty() {
+        let store = store_with(&["a", "b", "c"]);
+        assert_eq!(store.scan("c".."a").unwrap().count(), 0);
+        assert_eq!(store.scan("b".."b").unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_scan_prefix() {
+        let store = store_with(&[
+            "sessions:1",
+            "users:2",
+            "users:1",
+            "users;",
+            "user",
+            "users:",
+        ]);
+        assert_eq!(
+            keys(store.scan_prefix("users:").unwrap()),
+            vec!["users:", "users:1", "users:2"]
+        );
+        assert_eq!(
+            keys(store.scan_prefix("users:").unwrap().rev()),
+            vec!["users:2", "users:1", "users:"]
+        );
+        assert_eq!(store.scan_prefix("").unwrap().count(), 6);
+        assert_eq!(store.scan_prefix("nope").unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_scan_prefix_with_max_char() {
+        let max = char::MAX.to_string();
+        let inside = format!("a{max}{max}b");
+        let store = store_with(&["a", &format!("a{max}"), &inside, "b"]);
+        assert_eq!(keys(store.scan_prefix(format!("a{max}")).unwrap()).len(), 2);
+        assert_eq!(keys(store.scan_prefix(&max).unwrap()).len(), 0);
+    }
+
+    #[test]
+    fn test_scan_values() {
+        let store = store_with(&["k1", "k2"]);
+        let values: Vec<&[u8]> = store.scan(..).unwrap().map(|pair| pair.value.as_slice()).collect();
+        assert_eq!(values, vec![b"value-k1", b"value-k2"]);
+    }
+
+    #[test]
+    fn test_scan_binary_keys() {
+        let mut store = Store::new();
+        for key in [&[0x01, 0xff][..], &[0x01, 0xff, 0x00], &[0x02], &[0xff, 0xff]] {
+            store.insert(key, [0x00, 0x80]).unwrap();
+        }
+        let keys: Vec<&[u8]> = store
+            .scan_bytes(&[0x01][..]..&[0x03][..]).unwrap()
+            .map(|pair| pair.key.as_slice())
+            .collect();
+        assert_eq!(keys, vec![&[0x01, 0xff][..], &[0x01, 0xff, 0x00], &[0x02]]);
+        assert_eq!(store.scan_prefix([0x01, 0xff]).unwrap().count(), 2);
+        assert_eq!(store.scan_prefix([0xff]).unwrap().count(), 1);
+        assert_eq!(store.get([0xff, 0xff]).unwrap().unwrap(), [0x00, 0x80]);
+    }
+}
@@ -0,0 +1,231 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-shard-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::bitcask::BitcaskOptions;
+    use safina_db::{Backend, Database, Error, Options, Store, WriteBatch};
+    use std::fs::{self, OpenOptions};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn options(backend: Backend, shard_count: usize) -> Options {
+        Options {
+            backend,
+            shard_count,
+            ..Options::default()
+        }
+    }
+
+    /// Returns two keys held by different shards of `db`.
+    fn keys_in_two_shards(db: &Database) -> (String, String) {
+        let first = "key0".to_string();
+        let second = (1..)
+            .map(|i| format!("key{i}"))
+            .find(|key| db.shard_of(key) != db.shard_of(&first))
+            .unwrap();
+        (first, second)
+    }
+
+    #[test]
+    fn test_keys_spread_over_shards() {
+        let db = Database::open("memory", options(Backend::Memory, 8)).unwrap();
+        assert_eq!(db.shard_count(), 8);
+        for i in 0..1000 {
+            db.insert(format!("key{i:04}"), "value").unwrap();
+        }
+
+        let sizes: Vec<usize> = (0..8)
+            .map(|index| db.with_shard(index, Store::len).unwrap())
+            .collect();
+        assert!(sizes.iter().all(|size| *size > 0));
+        assert_eq!(sizes.iter().sum::<usize>(), 1000);
+
+        // Scans merge the shards back in key order.
+        let keys: Vec<String> = db
+            .scan(..)
+            .unwrap()
+            .iter()
+            .map(|pair| pair.key_str().into_owned())
+            .collect();
+        let expected: Vec<String> = (0..1000).map(|i| format!("key{i:04}")).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(db.scan_prefix("key01").unwrap().len(), 100);
+        assert_eq!(db.scan("key0100".."key0110").unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_parallel_writers() {
+        let db = Arc::new(Database::open("memory", options(Backend::Memory, 4)).unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    for i in 0..250 {
+                        db.insert(format!("t{t}-key{i}"), "value").unwrap();
+                        db.update(format!("t{t}-key{i}"), "updated").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let pairs = db.scan(..).unwrap();
+        assert_eq!(pairs.len(), 1000);
+        assert!(pairs.iter().all(|pair| pair.value == b"updated"));
+    }
+
+    #[test]
+    fn test_sharded_database_reopens() {
+        let db_name = test_db_name("reopen");
+        let db = Database::open(&db_name, options(Backend::Bincode, 4)).unwrap();
+        for i in 0..100 {
+            db.insert(format!("key{i}"), format!("value{i}")).unwrap();
+        }
+        db.delete("key7").unwrap();
+        db.close().unwrap();
+
+        assert!(Path::new(&format!("{db_name}.shard-3.wal")).exists());
+        assert_eq!(
+            std::fs::read_to_string(format!("{db_name}.shards")).unwrap(),
+            "4"
+        );
+        let db = Database::open(&db_name, options(Backend::Bincode, 4)).unwrap();
+        assert_eq!(db.scan(..).unwrap().len(), 99);
+        assert_eq!(db.get_string("key42").unwrap(), Some("value42".to_string()));
+        assert_eq!(db.get("key7").unwrap(), None);
+    }
+
+    #[test]
+    fn test_sharded_bitcask_reopens() {
+        let db_name = test_db_name("bitcask");
+        let backend = || Backend::Bitcask(BitcaskOptions::default());
+        let db = Database::open(&db_name, options(backend(), 3)).unwrap();
+        for i in 0..30 {
+            db.insert(format!("key{i}"), "value").unwrap();
+        }
+        db.close().unwrap();
+
+        let db = Database::open(&db_name, options(backend(), 3)).unwrap();
+        assert_eq!(db.scan(..).unwrap().len(), 30);
+    }
+
+    #[test]
+    fn test_shard_count_mismatch() {
+        let db_name = test_db_name("mismatch");
+        let db = Database::open(&db_name, options(Backend::Bincode, 4)).unwrap();
+        db.insert("key1", "value1").unwrap();
+        db.close().unwrap();
+        assert!(matches!(
+            Database::open(&db_name, options(Backend::Bincode, 2)),
+            Err(Error::ShardCount(4))
+        ));
+        assert!(matches!(
+            Database::open(&db_name, options(Backend::Bincode, 1)),
+            Err(Error::ShardCount(4))
+        ));
+
+        // A database created before sharding has a single shard.
+        let legacy = test_db_name("legacy");
+        let db = Database::open(&legacy, Options::default()).unwrap();
+        db.insert("key1", "value1").unwrap();
+        db.close().unwrap();
+        assert!(matches!(
+            Database::open(&legacy, options(Backend::Bincode, 4)),
+            Err(Error::ShardCount(1))
+        ));
+        let db = Database::open(&legacy, Options::default()).unwrap();
+        assert_eq!(db.get_string("key1").unwrap(), Some("value1".to_string()));
+    }
+
+    #[test]
+    fn test_cross_shard_transaction() {
+        let db = Database::open("memory", options(Backend::Memory, 4)).unwrap();
+        let (alice, bob) = keys_in_two_shards(&db);
+        db.insert(&alice, "100").unwrap();
+        db.insert(&bob, "0").unwrap();
+        let before = db.snapshot().unwrap();
+
+        db.transaction(|tx| {
+            tx.update(&alice, "70")?;
+            tx.update(&bob, "30")
+        })
+        .unwrap();
+        assert_eq!(db.get_string(&alice).unwrap(), Some("70".to_string()));
+        assert_eq!(db.get_string(&bob).unwrap(), Some("30".to_string()));
+        assert_eq!(before.get(&alice).unwrap(), Some(b"100".to_vec()));
+        assert_eq!(before.get(&bob).unwrap(), Some(b"0".to_vec()));
+
+        // A conflict in one shard rolls back the writes to every shard.
+        let mut tx = db.begin().unwrap();
+        tx.get(&bob).unwrap();
+        tx.update(&alice, "0").unwrap();
+        db.update(&bob, "31").unwrap();
+        assert!(matches!(tx.commit(), Err(Error::Conflict)));
+        assert_eq!(db.get_string(&alice).unwrap(), Some("70".to_string()));
+
+        drop(before);
+        assert!((0..4).all(|index| db.with_shard(index, Store::versions).unwrap() == 0));
+    }
+
+    #[test]
+    fn test_cross_shard_batch_is_recovered_whole_or_not_at_all() {
+        let db_name = test_db_name("crash");
+        let crash_options = || Options {
+            sweep_interval: None,
+            ..options(Backend::Bincode, 2)
+        };
+        let db = Database::open(&db_name, crash_options()).unwrap();
+        let (alice, bob) = keys_in_two_shards(&db);
+        let wal = |key: &str| format!("{db_name}.shard-{}.wal", db.shard_of(key));
+        let (alice_wal, bob_wal) = (wal(&alice), wal(&bob));
+        let len = |path: &str| fs::metadata(path).unwrap().len();
+        let cut = |path: &str, len: u64| {
+            let file = OpenOptions::new().write(true).open(path).unwrap();
+            file.set_len(len).unwrap();
+        };
+        db.insert(&alice, "100").unwrap();
+        db.insert(&bob, "0").unwrap();
+        let bob_len = len(&bob_wal);
+        db.transaction(|tx| {
+            tx.update(&alice, "70")?;
+            tx.update(&bob, "30")
+        })
+        .unwrap();
+        std::mem::forget(db); // Neither flushed nor closed
+
+        // The crash lost the part of one shard: it is applied again from the commit log.
+        cut(&bob_wal, bob_len);
+        let db = Database::open(&db_name, crash_options()).unwrap();
+        assert_eq!(db.get_string(&alice).unwrap(), Some("70".to_string()));
+        assert_eq!(db.get_string(&bob).unwrap(), Some("30".to_string()));
+        assert_eq!(len(&format!("{db_name}.txn")), 0);
+
+        let (alice_len, bob_len) = (len(&alice_wal), len(&bob_wal));
+        let mut batch = WriteBatch::new();
+        batch.put(&alice, "0").put(&bob, "100");
+        db.write_batch(batch).unwrap();
+        std::mem::forget(db);
+
+        // The crash tore the batch in the commit log: no shard keeps its part.
+        cut(&alice_wal, alice_len);
+        cut(&bob_wal, bob_len);
+        let txn = format!("{db_name}.txn");
+        cut(&txn, len(&txn) - 3);
+        let db = Database::open(&db_name, crash_options()).unwrap();
+        assert_eq!(db.get_string(&alice).unwrap(), Some("70".to_string()));
+        assert_eq!(db.get_string(&bob).unwrap(), Some("30".to_string()));
+        db.close().unwrap();
+    }
+}
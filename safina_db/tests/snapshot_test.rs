@@ -0,0 +1,80 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-snapshot-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::kv_store::KV;
+    use safina_db::storage::snapshot;
+    use safina_db::Storage;
+
+    fn kv(key: &str, value: &str) -> KV {
+        KV::new(key, value)
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_content() {
+        let db_name = test_db_name("replace");
+        snapshot::write_atomic(&db_name, b"old").unwrap();
+        snapshot::write_atomic(&db_name, b"new").unwrap();
+        assert_eq!(std::fs::read(&db_name).unwrap(), b"new");
+        assert!(!std::path::Path::new(&snapshot::temp_path(&db_name)).exists());
+    }
+
+    #[test]
+    fn test_saved_snapshot_is_loaded() {
+        let db_name = test_db_name("save");
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        storage
+            .save_file(vec![kv("key1", "value1"), kv("key2", "value2")])
+            .unwrap();
+        storage
+            .save_file(vec![kv("key1", "value1-updated")])
+            .unwrap();
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].value, b"value1-updated");
+    }
+
+    #[test]
+    fn test_failed_save_keeps_previous_snapshot() {
+        let db_name = test_db_name("failed");
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        storage.save_file(vec![kv("key1", "value1")]).unwrap();
+
+        // A directory squatting the temp path makes the next snapshot fail before the rename.
+        std::fs::create_dir(snapshot::temp_path(&db_name)).unwrap();
+        assert!(storage.save_file(vec![kv("key2", "value2")]).is_err());
+        std::fs::remove_dir(snapshot::temp_path(&db_name)).unwrap();
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].key, b"key1");
+    }
+
+    #[test]
+    fn test_leftover_temp_file_is_ignored() {
+        let db_name = test_db_name("leftover");
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        storage.save_file(vec![kv("key1", "value1")]).unwrap();
+
+        // A crash before the rename leaves a half written temp file behind.
+        std::fs::write(snapshot::temp_path(&db_name), b"\x01\x02garbage").unwrap();
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].value, b"value1");
+    }
+}
@@ -1,24 +1,18 @@
 use once_cell::sync::Lazy;
-use safina_db::{Store, STORAGE_MUTEX};
+use safina_db::{Storage, Store};
 use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[cfg(test)]
 pub static TEST_STORE: Lazy<Mutex<Store>> = Lazy::new(|| {
     // Perform any setup needed before tests run
-    let mut store = Store::new();
     let start = SystemTime::now();
     let since_the_epoch = start.duration_since(UNIX_EPOCH).expect("Time went backwards");
     let timestamp = since_the_epoch.as_secs();
     let db_name = format!("db-test-{}", timestamp);
  
     println!("- Loading test database...");
-    match STORAGE_MUTEX.lock().unwrap().load_file(Some(db_name.as_str())) {
-        Ok(data) => store.data = data,
-        Err(err) => {
-            println!("Invalid: {}", err);
-        }
-    }
+    let store = Store::open(Box::new(Storage::new(Some(db_name.as_str())))).unwrap();
     // Initialize store or perform setup
     Mutex::new(store)
 });
@@ -27,134 +21,102 @@ pub static TEST_STORE: Lazy<Mutex<Store>> = Lazy::new(|| {
 mod tests {
     use super::TEST_STORE;
     use safina_db::kv_store::KV;
+    use safina_db::Error;
 
     #[test]
     fn test_insert_new_key() {
         let mut store = TEST_STORE.lock().unwrap();
-        let test_data = KV {
-            key: String::from("key1"),
-            value: String::from("value1")
-        };
+        let test_data = KV::new("key1", "value1");
         let result = store.insert(&test_data.key, &test_data.value);
         assert!(result.is_ok());
-        assert_eq!(store.get("key1").unwrap().value, "value1");
+        assert_eq!(store.get("key1").unwrap().unwrap(), b"value1");
     }
 
     #[test]
     fn test_insert_empty_key() {
-        let test_data = KV {
-            key: String::from(""),
-            value: String::from("value2")
-        };
+        let test_data = KV::new("", "value2");
         let mut store = TEST_STORE.lock().unwrap();
         let result = store.insert(&test_data.key, &test_data.value);
         assert!(result.is_ok());
-        assert_eq!(store.get("").unwrap().value, test_data.value);
+        assert_eq!(store.get("").unwrap().unwrap(), test_data.value);
     }
 
     #[test]
     fn test_insert_existing_key() {
-        let test_data = KV {
-            key: String::from("key3"),
-            value: String::from("value3")
-        };
+        let test_data = KV::new("key3", "value3");
         let mut store = TEST_STORE.lock().unwrap();
         store.insert(&test_data.key, &test_data.value).unwrap();
         let result = store.insert(&test_data.key, &test_data.value);
         assert!(result.is_err());
-        assert_eq!(result.err(), Some("Key already exists".to_string()));
+        assert!(matches!(result, Err(Error::KeyExists)));
     }
 
     #[test]
     fn test_get_existing_key() {
-        let test_data = KV {
-            key: String::from("key4"),
-            value: String::from("value4")
-        };
+        let test_data = KV::new("key4", "value4");
         let mut store = TEST_STORE.lock().unwrap();
  
         store.insert(&test_data.key, &test_data.value).unwrap();
-        let result = store.get(&test_data.key);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().value, test_data.value);
+        let result = store.get(&test_data.key).unwrap();
+        assert!(result.is_some());
+        assert_eq!(result.unwrap(), test_data.value);
     }
 
     #[test]
     fn test_get_non_existing_key() {
-        let test_data = KV {
-            key: String::from("key5"),
-            value: String::from("")
-        };
-        let mut store = TEST_STORE.lock().unwrap();
-        let result = store.get(&test_data.key);
-        assert!(result.is_err());
-        assert_eq!(result.err(), Some("Key not found"));
+        let test_data = KV::new("key5", "");
+        let store = TEST_STORE.lock().unwrap();
+        let result = store.get(&test_data.key).unwrap();
+        assert!(result.is_none());
     }
 
     #[test]
     fn test_update_existing_key() {
-        let test_data = KV {
-            key: String::from("key6"),
-            value: String::from("value6")
-        };
-        let test_data_update = KV {
-            key: test_data.key.clone(),
-            value: String::from("value6-updated")
-        };
+        let test_data = KV::new("key6", "value6");
+        let test_data_update = KV::new(test_data.key.clone(), "value6-updated");
         let mut store = TEST_STORE.lock().unwrap();
         store.insert(&test_data.key, &test_data.value).unwrap();
         let result = store.update(&test_data_update.key, &test_data_update.value);
         assert!(result.is_ok());
-        assert_eq!(store.get(&test_data.key).unwrap().value, test_data_update.value);
+        assert_eq!(store.get(&test_data.key).unwrap().unwrap(), test_data_update.value);
     }
 
     #[test]
     fn test_update_non_existing_key() {
-        let test_data_update = KV {
-            key: String::from(""),
-            value: String::from("value7-updated")
-        };
+        let test_data_update = KV::new("", "value7-updated");
         let mut store = TEST_STORE.lock().unwrap();
-        store.delete(&test_data_update.key);
+        store.delete(&test_data_update.key).unwrap();
         let result = store.update(&test_data_update.key, &test_data_update.value);
         assert!(result.is_err());
-        assert_eq!(result.err(), Some("Key not found".to_string()));
+        assert!(matches!(result, Err(Error::KeyNotFound)));
     }
 
     #[test]
     fn test_delete_existing_key() {
-        let test_data = KV {
-            key: String::from("key8-should-be-deleted"),
-            value: String::from("value8-should-be-deleted")
-        };
+        let test_data = KV::new("key8-should-be-deleted", "value8-should-be-deleted");
         let mut store = TEST_STORE.lock().unwrap();
         store.insert(&test_data.key, &test_data.value).unwrap();
-        store.delete(&test_data.key);
-        let result = store.get(&test_data.key);
-        assert!(result.is_err());
-        assert_eq!(result.err(), Some("Key not found"));
+        store.delete(&test_data.key).unwrap();
+        let result = store.get(&test_data.key).unwrap();
+        assert!(result.is_none());
     }
 
     #[test]
     fn test_delete_non_existing_key() {
         let mut store = TEST_STORE.lock().unwrap();
-        store.delete("key-doesnt-exists"); // Should not panic or cause error
-        let result = store.get("key-doesnt-exists");
-        assert!(result.is_err());
-        assert_eq!(result.err(), Some("Key not found"));
+        store.delete("key-doesnt-exists").unwrap(); // Should not panic or cause error
+        let result = store.get("key-doesnt-exists").unwrap();
+        assert!(result.is_none());
     }
 
     // Additional edge cases
     #[test]
     fn test_insert_empty_value() {
-        let test_data = KV {
-            key: String::from("key9-with-empty-value"),
-            value: String::from("")
-        };
+        let test_data = KV::new("key9-with-empty-value", "");
         let mut store = TEST_STORE.lock().unwrap();
         let result = store.insert(&test_data.key, &test_data.value);
         assert!(result.is_ok());
-        assert_eq!(store.get(&test_data.key).unwrap().value, test_data.value);
+        assert_eq!(store.get(&test_data.key).unwrap().unwrap(), test_data.value);
     }
 
     #[test]
@@ -164,49 +126,72 @@ mod tests {
         let large_value = "v".repeat(1000);
         let result = store.insert(large_key.clone().as_str(), large_value.clone().as_str());
         assert!(result.is_ok());
-        assert_eq!(store.get(&large_key).unwrap().value, large_value);
+        assert_eq!(store.get(&large_key).unwrap().unwrap(), large_value.as_bytes());
     }
 
     #[test]
     fn test_update_empty_value() {
-        let test_data = KV {
-            key: String::from("key10"),
-            value: String::from("value10")
-        };
+        let test_data = KV::new("key10", "value10");
         let mut store = TEST_STORE.lock().unwrap();
         store.insert(&test_data.key, &test_data.value).unwrap();
-        let result = store.update(&test_data.key, "");
+        let result = store.update(&test_data.key, b"");
         assert!(result.is_ok());
-        assert_eq!(store.get(&test_data.key).unwrap().value, "");
+        assert_eq!(store.get(&test_data.key).unwrap().unwrap(), b"");
     }
 
     #[test]
     fn test_delete_empty_key() {
-        let test_data = KV {
-            key: String::from(""),
-            value: String::from("value11-with-empty-key")
-        };
+        let test_data = KV::new("", "value11-with-empty-key");
         let mut store = TEST_STORE.lock().unwrap();
-        store.delete(&test_data.key);
+        store.delete(&test_data.key).unwrap();
         store.insert(&test_data.key, &test_data.value).unwrap();
-        store.delete(&test_data.key);
-        let result = store.get(&test_data.key);
-        assert!(result.is_err());
-        assert_eq!(result.err(), Some("Key not found"));
+        store.delete(&test_data.key).unwrap();
+        let result = store.get(&test_data.key).unwrap();
+        assert!(result.is_none());
     }
 
     #[test]
     fn test_delete_multiple_times() {
-        let test_data = KV {
-            key: String::from("key-12"),
-            value: String::from("value12")
-        };
+        let test_data = KV::new("key-12", "value12");
         let mut store = TEST_STORE.lock().unwrap();
         store.insert(&test_data.key, &test_data.value).unwrap();
-        store.delete(&test_data.key);
-        store.delete(&test_data.key);
-        let result = store.get(&test_data.key);
-        assert!(result.is_err());
-        assert_eq!(result.err(), Some("Key not found"));
+        store.delete(&test_data.key).unwrap();
+        store.delete(&test_data.key).unwrap();
+        let result = store.get(&test_data.key).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_load_from_snapshot_format() {
+        let mut store = safina_db::Store::new();
+        store.load(vec![
+            KV::new("key13", "value13"),
+            KV::new("key14", "value14"),
+        ]);
+        assert_eq!(store.get("key13").unwrap().unwrap(), b"value13");
+        assert_eq!(store.get("key14").unwrap().unwrap(), b"value14");
+
+        let mut pairs = store.to_vec().unwrap();
+        pairs.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].key, b"key13");
+        assert_eq!(pairs[1].value, b"value14");
+    }
+
+    #[test]
+    fn test_get_mut_changes_memory() {
+        let mut store = safina_db::Store::new();
+        store.insert("key15", "value15").unwrap();
+        let watcher = store.watch("key15");
+        let value = store
+            .update_with("key15", |value| value.extend_from_slice(b"-edited"))
+            .unwrap();
+        assert_eq!(value, b"value15-edited");
+        assert_eq!(store.get("key15").unwrap().unwrap(), b"value15-edited");
+        assert_eq!(watcher.try_recv().unwrap().value, Some(value));
+        assert!(matches!(
+            store.update_with("key-doesnt-exists", |_| {}),
+            Err(Error::KeyNotFound)
+        ));
     }
 }
@@ -0,0 +1,212 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!(
+        "db-test-transaction-{}-{}",
+        name,
+        since_the_epoch.as_nanos()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::bitcask::{Bitcask, BitcaskOptions};
+    use safina_db::storage::Mutation;
+    use safina_db::{Backend, Database, Error, Options, Storage, Store};
+    use std::fs::{self, OpenOptions};
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn memory_db() -> Database {
+        let options = Options {
+            backend: Backend::Memory,
+            ..Options::default()
+        };
+        Database::open("memory", options).unwrap()
+    }
+
+    fn put(key: &str, value: &str) -> Mutation {
+        Mutation::Put {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    #[test]
+    fn test_commit_applies_every_write() {
+        let db = memory_db();
+        db.insert("alice", "100").unwrap();
+        db.insert("carol", "5").unwrap();
+
+        db.transaction(|tx| {
+            assert_eq!(tx.get("alice")?, Some(b"100".to_vec()));
+            tx.update("alice", "70")?;
+            tx.insert("bob", "30")?;
+            tx.delete("carol")?;
+            // The transaction sees its own writes, the database doesn't yet.
+            assert_eq!(tx.get("bob")?, Some(b"30".to_vec()));
+            assert_eq!(tx.get("carol")?, None);
+            assert_eq!(db.get("bob").unwrap(), None);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(db.get_string("alice").unwrap(), Some("70".to_string()));
+        assert_eq!(db.get_string("bob").unwrap(), Some("30".to_string()));
+        assert_eq!(db.get("carol").unwrap(), None);
+    }
+
+    #[test]
+    fn test_error_rolls_back() {
+        let db = memory_db();
+        db.insert("key1", "value1").unwrap();
+
+        let result = db.transaction(|tx| {
+            tx.insert("key2", "value2")?;
+            tx.insert("key1", "again") // Fails with KeyExists
+        });
+        assert!(matches!(result, Err(Error::KeyExists)));
+        assert_eq!(db.get("key2").unwrap(), None);
+    }
+
+    #[test]
+    fn test_panic_rolls_back() {
+        let db = memory_db();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            db.transaction(|tx| -> safina_db::Result<()> {
+                tx.insert("key1", "value1")?;
+                panic!("boom");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(db.get("key1").unwrap(), None);
+        db.insert("key1", "value1").unwrap(); // The store is still usable
+    }
+
+    #[test]
+    fn test_read_write_conflict() {
+        let db = memory_db();
+        db.insert("balance", "100").unwrap();
+
+        let mut tx = db.begin().unwrap();
+        assert_eq!(tx.get("balance").unwrap(), Some(b"100".to_vec()));
+        tx.insert("audit", "read 100").unwrap();
+        db.update("balance", "50").unwrap(); // Another writer changes what tx read
+
+        assert!(matches!(tx.commit(), Err(Error::Conflict)));
+        assert_eq!(db.get("audit").unwrap(), None);
+        assert_eq!(db.get_string("balance").unwrap(), Some("50".to_string()));
+    }
+
+    #[test]
+    fn test_write_write_conflict() {
+        let db = memory_db();
+        let mut first = db.begin().unwrap();
+        let mut second = db.begin().unwrap();
+        first.delete("key").unwrap();
+        second.delete("other").unwrap();
+        first.insert("key", "first").unwrap();
+        second.insert("key", "second").unwrap();
+
+        first.commit().unwrap();
+        assert!(matches!(second.commit(), Err(Error::Conflict)));
+        assert_eq!(db.get_string("key").unwrap(), Some("first".to_string()));
+    }
+
+    #[test]
+    fn test_unrelated_changes_dont_conflict() {
+        let db = memory_db();
+        db.insert("key1", "value1").unwrap();
+
+        let mut tx = db.begin().unwrap();
+        tx.update("key1", "updated").unwrap();
+        db.insert("key2", "value2").unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.begin().unwrap();
+        tx.insert("key3", "value3").unwrap();
+        tx.rollback();
+        assert_eq!(db.get_string("key1").unwrap(), Some("updated".to_string()));
+        assert_eq!(db.get("key3").unwrap(), None);
+    }
+
+    #[test]
+    fn test_torn_batch_is_discarded_from_wal() {
+        let db_name = test_db_name("wal");
+        let mut store = Store::open(Box::new(Storage::new(Some(&db_name)))).unwrap();
+        store.insert("key1", "value1").unwrap();
+        store
+            .apply_batch(vec![put("key2", "value2"), put("key3", "value3")])
+            .unwrap();
+        drop(store); // Without closing, the log is not folded into the snapshot
+
+        let wal = OpenOptions::new()
+            .write(true)
+            .open(format!("{db_name}.wal"))
+            .unwrap();
+        let len = wal.metadata().unwrap().len();
+        wal.set_len(len - 3).unwrap(); // Tear the batch record
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].key, b"key1");
+    }
+
+    #[test]
+    fn test_torn_batch_is_discarded_from_bitcask() {
+        let db_name = test_db_name("bitcask");
+        let bitcask = Bitcask::open(&db_name, BitcaskOptions::default()).unwrap();
+        bitcask.put("key1", "value1").unwrap();
+        bitcask
+            .write_batch(&[put("key2", "value2"), put("key1", "changed")])
+            .unwrap();
+        assert_eq!(bitcask.get("key1").unwrap(), Some(b"changed".to_vec()));
+        drop(bitcask);
+
+        let segment = fs::read_dir(&db_name)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| fs::metadata(path).unwrap().len() > 0)
+            .unwrap();
+        let file = OpenOptions::new().write(true).open(&segment).unwrap();
+        let len = file.metadata().unwrap().len();
+        file.set_len(len - 1).unwrap(); // Tear the commit marker
+
+        let bitcask = Bitcask::open(&db_name, BitcaskOptions::default()).unwrap();
+        assert_eq!(bitcask.get("key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(bitcask.get("key2").unwrap(), None);
+    }
+
+    #[test]
+    fn test_committed_transaction_survives_reopen() {
+        let db_name = test_db_name("reopen");
+        for backend in [
+            Backend::Bincode,
+            Backend::Json,
+            Backend::Bitcask(BitcaskOptions::default()),
+        ] {
+            let path = format!("{db_name}-{backend:?}");
+            let options = Options {
+                backend,
+                ..Options::default()
+            };
+            let db = Database::open(&path, options.clone()).unwrap();
+            db.insert("key1", "value1").unwrap();
+            db.transaction(|tx| {
+                tx.delete("key1")?;
+                tx.insert("key2", "value2")
+            })
+            .unwrap();
+            drop(db);
+
+            let db = Database::open(&path, options).unwrap();
+            assert_eq!(db.get("key1").unwrap(), None);
+            assert_eq!(db.get_string("key2").unwrap(), Some("value2".to_string()));
+        }
+    }
+}
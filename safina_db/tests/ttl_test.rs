@@ -0,0 +1,173 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-ttl-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::bitcask::{Bitcask, BitcaskOptions};
+    use safina_db::storage::{JsonStorage, StorageBackend};
+    use safina_db::{Backend, Database, Error, Options, Storage, Store};
+    use std::thread;
+    use std::time::Duration;
+
+    const SHORT: Duration = Duration::from_millis(50);
+    const LONG: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn test_expired_keys_are_hidden() {
+        let mut store = Store::new();
+        store.insert_with_ttl("session", "token", SHORT).unwrap();
+        store.insert("user", "alice").unwrap();
+        assert!(store.get("session").unwrap().is_some());
+        assert!(store.ttl("session").unwrap().unwrap() <= SHORT);
+
+        thread::sleep(SHORT * 2);
+        assert!(store.get("session").unwrap().is_none());
+        assert!(matches!(store.ttl("session"), Err(Error::KeyNotFound)));
+        assert!(matches!(
+            store.update("session", "new"),
+            Err(Error::KeyNotFound)
+        ));
+        assert_eq!(store.scan(..).unwrap().count(), 1);
+        assert_eq!(store.scan(..).unwrap().rev().count(), 1);
+
+        // The expired pair is still held until it is purged, but can be replaced.
+        assert_eq!(store.len(), 2);
+        store.insert("session", "fresh").unwrap();
+        assert_eq!(store.get("session").unwrap().unwrap(), b"fresh");
+        assert_eq!(store.ttl("session").unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_expiry_and_persist() {
+        let mut store = Store::new();
+        store.insert("key", "value").unwrap();
+        assert_eq!(store.ttl("key").unwrap(), None);
+
+        store.set_expiry("key", LONG).unwrap();
+        assert!(store.ttl("key").unwrap().unwrap() > LONG - Duration::from_secs(60));
+        store.update("key", "updated").unwrap();
+        assert!(store.ttl("key").unwrap().is_some()); // Updates keep the expiry
+
+        store.persist("key").unwrap();
+        assert_eq!(store.ttl("key").unwrap(), None);
+        assert!(matches!(
+            store.set_expiry("nope", LONG),
+            Err(Error::KeyNotFound)
+        ));
+        assert!(matches!(store.persist("nope"), Err(Error::KeyNotFound)));
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let mut store = Store::new();
+        store.insert_with_ttl("a", "1", SHORT).unwrap();
+        store.insert_with_ttl("b", "2", SHORT).unwrap();
+        store.insert_with_ttl("c", "3", LONG).unwrap();
+        thread::sleep(SHORT * 2);
+        assert_eq!(store.purge_expired().unwrap(), 2);
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.purge_expired().unwrap(), 0);
+    }
+
+    /// Writes expiring pairs through a store on `backend`, purging the expired one.
+    fn write_expiring(backend: Box<dyn StorageBackend>) {
+        let mut store = Store::open(backend).unwrap();
+        store.insert_with_ttl("short", "value", SHORT).unwrap();
+        store.insert_with_ttl("long", "value", LONG).unwrap();
+        store.insert("forever", "value").unwrap();
+        store.insert("later", "value").unwrap();
+        store.set_expiry("later", LONG).unwrap();
+        thread::sleep(SHORT * 2);
+        assert_eq!(store.purge_expired().unwrap(), 1);
+        store.close().unwrap();
+    }
+
+    /// Checks a store reopened after `write_expiring` kept the expiry times.
+    fn assert_expiry_reloaded(backend: Box<dyn StorageBackend>) {
+        let store = Store::open(backend).unwrap();
+        assert_eq!(store.len(), 3);
+        assert!(store.ttl("long").unwrap().is_some());
+        assert!(store.ttl("later").unwrap().is_some());
+        assert_eq!(store.ttl("forever").unwrap(), None);
+    }
+
+    #[test]
+    fn test_bincode_expiry_round_trip() {
+        let db_name = test_db_name("bincode");
+        write_expiring(Box::new(Storage::new(Some(&db_name))));
+        assert_expiry_reloaded(Box::new(Storage::new(Some(&db_name))));
+    }
+
+    #[test]
+    fn test_json_expiry_round_trip() {
+        let db_name = test_db_name("json");
+        write_expiring(Box::new(JsonStorage::new(&db_name)));
+        assert_expiry_reloaded(Box::new(JsonStorage::new(&db_name)));
+    }
+
+    #[test]
+    fn test_bitcask_expiry_survives_merge() {
+        let db_name = test_db_name("bitcask");
+        let options = BitcaskOptions {
+            max_segment_size: 64,
+            merge_trigger: usize::MAX,
+        };
+        write_expiring(Box::new(Bitcask::open(&db_name, options.clone()).unwrap()));
+
+        let bitcask = Bitcask::open(&db_name, options.clone()).unwrap();
+        bitcask.merge().unwrap(); // The reopened keydir then comes from the hint file
+        drop(bitcask);
+        assert_expiry_reloaded(Box::new(Bitcask::open(&db_name, options).unwrap()));
+    }
+
+    #[test]
+    fn test_legacy_snapshot_loads() {
+        #[derive(serde::Serialize)]
+        struct LegacyKV {
+            key: String,
+            value: String,
+        }
+
+        let db_name = test_db_name("legacy");
+        let legacy = vec![LegacyKV {
+            key: "key1".to_string(),
+            value: "value1".to_string(),
+        }];
+        std::fs::write(&db_name, bincode::serialize(&legacy).unwrap()).unwrap();
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].value, b"value1");
+        assert_eq!(data[0].expires_at, None);
+    }
+
+    #[test]
+    fn test_database_sweeper_deletes_expired() {
+        let db_name = test_db_name("sweeper");
+        let options = Options {
+            backend: Backend::Bincode,
+            sweep_interval: Some(Duration::from_millis(10)),
+            ..Options::default()
+        };
+        let db = Database::open(&db_name, options).unwrap();
+        db.insert_with_ttl("key1", "value1", SHORT).unwrap();
+        db.insert("key2", "value2").unwrap();
+
+        thread::sleep(SHORT * 4);
+        assert_eq!(db.with_shard(0, Store::len).unwrap(), 1);
+        db.close().unwrap();
+
+        let db = Database::open(&db_name, Options::default()).unwrap();
+        assert_eq!(db.with_shard(0, Store::len).unwrap(), 1);
+        assert_eq!(db.get("key2").unwrap(), Some(b"value2".to_vec()));
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a unique database file name for a single test.
+#[cfg(test)]
+pub fn test_db_name(name: &str) -> String {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    format!("db-test-wal-{}-{}", name, since_the_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_db_name;
+    use safina_db::storage::wal::{self, LogFile};
+    use safina_db::storage::Mutation;
+    use safina_db::Storage;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Seek, SeekFrom, Write};
+
+    fn put(key: &str, value: &str) -> Mutation {
+        Mutation::Put {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    /// A log file whose writes fail once `budget` bytes have been written, like a full disk.
+    struct FailingFile {
+        file: File,
+        budget: usize,
+    }
+
+    impl Write for FailingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.budget == 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            let written = self.file.write(&buf[..buf.len().min(self.budget)])?;
+            self.budget -= written;
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+    }
+
+    impl Seek for FailingFile {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.file.seek(pos)
+        }
+    }
+
+    impl LogFile for FailingFile {
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.file.set_len(len)
+        }
+    }
+
+    #[test]
+    fn test_log_is_replayed_on_load() {
+        let db_name = test_db_name("replay");
+        let mut storage = Storage::new(None);
+        assert!(storage.load_file(Some(&db_name)).unwrap().is_empty());
+        storage.append_log(&put("key1", "value1")).unwrap();
+        storage.append_log(&put("key2", "value2")).unwrap();
+        storage.append_log(&put("key1", "value1-updated")).unwrap();
+        storage
+            .append_log(&Mutation::Delete {
+                key: "key2".into(),
+            })
+            .unwrap();
+        drop(storage); // Simulate a crash: no snapshot was ever written
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].key, b"key1");
+        assert_eq!(data[0].value, b"value1-updated");
+    }
+
+    #[test]
+    fn test_log_is_replayed_on_top_of_snapshot() {
+        let db_name = test_db_name("snapshot");
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        storage.append_log(&put("key1", "value1")).unwrap();
+        let snapshot = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        storage.save_file(snapshot).unwrap(); // Empties the log
+        storage.append_log(&put("key2", "value2")).unwrap();
+        drop(storage);
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        let keys: Vec<&[u8]> = data.iter().map(|pair| pair.key.as_slice()).collect();
+        assert_eq!(keys, vec![b"key1", b"key2"]);
+    }
+
+    #[test]
+    fn test_torn_record_is_discarded() {
+        let db_name = test_db_name("torn");
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        storage.append_log(&put("key1", "value1")).unwrap();
+        drop(storage);
+
+        // Simulate a crash in the middle of an append: a header promising more bytes than written.
+        let mut wal = OpenOptions::new()
+            .append(true)
+            .open(format!("{db_name}.wal"))
+            .unwrap();
+        wal.write_all(&[64, 0, 0, 0, 1, 2, 3, 4, b'k']).unwrap();
+        drop(wal);
+
+        let mut storage = Storage::new(None);
+        let data = storage.load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 1);
+        storage.append_log(&put("key2", "value2")).unwrap(); // Must not land after the garbage
+        drop(storage);
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[1].value, b"value2");
+    }
+
+    #[test]
+    fn test_corrupted_record_is_not_applied() {
+        let db_name = test_db_name("corrupt");
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        storage.append_log(&put("key1", "value1")).unwrap();
+        storage.append_log(&put("key2", "value2")).unwrap();
+        drop(storage);
+
+        // Flip the last byte of the log so the second record fails its checksum.
+        let path = format!("{db_name}.wal");
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].key, b"key1");
+    }
+
+    #[test]
+    fn test_failed_append_leaves_no_torn_bytes() {
+        let db_name = test_db_name("failed");
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        storage.append_log(&put("key1", "value1")).unwrap();
+        drop(storage);
+
+        let path = format!("{db_name}.wal");
+        let end = std::fs::metadata(&path).unwrap().len();
+        let mut file = FailingFile {
+            file: OpenOptions::new().write(true).open(&path).unwrap(),
+            budget: 5,
+        };
+        file.seek(SeekFrom::Start(end)).unwrap();
+        assert!(wal::append_record(&mut file, end, &[64, 0, 0, 0, 1, 2, 3, 4, b'k']).is_err());
+        assert_eq!(file.file.metadata().unwrap().len(), end); // Cut back
+        assert_eq!(file.stream_position().unwrap(), end);
+        drop(file);
+
+        let mut storage = Storage::new(None);
+        storage.load_file(Some(&db_name)).unwrap();
+        storage.append_log(&put("key2", "value2")).unwrap();
+        drop(storage);
+        let data = Storage::new(None).load_file(Some(&db_name)).unwrap();
+        assert_eq!(data.len(), 2);
+    }
+}
@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod tests {
+    use safina_db::index::Extractor;
+    use safina_db::{Backend, Database, Event, EventKind, Options, Store, WriteBatch};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn options(shard_count: usize) -> Options {
+        Options {
+            backend: Backend::Memory,
+            sweep_interval: None,
+            shard_count,
+            ..Options::default()
+        }
+    }
+
+    fn put(key: &str, value: &str, seq: u64) -> Event {
+        Event {
+            kind: EventKind::Put,
+            key: key.as_bytes().to_vec(),
+            value: Some(value.as_bytes().to_vec()),
+            seq,
+        }
+    }
+
+    #[test]
+    fn test_store_watch() {
+        let mut store = Store::new();
+        let watcher = store.watch("user:");
+        let seq = store.seq();
+        store.insert("user:1", "alice").unwrap();
+        store.insert("order:1", "book").unwrap(); // Not watched
+        store.update("user:1", "bob").unwrap();
+        store.incr("user:count").unwrap();
+        store.set_expiry("user:1", Duration::from_secs(60)).unwrap(); // Not reported
+        store.delete("user:1").unwrap();
+        store.delete("user:missing").unwrap(); // Nothing to delete
+
+        assert_eq!(watcher.try_recv(), Some(put("user:1", "alice", seq + 1)));
+        assert_eq!(watcher.try_recv(), Some(put("user:1", "bob", seq + 3)));
+        assert_eq!(watcher.try_recv(), Some(put("user:count", "1", seq + 4)));
+        let event = watcher.try_recv().unwrap();
+        assert_eq!((event.kind, event.value), (EventKind::Delete, None));
+        assert_eq!(event.seq, store.seq());
+        assert_eq!(watcher.try_recv(), None);
+
+        // Purging an expired pair reports an expiry.
+        store
+            .insert_with_ttl("user:2", "carol", Duration::from_millis(1))
+            .unwrap();
+        thread::sleep(Duration::from_millis(5));
+        store.purge_expired().unwrap();
+        assert_eq!(watcher.try_recv().unwrap().kind, EventKind::Put);
+        assert_eq!(watcher.try_recv().unwrap().kind, EventKind::Expire);
+
+        // A dropped watcher is no longer notified, the others still are.
+        let everything = store.watch("");
+        drop(watcher);
+        store.insert("user:3", "dave").unwrap();
+        assert_eq!(everything.try_recv().unwrap().key, b"user:3");
+    }
+
+    #[test]
+    fn test_database_watch() {
+        let db = Database::open("memory", options(4)).unwrap();
+        let watcher = db.watch("key").unwrap();
+        let mut batch = WriteBatch::new();
+        for i in 0..20 {
+            batch.put(format!("key{i}"), "value");
+        }
+        batch.put("other", "value");
+        db.write_batch(batch).unwrap();
+        db.transaction(|tx| {
+            tx.delete("key3")?;
+            tx.update("key4", "updated")
+        })
+        .unwrap();
+
+        let events: Vec<Event> = (0..22).map_while(|_| watcher.try_recv()).collect();
+        assert_eq!(events.len(), 22);
+        assert!(events.iter().all(|event| event.key.starts_with(b"key")));
+        let deleted: Vec<&Event> = events
+            .iter()
+            .filter(|event| event.kind == EventKind::Delete)
+            .collect();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].key, b"key3");
+        let key4: Vec<&Event> = events.iter().filter(|event| event.key == b"key4").collect();
+        assert_eq!(key4[1].value, Some(b"updated".to_vec()));
+        assert!(key4[1].seq > key4[0].seq);
+        assert_eq!(db.version("key4").unwrap(), Some(key4[1].seq));
+    }
+
+    #[test]
+    fn test_watch_from_another_thread() {
+        let db = Arc::new(Database::open("memory", options(2)).unwrap());
+        let watcher = db.watch("").unwrap();
+        let reader = thread::spawn(move || watcher.map(|event| event.key).collect::<Vec<_>>());
+        for i in 0..10 {
+            db.insert(format!("key{i}"), "value").unwrap();
+        }
+        drop(db); // Ends the iterator
+
+        let mut keys = reader.join().unwrap();
+        keys.sort();
+        assert_eq!(keys.len(), 10);
+        assert_eq!(keys[0], b"key0");
+    }
+
+    #[test]
+    fn test_unfiltered_watch_hides_reserved_keys() {
+        let db = Database::open("memory", options(4)).unwrap();
+        let all = db.watch("").unwrap();
+        let reserved = db.watch([0]).unwrap();
+        let users = db.create_keyspace("users", Default::default()).unwrap();
+        db.create_index("city", Extractor::json_path("city"))
+            .unwrap();
+        let in_users = db.watch(users.key("")).unwrap();
+        users.insert("1", r#"{"city": "Paris"}"#).unwrap();
+        db.insert("office", "Rabat").unwrap();
+
+        assert_eq!(all.try_recv().unwrap().key, b"office"); // No catalog nor keyspace key
+        assert!(all.try_recv().is_none());
+        assert_eq!(in_users.try_recv().unwrap().key, users.key("1"));
+        assert!(in_users.try_recv().is_none());
+        let keys: Vec<Vec<u8>> = std::iter::from_fn(|| reserved.try_recv())
+            .map(|event| event.key)
+            .collect();
+        assert!(keys.contains(&users.key("1")));
+        assert!(keys.len() > 1); // With the catalog changes
+    }
+}